@@ -4,6 +4,8 @@ from .output import Output, OutputDir, OutputBase  # noqa
 from .data import blob, pickled, directory, DataObject  # noqa
 from .task import Task  # noqa
 from ..common import RainException, RainWarning, TaskException, SessionException # noqa
+from ..common import SessionLimitException # noqa
+from ..common import UnknownResourceException # noqa
 from .pycode import remote, Remote  # noqa
 from .client import Client  # noqa
 from .program import Program  # noqa
@@ -1,14 +1,18 @@
 import capnp
 import json
+import os
 
 from . import rpc
-from ..common import RainException, SessionException, TaskException
+from ..common import (RainException, SessionException, SessionLimitException, TaskException,
+                      UnknownResourceException)
 from ..common.attributes import ObjectInfo, TaskInfo
 from ..common.data_instance import DataInstance
-from ..common.ids import governor_id_from_capnp, id_from_capnp, id_to_capnp
+from ..common.ids import (governor_id_from_capnp, governor_id_to_capnp,
+                          id_from_capnp, id_to_capnp)
 from .data import DataObject
 from .session import Session
 from .task import Task
+from .tls import TlsTunnel
 
 CLIENT_PROTOCOL_VERSION = 1
 FETCH_SIZE = 8 << 20  # 8MB
@@ -20,8 +24,16 @@ def check_result(sessions, result):
     elif result.which() == "error":
         task_id = id_from_capnp(result.error.task)
         message = []
+        args = ()
 
-        if task_id.session_id == -1:
+        if result.error.limitExceeded:
+            cls = SessionLimitException
+            task = None
+        elif result.error.resource:
+            cls = UnknownResourceException
+            task = None
+            args = (result.error.resource,)
+        elif task_id.session_id == -1:
             cls = SessionException
             task = None
         else:
@@ -49,7 +61,7 @@ def check_result(sessions, result):
         if result.error.debug:
             message.append("Debug:\n" + result.error.debug)
         message = "\n".join(message)
-        raise cls(message)
+        raise cls(message, *args)
     else:
         raise Exception("Invalid result: {}".format(result))
 
@@ -60,26 +72,121 @@ class Client:
     :py:class:`Session` s.
     """
 
-    def __init__(self, address, port):
-        self._rpc_client = capnp.TwoPartyClient("{}:{}".format(address, port))
+    def __init__(self, address, port, token=None,
+                 tls_ca=None, tls_cert=None, tls_key=None):
+        """
+        Args:
+            token (str): Token for a server that requires authentication
+                (default: the environment variable ``RAIN_TOKEN``).
+            tls_ca (str): Connect over TLS and verify the server certificate
+                by the CA certificates in this PEM file.
+            tls_cert (str): Certificate (PEM) presented to a server that
+                verifies clients.
+            tls_key (str): Private key (PEM) of ``tls_cert``.
+        """
+        if token is None:
+            token = os.environ.get("RAIN_TOKEN", "")
+        if tls_ca:
+            self._tls = TlsTunnel(address, port, tls_ca, tls_cert, tls_key)
+            self._rpc_client = capnp.TwoPartyClient(self._tls.socket)
+        else:
+            self._tls = None
+            self._rpc_client = capnp.TwoPartyClient(
+                "{}:{}".format(address, port))
 
         bootstrap = self._rpc_client.bootstrap().cast_as(
             rpc.server.ServerBootstrap)
-        registration = bootstrap.registerAsClient(CLIENT_PROTOCOL_VERSION)
+        registration = bootstrap.registerAsClient(CLIENT_PROTOCOL_VERSION,
+                                                  token)
         self._service = registration.wait().service
 
-    def new_session(self, name="Unnamed Session", default=False):
+    def new_session(self, name="Unnamed Session", default=False, weight=None,
+                    limits=None, grace_period=None, persistent=False):
         """
         Creates a new session.
 
-        Note the session is destroyed server-side when the client disconnects.
+        Note the session is destroyed server-side when the client disconnects,
+        unless ``grace_period`` or ``persistent`` is given.
+
+        Args:
+            weight (float): Weight of the session in the fair sharing of the
+                cluster (default 1.0), used when the server runs with
+                ``--fair-share``.
+            limits (dict): Limits of the session with optional keys ``cpus``
+                (cpus used by running tasks), ``tasks`` (number of running
+                tasks) and ``kept_bytes`` (total size of kept objects).
+            grace_period (int): Seconds for which the session survives a
+                disconnection of the client; it can be reattached by
+                :meth:`reattach_session` in the meantime.
+            persistent (bool): The session survives a disconnection of the
+                client until it is closed.
 
         Returns:
             :class:`Session`: A new session
         """
-        spec = json.dumps({"name": str(name)})
-        session_id = self._service.newSession(spec).wait().sessionId
-        return Session(self, session_id, default)
+        spec = {"name": str(name)}
+        if weight is not None:
+            spec["weight"] = float(weight)
+        if limits is not None:
+            spec["limits"] = {key: int(value) for key, value in limits.items()}
+        if grace_period is not None:
+            spec["grace_period"] = int(grace_period)
+        if persistent:
+            spec["persistent"] = True
+        spec = json.dumps(spec)
+        result = self._service.newSession(spec).wait()
+        session = Session(self, result.sessionId, default)
+        session.token = result.token or None
+        return session
+
+    def attach_session(self, session_id, default=False):
+        """
+        Attaches a session recovered by a server started with ``--recover``.
+
+        The tasks and objects submitted before the server restart are not
+        available as client-side objects, but the session can be waited for
+        and new tasks can be submitted into it.
+
+        Returns:
+            :class:`Session`: The attached session
+        """
+        max_id = self._service.attachSession(session_id).wait().maxId
+        session = Session(self, session_id, default)
+        session._id_counter = max(session._id_counter, max_id)
+        return session
+
+    def reattach_session(self, session, token=None, default=False):
+        """
+        Reattaches a session that survived the disconnection of its client
+        (see ``grace_period`` and ``persistent`` in :meth:`new_session`).
+
+        Args:
+            session: A :class:`Session` created by a disconnected client, it
+                is reattached together with its tasks and objects; or an id
+                of the session.
+            token (str): The token of the session (``Session.token``); not
+                needed when a :class:`Session` is given.
+
+        Returns:
+            :class:`Session`: The reattached session
+        """
+        if isinstance(session, Session):
+            session_id = session.session_id
+            if token is None:
+                token = session.token
+        else:
+            session_id = session
+            session = None
+        if token is None:
+            raise RainException("A token is required to reattach a session")
+        max_id = self._service.reattachSession(session_id, token).wait().maxId
+        if session is None:
+            session = Session(self, session_id, default)
+            session._id_counter = max(session._id_counter, max_id)
+        else:
+            session.client = self
+        session.token = token
+        return session
 
     def get_server_info(self):
         """
@@ -94,10 +201,29 @@ class Client:
                            "tasks": [id_from_capnp(t) for t in w.tasks],
                            "objects": [id_from_capnp(o) for o in w.objects],
                            "objects_to_delete": [id_from_capnp(o) for o in w.objectsToDelete],
-                           "resources": {"cpus": w.resources.nCpus}}
+                           "resources": dict([("cpus", w.resources.nCpus),
+                                               ("memory", w.resources.memory)] +
+                                              [(r.name, r.amount) for r in w.resources.named])}
                           for w in info.governors]
         }
 
+    def drain_governor(self, governor_id):
+        """
+        Takes a governor out of service.
+
+        The governor gets no new tasks, its running tasks are finished and the
+        objects that are still needed are copied to other governors. Then the
+        governor is unregistered and terminated. The call does not wait for
+        the draining to finish.
+
+        Args:
+            governor_id (str): Id of the governor as returned by
+                :meth:`get_server_info` (``"address:port"``)
+        """
+        req = self._service.drainGovernor_request()
+        governor_id_to_capnp(governor_id, req.governorId)
+        req.send().wait()
+
     def _submit(self, tasks, dataobjs):
         req = self._service.submit_request()
 
@@ -112,7 +238,7 @@ class Client:
         for i in range(len(dataobjs)):
             dataobjs[i]._to_capnp(req.objects[i])
 
-        req.send().wait()
+        check_result((), req.send().wait())
 
     def _fetch(self, dataobj):
         "Fetch the object data and update its state."
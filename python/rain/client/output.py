@@ -20,7 +20,8 @@ class OutputBase:
     data_type = None
 
     def __init__(self, label=None, *, size_hint=None, content_type=None,
-                 mode=None, encode=None, path=None):
+                 mode=None, encode=None, path=None, compression=None,
+                 replicas=None):
         assert self.data_type is not None
         self.label = label
         self.size_hint = size_hint
@@ -35,6 +36,13 @@ class OutputBase:
                 "for Output, they must match.")
 
         self.path = path
+        if compression not in (None, "none", "zstd"):
+            raise ValueError(
+                "Unknown compression {!r}".format(compression))
+        self.compression = compression
+        if replicas is not None and replicas < 1:
+            raise ValueError("`replicas` has to be at least 1")
+        self.replicas = replicas
 
     def _to_json(self):
         return {k: v for (k, v) in self.__dict__.items() if v is not None}
@@ -59,6 +67,10 @@ class OutputBase:
             o.label = proto.label
         if o.path is None:
             o.path = proto.path
+        if o.compression is None:
+            o.compression = proto.compression
+        if o.replicas is None:
+            o.replicas = proto.replicas
         o.content_type = merge_content_types(o.content_type, proto.content_type)
         o.encode = merge_content_types(o.encode, proto.encode)
         return o
@@ -70,6 +82,10 @@ class OutputBase:
                        content_type=self.content_type)
         if self.size_hint is not None:
             d.spec.size_hint = self.size_hint
+        if self.compression is not None:
+            d.spec.compression = self.compression
+        if self.replicas is not None:
+            d.spec.replicas = self.replicas
         return d
 
     @classmethod
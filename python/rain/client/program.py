@@ -21,7 +21,13 @@ class Program:
                  input_paths=(), output_paths=(),
                  shell=False,
                  name=None,
-                 cpus=1):
+                 cpus=1,
+                 memory=None,
+                 resources=None,
+                 retry=None,
+                 timeout=None,
+                 placement=None,
+                 priority=None):
 
         self.default_name = name
 
@@ -38,6 +44,12 @@ class Program:
         self.output_paths = tuple(Output._for_program(obj, label_as_path=True)
                                   for obj in output_paths)
         self.cpus = cpus
+        self.memory = memory
+        self.resources = resources
+        self.retry = retry
+        self.timeout = timeout
+        self.placement = placement
+        self.priority = priority
 
         if isinstance(args, str):
             args = shlex.split(args)
@@ -81,4 +93,10 @@ class Program:
                        output_paths=[obj for obj in self.output_paths],
                        shell=self.shell,
                        cpus=self.cpus,
+                       memory=self.memory,
+                       resources=self.resources,
+                       retry=self.retry,
+                       timeout=self.timeout,
+                       placement=self.placement,
+                       priority=self.priority,
                        name=name)
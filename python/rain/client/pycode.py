@@ -65,7 +65,10 @@ def remote(*,
            auto_load=None,
            auto_encode=None,
            name=None,
-           cpus=1):
+           cpus=1,
+           memory=None,
+           resources=None,
+           priority=None):
     "Decorator for :py:class:`Remote`, see the documentation there."
     def make_remote(fn):
         if not inspect.isfunction(fn):
@@ -77,7 +80,10 @@ def remote(*,
                       auto_load=auto_load,
                       auto_encode=auto_encode,
                       name=name,
-                      cpus=cpus)
+                      cpus=cpus,
+                      memory=memory,
+                      resources=resources,
+                      priority=priority)
     return make_remote
 
 
@@ -96,10 +102,16 @@ class Remote:
                  auto_load=False,
                  auto_encode=None,
                  name=None,
-                 cpus=1):
+                 cpus=1,
+                 memory=None,
+                 resources=None,
+                 priority=None):
         self.fn = fn
         code = self.fn.__code__
         self.cpus = cpus
+        self.memory = memory
+        self.resources = resources
+        self.priority = priority
 
         if name is None:
             self.default_name = fn.__name__
@@ -199,5 +211,8 @@ class Remote:
                     task_type="py/",
                     config=task_config,
                     cpus=self.cpus,
+                    memory=self.memory,
+                    resources=self.resources,
+                    priority=self.priority,
                     session=session,
                     name=task_name)
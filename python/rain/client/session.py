@@ -72,6 +72,8 @@ class Session:
         self.active = True  # True if a session is live in server
         self.client = client
         self.session_id = session_id
+        # Token for reattaching the session, see Client.reattach_session
+        self.token = None
 
         self._tasks = []  # Unsubmitted task
         self._dataobjs = []  # Unsubmitted objects
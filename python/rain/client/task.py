@@ -47,6 +47,20 @@ class Task(metaclass=TaskMeta):
         session (`Session` or `None`): Session to create the task in.
             If not specified, the current `Session` is used.
         cpus (`int`): Number of cpus.
+        memory (`int`): Amount of memory in bytes needed by the task.
+        resources (`dict`): Amounts of user-defined resources needed by the task,
+            e.g. ``{"license_matlab": 1}``.
+        retry (`int` or `dict`): Maximal number of attempts to run the task or
+            a retry policy (see `TaskSpec.retry`). Failed task is rescheduled
+            instead of failing the session until the attempts are exhausted.
+        timeout (`float`): Wall-clock limit of the task run in seconds.
+            The task fails when the limit is exceeded.
+        placement (`dict`): Placement constraints, a dictionary with optional keys
+            ``labels`` (governor labels required by the task, e.g. ``{"disk": "nvme"}``),
+            ``anti_affinity`` (tasks that must not share a governor with this task)
+            and ``preferred_governor`` (ID of a governor used whenever possible).
+        priority (`int`): Ready tasks with higher priority are started first
+            (default 0, may be negative).
 
     Attributes:
         id (`ID`): Auto-assigned task ID.
@@ -71,8 +85,14 @@ class Task(metaclass=TaskMeta):
                  session=None,
                  task_type=None,
                  cpus=1,
+                 memory=None,
+                 resources=None,
                  name=None,
-                 user_spec=None):
+                 user_spec=None,
+                 retry=None,
+                 timeout=None,
+                 placement=None,
+                 priority=None):
 
         self._spec = TaskSpec()
         self._info = None
@@ -101,6 +121,35 @@ class Task(metaclass=TaskMeta):
         if cpus is not None:
             self._spec.resources['cpus'] = cpus
 
+        if memory is not None:
+            self._spec.resources['memory'] = int(memory)
+
+        if resources is not None:
+            for name, amount in resources.items():
+                if name in ('cpus', 'memory'):
+                    raise ValueError(
+                        "Use cpus=... and memory=... to request {!r}".format(name))
+                self._spec.resources[name] = int(amount)
+
+        if retry is not None:
+            if isinstance(retry, int):
+                retry = {"max_attempts": retry}
+            self._spec.retry = retry
+
+        if timeout is not None:
+            self._spec.timeout = float(timeout)
+
+        if placement is not None:
+            placement = dict(placement)
+            if "anti_affinity" in placement:
+                placement["anti_affinity"] = [
+                    (t.id if isinstance(t, Task) else ID(*t))._to_json()
+                    for t in placement["anti_affinity"]]
+            self._spec.placement = placement
+
+        if priority is not None:
+            self._spec.priority = int(priority)
+
         def to_data_object(o):
             if isinstance(o, str):
                 return DataObject(label=o, session=session)
@@ -150,7 +150,13 @@ class Execute(Task):
                  *,
                  name=None,
                  session=None,
-                 cpus=1):
+                 cpus=1,
+                 memory=None,
+                 resources=None,
+                 retry=None,
+                 timeout=None,
+                 placement=None,
+                 priority=None):
 
         ins = []
         outs = []
@@ -207,7 +213,9 @@ class Execute(Task):
             "out_paths": [obj.path for obj in outs]}
 
         super().__init__(
-            task_inputs, task_outputs, cpus=cpus, config=config, name=name, session=session)
+            task_inputs, task_outputs, cpus=cpus, memory=memory, resources=resources, config=config,
+            name=name, session=session, retry=retry, timeout=timeout, placement=placement,
+            priority=priority)
 
     def __repr__(self):
         return "<{} {}, inputs {}, outputs {}, cmd {!r}>".format(
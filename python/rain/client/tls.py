@@ -0,0 +1,56 @@
+import selectors
+import socket
+import ssl
+import threading
+
+BUFFER_SIZE = 256 * 1024
+
+
+class TlsTunnel:
+    """
+    A TLS connection to the server forwarded to a local socket.
+
+    pycapnp reads and writes the file descriptor of its socket directly, so
+    it cannot use an SSL socket. It gets one end of a local socket pair
+    instead; a thread moves the data between the other end and the TLS
+    connection.
+    """
+
+    def __init__(self, address, port, ca, cert=None, key=None):
+        context = ssl.create_default_context(cafile=ca)
+        if cert:
+            context.load_cert_chain(cert, key)
+        raw = socket.create_connection((address, port))
+        self._tls = context.wrap_socket(raw, server_hostname=address)
+        self.socket, self._local = socket.socketpair()
+        self._thread = threading.Thread(target=self._forward, daemon=True)
+        self._thread.start()
+
+    def _forward(self):
+        selector = selectors.DefaultSelector()
+        selector.register(self._tls, selectors.EVENT_READ)
+        selector.register(self._local, selectors.EVENT_READ)
+        try:
+            while True:
+                for key, _ in selector.select():
+                    if key.fileobj is self._tls:
+                        data = self._tls.recv(BUFFER_SIZE)
+                        if not data:
+                            return
+                        self._local.sendall(data)
+                        # Decrypted data buffered by the SSL layer are not
+                        # signalled by the selector
+                        while self._tls.pending():
+                            self._local.sendall(
+                                self._tls.recv(self._tls.pending()))
+                    else:
+                        data = self._local.recv(BUFFER_SIZE)
+                        if not data:
+                            return
+                        self._tls.sendall(data)
+        except OSError:
+            pass
+        finally:
+            selector.close()
+            self._local.close()
+            self._tls.close()
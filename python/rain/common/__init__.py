@@ -1,6 +1,7 @@
 from .labeled_list import LabeledList  # noqa
 from .data_instance import DataInstance  # noqa
 from .errors import RainException, RainWarning  # noqa
-from .errors import SessionException, TaskException  # noqa
+from .errors import SessionException, SessionLimitException, TaskException  # noqa
+from .errors import UnknownResourceException  # noqa
 from .ids import ID  # noqa
 from .data_type import DataType  # noqa
\ No newline at end of file
@@ -103,6 +103,14 @@ class TaskSpec(AttributeBase):
         outputs (`list` of `ID`): Output object IDs.
         resources (`dict` with `str` keys): Resource specification.
         user (`dict` with `str` keys): Arbitrary user json-serializable attributes.
+        retry (`dict` or `None`): Retry policy with keys `max_attempts`,
+            `delay` (seconds, doubled with every retry) and `errors`
+            (list of prefixes of retryable error messages).
+        timeout (`float` or `None`): Wall-clock limit of a task run in seconds.
+        placement (`dict` or `None`): Placement constraints with keys `labels`
+            (`dict` of required governor labels), `anti_affinity` (list of task IDs)
+            and `preferred_governor` (governor ID).
+        priority (`int`): Ready tasks with higher priority are scheduled first.
     """
     _ATTRS = {
         "id": (ID._from_json, lambda x: x._to_json(), lambda: None),
@@ -116,6 +124,10 @@ class TaskSpec(AttributeBase):
         "resources": (dict, dict, lambda: {}),
         "name": (str, str, lambda: None),
         "user": (dict, dict, dict),
+        "retry": (dict, dict, lambda: None),
+        "timeout": (float, float, lambda: None),
+        "placement": (dict, dict, lambda: None),
+        "priority": (int, int, lambda: 0),
     }
 
 
@@ -151,6 +163,10 @@ class ObjectSpec(AttributeBase):
         content_type (`str`): Content type name.
         data_type (`str`): Object type, "blob" or "directory".
         user (`dict` with `str` keys): Arbitrary user json-serializable attributes.
+        compression (`str`): Compression of transfers between governors, "none"
+            or "zstd". Chosen by the content type when empty.
+        replicas (`int`): Number of governors keeping a copy of the finished
+            object. One when not given.
     """
     _ATTRS = {
         "id": (ID._from_json, lambda x: x._to_json(), lambda: None),
@@ -158,6 +174,8 @@ class ObjectSpec(AttributeBase):
         "content_type": (str, str, str),
         "data_type": (DataType, lambda x: DataType(x).value, lambda: DataType.BLOB),
         "user": (dict, dict, dict),
+        "compression": (str, str, str),
+        "replicas": (int, int, lambda: None),
     }
 
 
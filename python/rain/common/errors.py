@@ -19,6 +19,24 @@ class SessionException(RainException):
     pass
 
 
+class SessionLimitException(SessionException):
+    """
+    Session failure caused by exceeding a limit of the session
+    """
+    pass
+
+
+class UnknownResourceException(SessionException):
+    """
+    Submit failure caused by a task requesting a resource that no governor
+    provides. The name of the resource is in ``resource``.
+    """
+
+    def __init__(self, message, resource):
+        super().__init__(message)
+        self.resource = resource
+
+
 class TaskException(SessionException):
     """
     Task failure
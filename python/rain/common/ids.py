@@ -35,3 +35,9 @@ def governor_id_from_capnp(reader):
     else:
         raise Exception("Unknown address")
     return "{}:{}".format(".".join(map(str, address)), reader.port)
+
+
+def governor_id_to_capnp(governor_id, builder):
+    address, port = governor_id.rsplit(":", 1)
+    builder.port = int(port)
+    builder.address.ipv4 = bytes(int(b) for b in address.split("."))
@@ -2,7 +2,7 @@
 
 use std::fmt;
 
-use types::TaskId;
+use types::{SId, TaskId};
 use utils::convert::ToCapnp;
 
 // Create the Error, ErrorKind, ResultExt, and Result types
@@ -42,6 +42,10 @@ pub struct SessionError {
     message: String,
     debug: String,
     task_id: TaskId,
+    /// The error is caused by exceeding a limit of the session
+    limit_exceeded: bool,
+    /// Name of a requested resource that no governor provides, empty otherwise
+    resource: String,
 }
 
 impl SessionError {
@@ -50,12 +54,51 @@ impl SessionError {
             message,
             debug,
             task_id,
+            limit_exceeded: false,
+            resource: String::new(),
+        }
+    }
+
+    /// Error of the whole session caused by exceeding one of its limits.
+    pub fn limit_exceeded(message: String) -> Self {
+        SessionError {
+            message,
+            debug: String::new(),
+            task_id: TaskId::invalid(),
+            limit_exceeded: true,
+            resource: String::new(),
+        }
+    }
+
+    /// Error of a submit of a task requesting a resource that no governor provides.
+    pub fn unknown_resource(message: String, resource: String) -> Self {
+        SessionError {
+            message,
+            debug: String::new(),
+            task_id: TaskId::invalid(),
+            limit_exceeded: false,
+            resource,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Name of the resource of an `unknown_resource` error
+    pub fn resource(&self) -> Option<&str> {
+        if self.resource.is_empty() {
+            None
+        } else {
+            Some(&self.resource)
         }
     }
 
     pub fn to_capnp(&self, builder: &mut ::common_capnp::error::Builder) {
         builder.reborrow().set_message(&self.message);
         builder.reborrow().set_debug(&self.debug);
+        builder.reborrow().set_limit_exceeded(self.limit_exceeded);
+        builder.reborrow().set_resource(&self.resource);
         self.task_id
             .to_capnp(&mut builder.reborrow().get_task().unwrap());
     }
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::net::SocketAddr;
 
 use types::{ClientId, DataObjectId, GovernorId, ObjectSpec, SId, SessionId, TaskId, TaskSpec, SessionSpec, TaskInfo};
 
@@ -9,10 +10,30 @@ pub struct GovernorNewEvent {
     pub governor: GovernorId, // TODO: Resources
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum GovernorRemovedReason {
+    /// Connection to the governor was lost
+    Lost,
+    /// Governor was drained on a request and decommissioned
+    Drained,
+}
+
+impl Default for GovernorRemovedReason {
+    fn default() -> Self {
+        GovernorRemovedReason::Lost
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GovernorRemovedEvent {
     pub governor: GovernorId,
+    #[serde(default)]
+    pub reason: GovernorRemovedReason,
     pub error_msg: String,
+    /// The last monitoring event of the governor, kept for diagnosis
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub last_monitoring: Option<MonitoringEvent>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -32,6 +53,10 @@ pub struct SessionNewEvent {
     pub session: SessionId,
     pub client: ClientId,
     pub spec: SessionSpec,
+    /// Authenticated user of the client, see `Client::user`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub user: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -59,6 +84,10 @@ pub struct SessionClosedEvent {
 pub struct ClientSubmitEvent {
     pub tasks: Vec<TaskSpec>,
     pub dataobjs: Vec<ObjectSpec>,
+    /// Objects submitted with the keep flag
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub kept: Vec<DataObjectId>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -78,6 +107,15 @@ pub struct TaskFinishedEvent {
     pub info: TaskInfo,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TaskRetryEvent {
+    pub task: TaskId,
+    /// Number of the failed attempt (starting from 1)
+    pub attempt: u32,
+    /// Info of the failed attempt
+    pub info: TaskInfo,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DataObjectFinishedEvent {
     pub dataobject: DataObjectId,
@@ -102,6 +140,14 @@ pub struct ClientInvalidRequestEvent {
     pub error_msg: String,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuthFailedEvent {
+    /// Remote address of the rejected connection
+    pub address: SocketAddr,
+    /// "client" or "governor"
+    pub role: String,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DummyEvent {
     pub dummy_value: i32,
@@ -124,12 +170,15 @@ pub enum Event {
 
     TaskStarted(TaskStartedEvent),
     TaskFinished(TaskFinishedEvent),
+    TaskRetry(TaskRetryEvent),
     DataObjectFinished(DataObjectFinishedEvent),
 
     Monitoring(MonitoringEvent),
 
     ClientInvalidRequest(ClientInvalidRequestEvent),
 
+    AuthFailed(AuthFailedEvent),
+
     Dummy(DummyEvent),
 }
 
@@ -146,9 +195,11 @@ impl Event {
             &Event::ClientUnkeep(_) => "ClientUnkeep",
             &Event::TaskStarted(_) => "TaskStarted",
             &Event::TaskFinished(_) => "TaskFinished",
+            &Event::TaskRetry(_) => "TaskRetry",
             &Event::DataObjectFinished(_) => "ObjectFinished",
             &Event::Monitoring(_) => "Monitoring",
             &Event::ClientInvalidRequest(_) => "InvalidRequest",
+            &Event::AuthFailed(_) => "AuthFailed",
             &Event::Dummy(_) => "Dummy",
         }
     }
@@ -157,12 +208,17 @@ impl Event {
         match self {
             &Event::TaskFinished(ref e) => Some(e.task.get_session_id()),
             &Event::TaskStarted(ref e) => Some(e.task.get_session_id()),
+            &Event::TaskRetry(ref e) => Some(e.task.get_session_id()),
             &Event::SessionNew(ref e) => Some(e.session),
             &Event::SessionClosed(ref e) => Some(e.session),
             &Event::ClientSubmit(ref e) => {
                 // TODO: Quick hack, we expect that submit contains only tasks/obj from one session
-                e.tasks.get(0).map(|t| t.id.get_session_id())
+                e.tasks
+                    .get(0)
+                    .map(|t| t.id.get_session_id())
+                    .or_else(|| e.dataobjs.get(0).map(|o| o.id.get_session_id()))
             }
+            &Event::ClientUnkeep(ref e) => e.dataobjs.get(0).map(|o| o.get_session_id()),
             _ => None,
         }
     }
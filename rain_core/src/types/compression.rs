@@ -0,0 +1,41 @@
+use std::{default, fmt};
+
+/// Compression of data object transfers between governors
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Compression {
+    #[serde(rename = "none")]
+    None,
+    #[serde(rename = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    pub fn from_capnp(value: ::common_capnp::Compression) -> Compression {
+        match value {
+            ::common_capnp::Compression::None => Compression::None,
+            ::common_capnp::Compression::Zstd => Compression::Zstd,
+        }
+    }
+
+    pub fn to_capnp(&self) -> ::common_capnp::Compression {
+        match self {
+            &Compression::None => ::common_capnp::Compression::None,
+            &Compression::Zstd => ::common_capnp::Compression::Zstd,
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Compression::None => write!(f, "none"),
+            &Compression::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+impl default::Default for Compression {
+    fn default() -> Self {
+        Compression::None
+    }
+}
@@ -1,7 +1,15 @@
+use std::collections::HashMap;
+
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Resources {
     #[serde(default = "default_cpus")]
     pub cpus: u32,
+    /// Memory in bytes
+    #[serde(default)]
+    pub memory: u64,
+    /// User-defined counted resources (e.g. licenses)
+    #[serde(flatten)]
+    pub named: HashMap<String, u32>,
 }
 
 #[inline]
@@ -13,44 +21,172 @@ impl ::std::default::Default for Resources {
     fn default() -> Self {
         Resources {
             cpus: default_cpus(),
+            memory: 0,
+            named: HashMap::new(),
         }
     }
 }
 
 impl Resources {
+    /// Resources with all amounts set to zero
+    pub fn empty() -> Self {
+        Resources {
+            cpus: 0,
+            memory: 0,
+            named: HashMap::new(),
+        }
+    }
+
     #[inline]
     pub fn cpus(&self) -> u32 {
         self.cpus
     }
 
+    #[inline]
+    pub fn memory(&self) -> u64 {
+        self.memory
+    }
+
+    /// Amount of a user-defined resource
+    #[inline]
+    pub fn named(&self, name: &str) -> u32 {
+        self.named.get(name).cloned().unwrap_or(0)
+    }
+
     pub fn add(&mut self, resources: &Resources) {
         self.cpus += resources.cpus;
+        self.memory += resources.memory;
+        for (name, amount) in resources.named.iter() {
+            if *amount > 0 {
+                *self.named.entry(name.clone()).or_insert(0) += amount;
+            }
+        }
     }
 
     pub fn remove(&mut self, resources: &Resources) {
         assert!(self.cpus >= resources.cpus);
+        assert!(self.memory >= resources.memory);
         self.cpus -= resources.cpus;
+        self.memory -= resources.memory;
+        for (name, amount) in resources.named.iter() {
+            if *amount == 0 {
+                continue;
+            }
+            let remaining = {
+                let value = self.named.get_mut(name).unwrap();
+                assert!(*value >= *amount);
+                *value -= amount;
+                *value
+            };
+            if remaining == 0 {
+                self.named.remove(name);
+            }
+        }
     }
 
     pub fn difference(&self, resources: &Resources) -> Resources {
-        assert!(self.cpus >= resources.cpus);
-        Resources {
-            cpus: self.cpus - resources.cpus,
-        }
+        let mut result = self.clone();
+        result.remove(resources);
+        result
     }
 
     pub fn from_capnp(reader: &::common_capnp::resources::Reader) -> Self {
         Resources {
             cpus: reader.get_n_cpus(),
+            memory: reader.get_memory(),
+            named: reader
+                .get_named()
+                .unwrap()
+                .iter()
+                .filter(|r| r.get_amount() > 0)
+                .map(|r| (r.get_name().unwrap().to_string(), r.get_amount()))
+                .collect(),
         }
     }
 
     pub fn to_capnp(&self, builder: &mut ::common_capnp::resources::Builder) {
         builder.set_n_cpus(self.cpus);
+        builder.set_memory(self.memory);
+        let mut named = builder.reborrow().init_named(self.named.len() as u32);
+        for (i, (name, amount)) in self.named.iter().enumerate() {
+            let mut r = named.reborrow().get(i as u32);
+            r.set_name(name);
+            r.set_amount(*amount);
+        }
     }
 
     #[inline]
     pub fn is_subset_of(&self, resources: &Resources) -> bool {
         self.cpus <= resources.cpus
+            && self.memory <= resources.memory
+            && self
+                .named
+                .iter()
+                .all(|(name, amount)| *amount <= resources.named(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resources(cpus: u32, named: &[(&str, u32)]) -> Resources {
+        Resources {
+            cpus,
+            memory: 0,
+            named: named.iter().map(|&(n, a)| (n.to_string(), a)).collect(),
+        }
+    }
+
+    #[test]
+    fn named_resources() {
+        let governor = resources(4, &[("license_matlab", 2), ("scratch_ssd", 1)]);
+        assert!(resources(1, &[("license_matlab", 2)]).is_subset_of(&governor));
+        assert!(!resources(1, &[("license_matlab", 3)]).is_subset_of(&governor));
+        assert!(!resources(1, &[("gpu", 1)]).is_subset_of(&governor));
+        assert!(resources(1, &[("gpu", 0)]).is_subset_of(&governor));
+
+        let mut active = Resources::empty();
+        active.add(&resources(1, &[("license_matlab", 1), ("gpu", 0)]));
+        active.add(&resources(2, &[("license_matlab", 1)]));
+        assert_eq!(active, resources(3, &[("license_matlab", 2)]));
+        assert!(active.is_subset_of(&governor));
+        active.remove(&resources(2, &[("license_matlab", 2)]));
+        assert_eq!(active, resources(1, &[]));
+        assert_eq!(
+            governor.difference(&resources(1, &[("scratch_ssd", 1)])),
+            resources(3, &[("license_matlab", 2)])
+        );
+    }
+
+    #[test]
+    fn memory_resources() {
+        let memory = |cpus, memory| Resources {
+            cpus,
+            memory,
+            named: Default::default(),
+        };
+        let governor = memory(4, 1000);
+        assert!(memory(4, 1000).is_subset_of(&governor));
+        assert!(!memory(1, 1001).is_subset_of(&governor));
+
+        let mut active = Resources::empty();
+        active.add(&memory(1, 600));
+        active.add(&memory(1, 300));
+        assert_eq!(active, memory(2, 900));
+        assert!(active.is_subset_of(&governor));
+        active.add(&memory(1, 200));
+        assert!(!active.is_subset_of(&governor));
+        active.remove(&memory(2, 800));
+        assert_eq!(active, memory(1, 300));
+        assert_eq!(governor.difference(&active), memory(3, 700));
+    }
+
+    #[test]
+    fn named_resources_serde() {
+        let r: Resources = ::serde_json::from_str(r#"{"cpus": 2, "scratch_ssd": 1}"#).unwrap();
+        assert_eq!(r, resources(2, &[("scratch_ssd", 1)]));
+        let r: Resources = ::serde_json::from_str(r#"{"license_matlab": 4}"#).unwrap();
+        assert_eq!(r, resources(1, &[("license_matlab", 4)]));
     }
 }
@@ -1,8 +1,9 @@
 use std::collections::HashMap;
 use std::error::Error;
+use std::time::Duration;
 
 use errors::Result;
-use types::{DataObjectId, DataType, Resources, TaskId, UserAttrs};
+use types::{Compression, DataObjectId, DataType, GovernorId, Resources, TaskId, UserAttrs};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 #[serde(deny_unknown_fields)]
@@ -43,6 +44,92 @@ pub struct TaskSpec {
     #[serde(skip_serializing_if = "HashMap::is_empty")]
     #[serde(default)]
     pub user: UserAttrs,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub retry: Option<RetryPolicy>,
+
+    /// Wall-clock limit of the task run in seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub timeout: Option<f32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub placement: Option<PlacementSpec>,
+
+    /// Ready tasks with higher priority are scheduled first (default 0)
+    #[serde(skip_serializing_if = "is_zero")]
+    #[serde(default)]
+    pub priority: i32,
+}
+
+#[inline]
+fn is_zero(value: &i32) -> bool {
+    *value == 0
+}
+
+/// Constraints on governors where the task may run.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct PlacementSpec {
+    /// Labels (and their values) the governor must have.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+
+    /// The task is not placed on a governor where any of these tasks is scheduled
+    /// (and vice versa).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub anti_affinity: Vec<TaskId>,
+
+    /// Governor used whenever it is able to run the task.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub preferred_governor: Option<GovernorId>,
+}
+
+impl PlacementSpec {
+    /// Returns true if the governor with the given labels satisfies the label constraints.
+    pub fn labels_match(&self, labels: &HashMap<String, String>) -> bool {
+        self.labels
+            .iter()
+            .all(|(key, value)| labels.get(key) == Some(value))
+    }
+}
+
+/// Automatic retry of a failed task.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RetryPolicy {
+    /// Maximal number of attempts to run the task, including the first one.
+    pub max_attempts: u32,
+
+    /// Delay (in seconds) before the first retry, doubled for every next retry.
+    #[serde(default)]
+    pub delay: f32,
+
+    /// When non-empty, only failures with an error message starting with
+    /// one of the strings are retried.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub errors: Vec<String>,
+}
+
+impl RetryPolicy {
+    /// Returns true if a task that failed `failed_attempts` times (including the last failure)
+    /// with the given error should be run again.
+    pub fn should_retry(&self, failed_attempts: u32, error: &str) -> bool {
+        failed_attempts < self.max_attempts
+            && (self.errors.is_empty() || self.errors.iter().any(|e| error.starts_with(e.as_str())))
+    }
+
+    /// Delay before the given retry (numbered from 1).
+    pub fn retry_delay(&self, retry: u32) -> Duration {
+        let secs = self.delay.max(0.0) * 2f32.powi(retry as i32 - 1);
+        Duration::from_millis((secs * 1000.0) as u64)
+    }
 }
 
 impl TaskSpec {
@@ -76,6 +163,32 @@ pub struct ObjectSpec {
     #[serde(skip_serializing_if = "HashMap::is_empty")]
     #[serde(default)]
     pub user: UserAttrs,
+
+    /// Compression of transfers between governors; chosen by the content type if not given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub compression: Option<Compression>,
+
+    /// Number of governors that keep a copy of the finished object
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub replicas: Option<u32>,
+}
+
+impl ObjectSpec {
+    /// The compression of transfers of the object: the one given in the spec,
+    /// otherwise zstd for textual content types.
+    pub fn transfer_compression(&self) -> Compression {
+        if let Some(compression) = self.compression {
+            return compression;
+        }
+        let ct = &self.content_type;
+        if ct == "text" || ct == "json" || ct.starts_with("text-") || ct.starts_with("mime/text/") {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
 }
 
 
@@ -87,4 +200,159 @@ pub struct SessionSpec {
     #[serde(skip_serializing_if = "HashMap::is_empty")]
     #[serde(default)]
     pub user: UserAttrs,
+
+    /// Weight of the session in the fair sharing of the cluster
+    #[serde(skip_serializing_if = "is_default_weight")]
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub limits: Option<SessionLimits>,
+
+    /// Seconds for which the session survives a disconnection of its client
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub grace_period: Option<u64>,
+
+    /// The session survives a disconnection of its client until it is closed
+    #[serde(skip_serializing_if = "is_false")]
+    #[serde(default)]
+    pub persistent: bool,
+}
+
+impl SessionSpec {
+    /// Returns true if the session may outlive its client
+    pub fn survives_client(&self) -> bool {
+        self.persistent || self.grace_period.is_some()
+    }
+}
+
+impl Default for SessionSpec {
+    fn default() -> Self {
+        SessionSpec {
+            name: String::new(),
+            user: Default::default(),
+            weight: default_weight(),
+            limits: None,
+            grace_period: None,
+            persistent: false,
+        }
+    }
+}
+
+/// Limits of the resources used by a session.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SessionLimits {
+    /// Maximal number of cpus used by running tasks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub cpus: Option<u32>,
+
+    /// Maximal number of running tasks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub tasks: Option<u32>,
+
+    /// Maximal total size of kept objects in bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub kept_bytes: Option<u64>,
+}
+
+impl SessionLimits {
+    /// Returns true if one more task with the given cpus fits into the limits
+    /// when `cpus` cpus and `tasks` tasks are already running.
+    pub fn allows_task(&self, task_cpus: u32, cpus: u32, tasks: u32) -> bool {
+        self.cpus
+            .map(|limit| cpus + task_cpus <= limit)
+            .unwrap_or(true)
+            && self.tasks.map(|limit| tasks < limit).unwrap_or(true)
+    }
+}
+
+#[inline]
+fn default_weight() -> f32 {
+    1.0
+}
+
+fn is_default_weight(value: &f32) -> bool {
+    *value == default_weight()
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::SId;
+
+    #[test]
+    fn retry_policy() {
+        let policy: RetryPolicy = ::serde_json::from_str(
+            r#"{"max_attempts": 3, "delay": 0.5, "errors": ["Task timed out"]}"#,
+        )
+        .unwrap();
+        assert!(policy.should_retry(1, "Task timed out after 10 s"));
+        assert!(policy.should_retry(2, "Task timed out"));
+        assert!(!policy.should_retry(3, "Task timed out"));
+        assert!(!policy.should_retry(1, "Invalid argument"));
+        assert!(!policy.should_retry(1, "Program exit with exit code 1\nTask timed out"));
+        assert_eq!(policy.retry_delay(1), Duration::from_millis(500));
+        assert_eq!(policy.retry_delay(3), Duration::from_millis(2000));
+    }
+
+    #[test]
+    fn session_spec_default() {
+        let spec: SessionSpec = ::serde_json::from_str(r#"{"name": ""}"#).unwrap();
+        assert_eq!(
+            ::serde_json::to_value(&spec).unwrap(),
+            ::serde_json::to_value(&SessionSpec::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn session_limits() {
+        let limits: SessionLimits = ::serde_json::from_str(r#"{"cpus": 4, "tasks": 2}"#).unwrap();
+        assert_eq!(limits.kept_bytes, None);
+        assert!(limits.allows_task(2, 2, 1));
+        assert!(!limits.allows_task(3, 2, 1));
+        assert!(!limits.allows_task(1, 0, 2));
+        assert!(SessionLimits::default().allows_task(100, 100, 100));
+    }
+
+    #[test]
+    fn placement_spec() {
+        let placement: PlacementSpec = ::serde_json::from_str(
+            r#"{"labels": {"disk": "nvme"}, "anti_affinity": [[1, 2]],
+                "preferred_governor": "10.0.0.1:1234"}"#,
+        )
+        .unwrap();
+        assert_eq!(placement.anti_affinity, vec![TaskId::new(1, 2)]);
+        assert_eq!(
+            placement.preferred_governor,
+            Some("10.0.0.1:1234".parse().unwrap())
+        );
+        let mut labels = HashMap::new();
+        labels.insert("host".to_string(), "big1".to_string());
+        assert!(!placement.labels_match(&labels));
+        labels.insert("disk".to_string(), "nvme".to_string());
+        assert!(placement.labels_match(&labels));
+    }
+
+    #[test]
+    fn transfer_compression() {
+        let mut spec: ObjectSpec = ::serde_json::from_str(
+            r#"{"id": [1, 2], "content_type": "mime/text/csv", "data_type": "blob"}"#,
+        )
+        .unwrap();
+        assert_eq!(spec.transfer_compression(), Compression::Zstd);
+        spec.content_type = "pickle".into();
+        assert_eq!(spec.transfer_compression(), Compression::None);
+        spec.compression = Some(Compression::Zstd);
+        assert_eq!(spec.transfer_compression(), Compression::Zstd);
+    }
 }
@@ -1,7 +1,9 @@
 pub(crate) mod connection;
 pub(crate) mod monitor;
 pub(crate) mod rpc;
+pub(crate) mod tls;
 
 pub use self::connection::{create_protocol_stream, Connection, SendType, Sender};
 pub use self::monitor::Monitor;
 pub use self::rpc::new_rpc_system;
+pub use self::tls::{accept_stream, connect_stream, RpcStream, TlsConfig, GOVERNOR_TLS_NAME};
@@ -0,0 +1,242 @@
+use futures::{future, Future};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+use tokio_core::net::TcpStream;
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_rustls::rustls::internal::pemfile;
+use tokio_rustls::rustls::{AllowAnyAuthenticatedClient, Certificate, ClientConfig, NoClientAuth,
+                           PrivateKey, RootCertStore, ServerConfig};
+use tokio_rustls::webpki::DNSNameRef;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use error_chain::bail;
+
+use rain_core::errors::Result;
+
+/// Name that the certificates of governors have to be issued for.
+/// Governors are connected by their IP addresses, so the name is fixed and
+/// a certificate of one governor is accepted for any other governor.
+pub const GOVERNOR_TLS_NAME: &str = "rain-governor";
+
+/// A connection carrying RPC, plain TCP or TLS
+pub trait RpcStream: AsyncRead + AsyncWrite {}
+
+impl<T: AsyncRead + AsyncWrite> RpcStream for T {}
+
+pub type RpcStreamFuture = Box<Future<Item = Box<RpcStream>, Error = io::Error>>;
+
+/// TLS configuration of a server, governor or client.
+#[derive(Clone)]
+pub struct TlsConfig {
+    /// Present when the node has its own certificate (needed for accepting connections)
+    server_config: Option<Arc<ServerConfig>>,
+    client_config: Arc<ClientConfig>,
+}
+
+impl TlsConfig {
+    /// Peers are verified by the CA certificates in `ca`. `cert` is a pair of
+    /// PEM files with the certificate chain and the private key of this node;
+    /// it is presented to peers that verify clients. With `verify_clients`,
+    /// accepted connections have to present a certificate signed by the CA.
+    pub fn new(ca: &Path, cert: Option<(&Path, &Path)>, verify_clients: bool) -> Result<Self> {
+        let roots = read_roots(ca)?;
+        let mut client_config = ClientConfig::new();
+        client_config.root_store = roots.clone();
+
+        let server_config = match cert {
+            Some((cert_path, key_path)) => {
+                let certs = read_certs(cert_path)?;
+                let key = read_key(key_path)?;
+                client_config.set_single_client_cert(certs.clone(), key.clone());
+                let verifier = if verify_clients {
+                    AllowAnyAuthenticatedClient::new(roots)
+                } else {
+                    NoClientAuth::new()
+                };
+                let mut config = ServerConfig::new(verifier);
+                if let Err(e) = config.set_single_cert(certs, key) {
+                    bail!("Invalid certificate {}: {:?}", cert_path.display(), e);
+                }
+                Some(Arc::new(config))
+            }
+            None => {
+                if verify_clients {
+                    bail!("Verification of clients needs a certificate");
+                }
+                None
+            }
+        };
+
+        Ok(TlsConfig {
+            server_config,
+            client_config: Arc::new(client_config),
+        })
+    }
+
+    pub fn accept(&self, stream: TcpStream) -> RpcStreamFuture {
+        let config = self
+            .server_config
+            .clone()
+            .expect("TLS certificate is not configured");
+        Box::new(
+            TlsAcceptor::from(config)
+                .accept(stream)
+                .map(|s| Box::new(s) as Box<RpcStream>),
+        )
+    }
+
+    /// Connect to a peer whose certificate is issued for `name`.
+    pub fn connect(&self, name: &str, stream: TcpStream) -> RpcStreamFuture {
+        let dns_name = match DNSNameRef::try_from_ascii_str(name) {
+            Ok(dns_name) => dns_name,
+            Err(_) => {
+                return Box::new(future::err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Invalid TLS name '{}' (IP addresses are not supported)",
+                        name
+                    ),
+                )))
+            }
+        };
+        Box::new(
+            TlsConnector::from(self.client_config.clone())
+                .connect(dns_name, stream)
+                .map(|s| Box::new(s) as Box<RpcStream>),
+        )
+    }
+}
+
+/// Accept a connection over TLS when it is configured, plain TCP otherwise.
+pub fn accept_stream(tls: Option<&TlsConfig>, stream: TcpStream) -> RpcStreamFuture {
+    match tls {
+        Some(tls) => tls.accept(stream),
+        None => Box::new(future::ok(Box::new(stream) as Box<RpcStream>)),
+    }
+}
+
+/// Connect over TLS when it is configured, plain TCP otherwise.
+pub fn connect_stream(tls: Option<&TlsConfig>, name: &str, stream: TcpStream) -> RpcStreamFuture {
+    match tls {
+        Some(tls) => tls.connect(name, stream),
+        None => Box::new(future::ok(Box::new(stream) as Box<RpcStream>)),
+    }
+}
+
+fn read_roots(path: &Path) -> Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    let mut reader = BufReader::new(File::open(path)?);
+    match roots.add_pem_file(&mut reader) {
+        Ok((valid, _)) if valid > 0 => Ok(roots),
+        _ => bail!("No valid CA certificates found in {}", path.display()),
+    }
+}
+
+fn read_certs(path: &Path) -> Result<Vec<Certificate>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    match pemfile::certs(&mut reader) {
+        Ok(certs) => {
+            if certs.is_empty() {
+                bail!("No certificates found in {}", path.display())
+            } else {
+                Ok(certs)
+            }
+        }
+        Err(_) => bail!("Invalid certificate file {}", path.display()),
+    }
+}
+
+/// Read a PKCS8 or RSA private key
+fn read_key(path: &Path) -> Result<PrivateKey> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if let Ok(mut keys) = pemfile::pkcs8_private_keys(&mut reader) {
+        if !keys.is_empty() {
+            return Ok(keys.remove(0));
+        }
+    }
+    let mut reader = BufReader::new(File::open(path)?);
+    if let Ok(mut keys) = pemfile::rsa_private_keys(&mut reader) {
+        if !keys.is_empty() {
+            return Ok(keys.remove(0));
+        }
+    }
+    bail!("No private key found in {}", path.display())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::Stream;
+    use std::path::PathBuf;
+    use tokio_core::net::TcpListener;
+    use tokio_core::reactor::Core;
+
+    /// The test CA and a certificate signed by it, issued for "localhost" and
+    /// `GOVERNOR_TLS_NAME` (as shown in the TLS section of the install guide)
+    fn test_file(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/common/testdata")
+            .join(name)
+    }
+
+    fn node_config(verify_clients: bool) -> TlsConfig {
+        let (cert, key) = (test_file("node.pem"), test_file("node.key"));
+        TlsConfig::new(&test_file("ca.pem"), Some((&cert, &key)), verify_clients).unwrap()
+    }
+
+    fn client_config() -> TlsConfig {
+        TlsConfig::new(&test_file("ca.pem"), None, false).unwrap()
+    }
+
+    /// Connect `client` to `server` as to `name` and send a message,
+    /// returns the message received by the server.
+    fn send(server: &TlsConfig, client: &TlsConfig, name: &str) -> io::Result<Vec<u8>> {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let listener = TcpListener::bind(&"127.0.0.1:0".parse().unwrap(), &handle).unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = server.clone();
+        let received = listener
+            .incoming()
+            .into_future()
+            .map_err(|(e, _)| e)
+            .and_then(move |(connection, _)| server.accept(connection.unwrap().0))
+            .and_then(|stream| ::tokio_io::io::read_exact(stream, vec![0; 4]))
+            .map(|(_, message)| message);
+        let client = client.clone();
+        let name = name.to_string();
+        let sent = TcpStream::connect(&address, &handle)
+            .and_then(move |stream| client.connect(&name, stream))
+            .and_then(|stream| ::tokio_io::io::write_all(stream, b"ping"))
+            .and_then(|(stream, _)| ::tokio_io::io::flush(stream));
+        // The client stream is kept open until the server reads the message
+        core.run(received.join(sent)).map(|(message, _)| message)
+    }
+
+    #[test]
+    fn accept_and_connect() {
+        let server = node_config(false);
+        assert_eq!(
+            send(&server, &client_config(), "localhost").unwrap(),
+            b"ping"
+        );
+        assert_eq!(
+            send(&server, &node_config(false), GOVERNOR_TLS_NAME).unwrap(),
+            b"ping"
+        );
+        assert!(send(&server, &client_config(), "otherhost").is_err());
+        assert!(send(&server, &client_config(), "127.0.0.1").is_err());
+    }
+
+    #[test]
+    fn verify_clients() {
+        let server = node_config(true);
+        assert!(send(&server, &client_config(), "localhost").is_err());
+        assert_eq!(
+            send(&server, &node_config(false), "localhost").unwrap(),
+            b"ping"
+        );
+    }
+}
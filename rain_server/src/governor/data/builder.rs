@@ -1,6 +1,7 @@
 use rain_core::{errors::*, types::*};
 use std::fs::File;
 use std::io::Write;
+use std::os::unix::fs::FileExt;
 
 use super::super::fs::workdir::WorkDir;
 use super::data::{Data, Storage};
@@ -14,6 +15,8 @@ enum BuilderStorage {
 pub struct DataBuilder {
     storage: BuilderStorage,
     data_type: DataType,
+    /// Decompresses the written data when they come compressed
+    decoder: Option<::zstd::stream::write::Decoder<Vec<u8>>>,
 }
 
 impl DataBuilder {
@@ -32,7 +35,19 @@ impl DataBuilder {
         } else {
             file_storage(workdir)
         };
-        DataBuilder { data_type, storage }
+        DataBuilder {
+            data_type,
+            storage,
+            decoder: None,
+        }
+    }
+
+    /// Set the compression of the data written by `write`.
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.decoder = match compression {
+            Compression::None => None,
+            Compression::Zstd => Some(::zstd::stream::write::Decoder::new(Vec::new()).unwrap()),
+        };
     }
 
     // TODO: Get rid of this method
@@ -49,13 +64,44 @@ impl DataBuilder {
     }
 
     pub fn write(&mut self, data: &[u8]) {
+        match self.decoder {
+            Some(ref mut decoder) => {
+                decoder.write_all(data).unwrap();
+                decoder.flush().unwrap();
+                let decompressed = ::std::mem::replace(decoder.get_mut(), Vec::new());
+                write_storage(&mut self.storage, &decompressed);
+            }
+            None => write_storage(&mut self.storage, data),
+        }
+    }
+
+    /// Write uncompressed data at the given offset, for parts of data that come
+    /// out of order.
+    pub fn write_at(&mut self, offset: usize, data: &[u8]) {
+        assert!(self.decoder.is_none());
         match self.storage {
-            BuilderStorage::Memory(ref mut buffer) => buffer.extend_from_slice(data),
-            BuilderStorage::File((ref mut file, _)) => file.write_all(data).unwrap(),
+            BuilderStorage::Memory(ref mut buffer) => {
+                let end = offset + data.len();
+                if buffer.len() < end {
+                    buffer.resize(end, 0);
+                }
+                buffer[offset..end].copy_from_slice(data);
+            }
+            BuilderStorage::File((ref file, _)) => {
+                let mut written = 0;
+                while written < data.len() {
+                    let n = file
+                        .write_at(&data[written..], (offset + written) as u64)
+                        .unwrap();
+                    assert!(n > 0);
+                    written += n;
+                }
+            }
         }
     }
 
     pub fn build(&mut self, workdir: &WorkDir) -> Data {
+        self.decoder = None;
         match self.storage {
             BuilderStorage::Memory(ref mut buffer) => Data::new(
                 Storage::Memory(::std::mem::replace(buffer, Vec::new())),
@@ -84,3 +130,10 @@ impl DataBuilder {
         }
     }
 }
+
+fn write_storage(storage: &mut BuilderStorage, data: &[u8]) {
+    match storage {
+        &mut BuilderStorage::Memory(ref mut buffer) => buffer.extend_from_slice(data),
+        &mut BuilderStorage::File((ref mut file, _)) => file.write_all(data).unwrap(),
+    }
+}
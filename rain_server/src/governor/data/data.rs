@@ -1,3 +1,4 @@
+use error_chain::bail;
 use rain_core::{comm::*, errors::*, types::*};
 use std::path::{Path, PathBuf};
 
@@ -200,6 +201,18 @@ impl Data {
         }
     }
 
+    /// Write data held in memory to a new path in the working directory
+    /// and return the same data backed by the path
+    pub fn spill_to_path(&self, path: PathBuf) -> Result<Data> {
+        match self.storage {
+            Storage::Memory(ref data) => self.memory_to_fs(data, &path)?,
+            Storage::Path(_) => bail!("Data is not stored in memory"),
+        }
+        // Walks a single file as well
+        set_readonly_dir(&path, true);
+        Ok(Data::new_from_path(path, self.size(), self.data_type))
+    }
+
     pub fn write_to_path(&self, path: &Path) -> Result<()> {
         match self.storage {
             Storage::Memory(ref data) => self.memory_to_fs(data, path),
@@ -1,17 +1,27 @@
-use rain_core::errors::*;
+use rain_core::{errors::*, types::*};
 use std::fs::File;
 use std::sync::Arc;
 
+use super::super::fs::tempfile::TempFileName;
 use super::super::State;
 use super::{Data, Storage};
 
+/// Objects smaller than this are never compressed for transport
+const MIN_COMPRESSED_SIZE: usize = 64 * 1024;
+
+/// Compression level of zstd
+const ZSTD_LEVEL: i32 = 3;
+
 pub enum TransportView {
     Empty([u8; 0]),
     Memory(Arc<Data>),
     Mmap(::memmap::Mmap),
+    /// A zstd stream of the data (of the tar archive for directories)
+    Zstd(::memmap::Mmap),
 }
 
 impl TransportView {
+    /// Create an uncompressed view of the data.
     pub fn from(state: &State, data: &Arc<Data>) -> Result<Self> {
         Ok(match data.storage() {
             &Storage::Memory(_) => TransportView::Memory(data.clone()),
@@ -37,6 +47,49 @@ impl TransportView {
         })
     }
 
+    /// Should the data be compressed when `compression` is requested?
+    /// Small data are never compressed.
+    pub fn should_compress(data: &Data, compression: Compression) -> bool {
+        compression == Compression::Zstd && data.size() >= MIN_COMPRESSED_SIZE
+    }
+
+    /// Create a compressed view of the data in `temp_file`, `None` when the compression
+    /// does not save any space. The compression blocks, so call it outside of the reactor.
+    pub fn compressed(data: &Data, temp_file: TempFileName) -> Result<Option<Self>> {
+        {
+            let file = temp_file.create()?;
+            match data.storage() {
+                &Storage::Memory(ref mem) => {
+                    ::zstd::stream::copy_encode(&mem[..], file, ZSTD_LEVEL)?;
+                }
+                &Storage::Path(ref p) if data.is_blob() => {
+                    ::zstd::stream::copy_encode(File::open(&p.path)?, file, ZSTD_LEVEL)?;
+                }
+                &Storage::Path(ref p) => {
+                    assert!(data.is_directory());
+                    let encoder = ::zstd::stream::Encoder::new(file, ZSTD_LEVEL)?;
+                    let mut tar_builder = ::tar::Builder::new(encoder);
+                    tar_builder.mode(::tar::HeaderMode::Deterministic);
+                    tar_builder.append_dir_all(".", &p.path)?;
+                    tar_builder.into_inner()?.finish()?;
+                }
+            }
+        }
+        let view = TransportView::Zstd(unsafe { ::memmap::Mmap::map(&temp_file.open()?) }?);
+        if view.get_slice().len() < data.size() {
+            Ok(Some(view))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn compression(&self) -> Compression {
+        match self {
+            &TransportView::Zstd(_) => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
     pub fn get_slice(&self) -> &[u8] {
         match self {
             &TransportView::Memory(ref data) => {
@@ -47,7 +100,58 @@ impl TransportView {
                 }
             }
             &TransportView::Mmap(ref m) => &m[..],
+            &TransportView::Zstd(ref m) => &m[..],
             &TransportView::Empty(ref e) => e,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::DataBuilder;
+    use super::*;
+    use governor::fs::workdir::WorkDir;
+    use tempdir::TempDir;
+
+    #[test]
+    fn compressed_round_trip() {
+        let dir = TempDir::new("rain-test").unwrap();
+        let workdir = WorkDir::new(dir.path().to_path_buf());
+        let bytes: Vec<u8> = (0..200 * 1024).map(|i| (i % 7) as u8).collect();
+        let data = Data::new(Storage::Memory(bytes.clone()), DataType::Blob);
+        assert!(TransportView::should_compress(&data, Compression::Zstd));
+
+        let view = TransportView::compressed(&data, workdir.make_temp_file())
+            .unwrap()
+            .unwrap();
+        assert_eq!(view.compression(), Compression::Zstd);
+        assert!(view.get_slice().len() < bytes.len());
+
+        let mut builder = DataBuilder::new(&workdir, DataType::Blob, Some(bytes.len()));
+        builder.set_compression(view.compression());
+        for chunk in view.get_slice().chunks(4096) {
+            builder.write(chunk);
+        }
+        match builder.build(&workdir).storage() {
+            &Storage::Memory(ref mem) => assert!(mem[..] == bytes[..]),
+            _ => panic!("Small data are expected in memory"),
+        }
+    }
+
+    #[test]
+    fn incompressible_data() {
+        let dir = TempDir::new("rain-test").unwrap();
+        let workdir = WorkDir::new(dir.path().to_path_buf());
+        let mut x: u32 = 1;
+        let bytes: Vec<u8> = (0..100 * 1024)
+            .map(|_| {
+                x = x.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (x >> 16) as u8
+            })
+            .collect();
+        let data = Data::new(Storage::Memory(bytes), DataType::Blob);
+        assert!(TransportView::compressed(&data, workdir.make_temp_file())
+            .unwrap()
+            .is_none());
+    }
+}
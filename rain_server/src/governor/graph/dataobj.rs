@@ -6,7 +6,7 @@ use std::sync::Arc;
 use error_chain::bail;
 
 use super::{Graph, TaskRef};
-use governor::data::Data;
+use governor::data::{Data, Storage};
 use governor::graph::ExecutorRef;
 use governor::WorkDir;
 use wrapped::WrappedRcRefCell;
@@ -97,6 +97,17 @@ impl DataObject {
         }
     }
 
+    /// Size of the finished data stored in the working directory, zero for data in memory
+    pub fn disk_size(&self) -> usize {
+        match self.state {
+            DataObjectState::Finished(ref data) => match *data.storage() {
+                Storage::Path(ref data) => data.size,
+                Storage::Memory(_) => 0,
+            },
+            _ => 0,
+        }
+    }
+
     pub fn remote(&self) -> Option<GovernorId> {
         match self.state {
             DataObjectState::Remote(ref addr) | DataObjectState::Pulling((ref addr, _)) => {
@@ -1,5 +1,6 @@
 use capnp::capability::Promise;
 use capnp_rpc::pry;
+use futures::Future;
 use rain_core::{types::*, utils::*};
 
 use governor::StateRef;
@@ -27,39 +28,58 @@ impl governor_bootstrap::Server for GovernorBootstrapImpl {
         let id = DataObjectId::from_capnp(&pry!(params.get_id()));
         let offset = params.get_offset() as usize;
         let size = params.get_size() as usize;
-        let mut state = self.state.get_mut();
+        let include_info = params.get_include_info();
+        let transport_view = self
+            .state
+            .get_mut()
+            .get_transport_view(id, params.get_compress());
+        let state = self.state.clone();
 
-        let transport_view = state.get_transport_view(id);
-        let mut results = results.get();
+        Promise::from_future(
+            transport_view
+                .map_err(|e| ::capnp::Error::failed(e.to_string()))
+                .map(move |transport_view| {
+                    let mut results = results.get();
+                    let transport_view = match transport_view {
+                        Some(transport_view) => transport_view,
+                        None => {
+                            log::debug!("Governor responding 'not here' for id={}", id);
+                            results.get_status().set_not_here(());
+                            return;
+                        }
+                    };
+                    let slice = transport_view.get_slice();
 
-        if transport_view.is_none() {
-            log::debug!("Governor responding 'not here' for id={}", id);
-            results.get_status().set_not_here(());
-            return Promise::ok(());
-        }
-        let transport_view = transport_view.unwrap();
-        let slice = transport_view.get_slice();
-
-        results.reborrow().get_status().set_ok(());
+                    results.reborrow().get_status().set_ok(());
 
-        if offset < slice.len() {
-            let end = if offset + size < slice.len() {
-                offset + size
-            } else {
-                slice.len()
-            };
-            log::debug!("Sending range [{}..{}]", offset, end);
-            results.set_data(slice[offset..end].into());
-        } else {
-            log::debug!("Fetch out of range");
-        }
+                    if offset < slice.len() {
+                        let end = if offset + size < slice.len() {
+                            offset + size
+                        } else {
+                            slice.len()
+                        };
+                        log::debug!("Sending range [{}..{}]", offset, end);
+                        results.set_data(slice[offset..end].into());
+                    } else {
+                        log::debug!("Fetch out of range");
+                    }
 
-        if params.get_include_info() {
-            let obj_ref = state.graph.objects.get(&id).unwrap();
-            let obj = obj_ref.get();
-            results.set_transport_size(slice.len() as u64);
-            results.set_info(&::serde_json::to_string(&obj.info).unwrap());
-        }
-        Promise::ok(())
+                    if include_info {
+                        let state = state.get();
+                        // The object could be removed while compressing its data
+                        let obj_ref = match state.graph.objects.get(&id) {
+                            Some(obj_ref) => obj_ref,
+                            None => {
+                                results.get_status().set_not_here(());
+                                return;
+                            }
+                        };
+                        let obj = obj_ref.get();
+                        results.set_transport_size(slice.len() as u64);
+                        results.set_compression(transport_view.compression().to_capnp());
+                        results.set_info(&::serde_json::to_string(&obj.info).unwrap());
+                    }
+                }),
+        )
     }
 }
@@ -33,9 +33,10 @@ impl governor_control::Server for GovernorControlImpl {
         _params: governor_control::GetGovernorResourcesParams,
         mut results: governor_control::GetGovernorResourcesResults,
     ) -> Promise<(), ::capnp::Error> {
-        results
+        self.state
             .get()
-            .set_n_cpus(self.state.get().get_resources().cpus);
+            .get_resources()
+            .to_capnp(&mut results.get());
         Promise::ok(())
     }
 
@@ -162,7 +163,8 @@ impl governor_control::Server for GovernorControlImpl {
                     .map_err(move |e| {
                         match e {
                             Error(ErrorKind::Ignored, _) => { /* do nothing, it is safe */ }
-                            e => panic!("Fetch dataobject failed {:?}", e),
+                            // The server unassigns the object when its source is lost
+                            e => log::error!("Fetch dataobject failed {:?}", e),
                         }
                     })
                     .select(receiver.then(move |_| {
@@ -176,6 +178,24 @@ impl governor_control::Server for GovernorControlImpl {
         Promise::ok(())
     }
 
+    fn decommission(
+        &mut self,
+        _params: governor_control::DecommissionParams,
+        _results: governor_control::DecommissionResults,
+    ) -> Promise<(), ::capnp::Error> {
+        log::info!("Governor decommissioned by the server");
+        self.state.get_mut().decommission();
+        Promise::ok(())
+    }
+
+    fn heartbeat(
+        &mut self,
+        _params: governor_control::HeartbeatParams,
+        _results: governor_control::HeartbeatResults,
+    ) -> Promise<(), ::capnp::Error> {
+        Promise::ok(())
+    }
+
     fn get_info(
         &mut self,
         _params: governor_control::GetInfoParams,
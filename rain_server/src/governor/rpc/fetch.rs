@@ -1,12 +1,32 @@
 use futures::future::Either;
 use futures::IntoFuture;
 use futures::{future, Future};
+use rain_core::governor_capnp::governor_bootstrap;
 use rain_core::{errors::*, types::*, utils::*};
+use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 use governor::data::{Data, DataBuilder};
 use governor::graph::DataObjectRef;
-use governor::StateRef;
+use governor::{State, StateRef};
+
+/// Maximal number of redirections (and retries after a failed source) of one fetch
+const MAX_REDIRECTS: i32 = 32;
+
+/// Delay in seconds before asking the server for a new location after a failed source
+const FETCH_RETRY_DELAY: u64 = 1;
+
+/// Size of one fetched chunk
+const FETCH_SIZE: usize = 4 << 20; // 4 MB
+
+/// Objects with at least this many bytes left after the first chunk are fetched
+/// from all governors holding them in parallel
+const PARALLEL_FETCH_MIN_SIZE: usize = 4 * FETCH_SIZE;
+
+type FetchLoop = future::Loop<Data, FetchContext>;
+type FetchStep = Box<Future<Item = FetchLoop, Error = Error>>;
 
 pub struct FetchContext {
     pub state_ref: StateRef,
@@ -21,8 +41,11 @@ pub struct FetchContext {
 pub fn fetch(context: FetchContext) -> Box<Future<Item = Data, Error = Error>> {
     Box::new(future::lazy(move || {
         future::loop_fn(context, |mut context| {
-            let id = context.dataobj_ref.get().spec.id;
-            let fetch_size = 4 << 20; // 4 MB
+            let (id, compress) = {
+                let dataobj = context.dataobj_ref.get();
+                (dataobj.spec.id, compress_transfer(&dataobj.spec))
+            };
+            let fetch_size = FETCH_SIZE;
             let state_ref = context.state_ref.clone();
             let state = state_ref.get();
             let send = match context.remote {
@@ -34,6 +57,7 @@ pub fn fetch(context: FetchContext) -> Box<Future<Item = Data, Error = Error>> {
                         request.set_offset(context.offset as u64);
                         request.set_size(fetch_size as u64);
                         request.set_include_info(context.builder.is_none());
+                        request.set_compress(compress);
                         id.to_capnp(&mut request.get_id().unwrap());
                     }
                     req.send()
@@ -58,23 +82,49 @@ pub fn fetch(context: FetchContext) -> Box<Future<Item = Data, Error = Error>> {
                 }
             };
             send.promise
-                .map_err(|e| Error::with_chain(e, "Fetch failed"))
-                .and_then(move |r| {
+                .then(move |r| -> Either<future::FutureResult<FetchLoop, Error>, FetchStep> {
+                    let r = match r {
+                        Ok(r) => r,
+                        Err(e) => {
+                            if context.remote.is_none() || context.n_redirects >= MAX_REDIRECTS {
+                                return Either::A(
+                                    Err(Error::with_chain(e, "Fetch failed")).into_future(),
+                                );
+                            }
+                            log::warn!("Fetching id={} from remote governor failed: {}", id, e);
+                            return Either::B(retry_from_server(context));
+                        }
+                    };
                     let response = r.get().unwrap();
                     let state_ref = context.state_ref.clone();
                     let mut state = state_ref.get_mut();
                     match response.get_status().which().unwrap() {
                         ::rain_core::common_capnp::fetch_result::status::Ok(()) => {
+                            let mut parallel = false;
                             if context.builder.is_none() {
                                 let mut dataobj = context.dataobj_ref.get_mut();
                                 dataobj.info =
                                     ::serde_json::from_str(response.get_info().unwrap()).unwrap();
                                 context.size = response.get_transport_size() as usize;
-                                context.builder = Some(DataBuilder::new(
+                                let compression =
+                                    Compression::from_capnp(response.get_compression().unwrap());
+                                // The transport size of compressed data is not the final size
+                                let expected_size = if compression == Compression::None {
+                                    Some(context.size)
+                                } else {
+                                    dataobj.info.size
+                                };
+                                let mut builder = DataBuilder::new(
                                     state.work_dir(),
                                     dataobj.spec.data_type,
-                                    Some(context.size),
-                                ))
+                                    expected_size,
+                                );
+                                builder.set_compression(compression);
+                                context.builder = Some(builder);
+                                // Chunks of the same uncompressed blob are equal on all governors
+                                parallel = context.remote.is_some()
+                                    && compression == Compression::None
+                                    && dataobj.spec.data_type == DataType::Blob;
                             };
                             let result = {
                                 let builder = context.builder.as_mut().unwrap();
@@ -87,6 +137,12 @@ pub fn fetch(context: FetchContext) -> Box<Future<Item = Data, Error = Error>> {
                                     Some(builder.build(state.work_dir()))
                                 }
                             };
+                            if result.is_none()
+                                && parallel
+                                && context.size - context.offset >= PARALLEL_FETCH_MIN_SIZE
+                            {
+                                return Either::B(fetch_parallel(context, &state));
+                            }
                             Either::A(
                                 Ok(result
                                     .map(future::Loop::Break)
@@ -104,16 +160,26 @@ pub fn fetch(context: FetchContext) -> Box<Future<Item = Data, Error = Error>> {
                             assert!(context.remote.is_none()); // The response is from the server
 
                             context.n_redirects += 1;
-                            if context.n_redirects > 32 {
-                                panic!("Too many redirections of fetch");
+                            if context.n_redirects > MAX_REDIRECTS {
+                                return Either::A(
+                                    Err(format!("Too many redirections of fetch id={}", id).into())
+                                        .into_future(),
+                                );
                             }
                             let governor_id = GovernorId::from_capnp(&w.unwrap());
-                            Either::B(state.wait_for_remote_governor(&governor_id).and_then(
+                            Either::B(Box::new(state.wait_for_remote_governor(&governor_id).and_then(
                                 move |remote_governor| {
                                     context.remote = Some(remote_governor);
                                     Ok(future::Loop::Continue(context))
                                 },
-                            ))
+                            )))
+                        }
+                        ::rain_core::common_capnp::fetch_result::status::Removed(()) => {
+                            assert!(context.remote.is_none()); // The response is from the server
+                            Either::A(
+                                Err(format!("Object id={} is not available anymore", id).into())
+                                    .into_future(),
+                            )
                         }
                         ::rain_core::common_capnp::fetch_result::status::Ignored(()) => {
                             assert!(context.remote.is_none()); // The response is from the server
@@ -132,3 +198,318 @@ pub fn fetch(context: FetchContext) -> Box<Future<Item = Data, Error = Error>> {
         })
     }))
 }
+
+/// Should the governor be asked for a compressed transfer? Compressed data can be only
+/// fetched from one governor, so replicated blobs are fetched uncompressed to allow
+/// fetching large ones from all their copies in parallel.
+fn compress_transfer(spec: &ObjectSpec) -> bool {
+    spec.data_type != DataType::Blob || !spec.replicas.map_or(false, |r| r > 1)
+}
+
+/// Ask the server for another source after a while; the failed source is probably
+/// gone and the server has to notice it first.
+fn retry_from_server(mut context: FetchContext) -> FetchStep {
+    context.remote = None;
+    context.n_redirects += 1;
+    // Another source may have a different transport form (e.g. tar)
+    context.builder = None;
+    context.offset = 0;
+    let delay = Instant::now() + Duration::from_secs(FETCH_RETRY_DELAY);
+    Box::new(
+        ::tokio_timer::Delay::new(delay)
+            .map_err(|e| e.into())
+            .map(move |()| future::Loop::Continue(context)),
+    )
+}
+
+/// Shared state of a parallel fetch of one object
+struct ParallelFetch {
+    builder: DataBuilder,
+    /// Chunks (offset, size) that are not fetched yet
+    chunks: VecDeque<(usize, usize)>,
+    /// Number of bytes that are not fetched yet
+    remaining: usize,
+}
+
+/// Fetch the rest of the object (from `context.offset`) from all governors holding it,
+/// each of them sends different chunks. Chunks of a failed governor are fetched from
+/// the others. Continues with the sequential fetch when there is only one source.
+fn fetch_parallel(mut context: FetchContext, state: &State) -> FetchStep {
+    let id = context.dataobj_ref.get().spec.id;
+    let mut req = state
+        .upstream()
+        .as_ref()
+        .unwrap()
+        .get_object_locations_request();
+    id.to_capnp(&mut req.get().get_id().unwrap());
+
+    Box::new(
+        req.send()
+            .promise
+            .map_err(|e| e.into())
+            .and_then(move |response| -> FetchStep {
+                let sources: Vec<GovernorId> = match response.get().and_then(|r| r.get_governors())
+                {
+                    Ok(list) => list.iter().map(|g| GovernorId::from_capnp(&g)).collect(),
+                    Err(e) => return Box::new(future::err(e.into())),
+                };
+                if sources.len() < 2 {
+                    return Box::new(future::ok(future::Loop::Continue(context)));
+                }
+                log::debug!(
+                    "Fetching id={} from {} governors in parallel",
+                    id,
+                    sources.len()
+                );
+
+                let mut chunks = VecDeque::new();
+                let mut offset = context.offset;
+                while offset < context.size {
+                    let size = ::std::cmp::min(FETCH_SIZE, context.size - offset);
+                    chunks.push_back((offset, size));
+                    offset += size;
+                }
+                let parallel = Rc::new(RefCell::new(ParallelFetch {
+                    builder: context.builder.take().unwrap(),
+                    chunks,
+                    remaining: context.size - context.offset,
+                }));
+
+                let connections: Vec<_> = sources
+                    .iter()
+                    .map(|governor_id| {
+                        let governor_id = *governor_id;
+                        context
+                            .state_ref
+                            .get_mut()
+                            .wait_for_remote_governor(&governor_id)
+                            .map(Some)
+                            .or_else(move |e| {
+                                log::warn!("Connecting to governor {} failed: {}", governor_id, e);
+                                Ok::<_, Error>(None)
+                            })
+                    })
+                    .collect();
+                let parallel2 = parallel.clone();
+                let rounds = future::join_all(connections).and_then(move |remotes| {
+                    let remotes = remotes.into_iter().filter_map(|r| r).collect();
+                    fetch_from_sources(remotes, id, parallel)
+                });
+
+                Box::new(rounds.and_then(move |()| -> FetchStep {
+                    let mut p = parallel2.borrow_mut();
+                    if p.remaining == 0 {
+                        let state = context.state_ref.get();
+                        let data = p.builder.build(state.work_dir());
+                        return Box::new(future::ok(future::Loop::Break(data)));
+                    }
+                    if context.n_redirects >= MAX_REDIRECTS {
+                        return Box::new(future::err(
+                            format!("Parallel fetch of id={} failed", id).into(),
+                        ));
+                    }
+                    log::warn!("Parallel fetch of id={} failed on all governors", id);
+                    retry_from_server(context)
+                }))
+            }),
+    )
+}
+
+/// Fetch the chunks of a parallel fetch from all sources. The sources that did not fail
+/// fetch the chunks returned by the failed ones, until there are no chunks left or all
+/// sources failed.
+fn fetch_from_sources(
+    sources: Vec<Rc<governor_bootstrap::Client>>,
+    id: DataObjectId,
+    parallel: Rc<RefCell<ParallelFetch>>,
+) -> Box<Future<Item = (), Error = Error>> {
+    Box::new(future::loop_fn(sources, move |sources| {
+        let workers: Vec<_> = sources
+            .iter()
+            .map(|remote| fetch_chunks(remote.clone(), id, parallel.clone()))
+            .collect();
+        let parallel = parallel.clone();
+        future::join_all(workers).map(move |alive| {
+            let alive: Vec<_> = sources
+                .into_iter()
+                .zip(alive)
+                .filter(|&(_, ok)| ok)
+                .map(|(remote, _)| remote)
+                .collect();
+            if parallel.borrow().remaining == 0 || alive.is_empty() {
+                future::Loop::Break(())
+            } else {
+                future::Loop::Continue(alive)
+            }
+        })
+    }))
+}
+
+/// Fetch chunks of a parallel fetch from one governor until there are no chunks left.
+/// Returns false when the governor failed; its chunk is returned for the others.
+fn fetch_chunks(
+    remote: Rc<governor_bootstrap::Client>,
+    id: DataObjectId,
+    parallel: Rc<RefCell<ParallelFetch>>,
+) -> Box<Future<Item = bool, Error = Error>> {
+    Box::new(future::loop_fn((), move |()| {
+        let (offset, size) = match parallel.borrow_mut().chunks.pop_front() {
+            Some(chunk) => chunk,
+            None => return Either::A(future::ok(future::Loop::Break(true))),
+        };
+        let mut req = remote.fetch_request();
+        {
+            let mut request = req.get();
+            request.set_offset(offset as u64);
+            request.set_size(size as u64);
+            request.set_include_info(false);
+            request.set_compress(false);
+            id.to_capnp(&mut request.get_id().unwrap());
+        }
+        let parallel = parallel.clone();
+        Either::B(req.send().promise.then(move |r| {
+            let mut p = parallel.borrow_mut();
+            let written = match r {
+                Ok(ref r) => match r.get() {
+                    Ok(response) => match response.get_status().which() {
+                        Ok(::rain_core::common_capnp::fetch_result::status::Ok(())) => {
+                            let data = response.get_data().unwrap();
+                            if data.len() == size {
+                                p.builder.write_at(offset, data);
+                                true
+                            } else {
+                                false
+                            }
+                        }
+                        _ => false,
+                    },
+                    Err(_) => false,
+                },
+                Err(_) => false,
+            };
+            if written {
+                p.remaining -= size;
+                Ok(future::Loop::Continue(()))
+            } else {
+                log::warn!("Fetching a chunk of id={} failed", id);
+                p.chunks.push_front((offset, size));
+                Ok(future::Loop::Break(false))
+            }
+        }))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use capnp::capability::Promise;
+    use governor::fs::workdir::WorkDir;
+    use tempdir::TempDir;
+    use tokio_core::reactor::Core;
+
+    /// Governor serving chunks of the data; it fails after serving `chunks_left` chunks
+    struct ChunkServer {
+        data: Rc<Vec<u8>>,
+        chunks_left: usize,
+    }
+
+    impl governor_bootstrap::Server for ChunkServer {
+        fn fetch(
+            &mut self,
+            params: governor_bootstrap::FetchParams,
+            mut results: governor_bootstrap::FetchResults,
+        ) -> Promise<(), ::capnp::Error> {
+            if self.chunks_left == 0 {
+                return Promise::err(::capnp::Error::failed("Governor lost".to_string()));
+            }
+            self.chunks_left -= 1;
+            let params = params.get().unwrap();
+            let offset = params.get_offset() as usize;
+            let end = offset + params.get_size() as usize;
+            let mut results = results.get();
+            results.reborrow().get_status().set_ok(());
+            results.set_data(&self.data[offset..end]);
+            Promise::ok(())
+        }
+    }
+
+    fn new_source(data: &Rc<Vec<u8>>, chunks_left: usize) -> Rc<governor_bootstrap::Client> {
+        Rc::new(
+            governor_bootstrap::ToClient::new(ChunkServer {
+                data: data.clone(),
+                chunks_left,
+            })
+            .from_server::<::capnp_rpc::Server>(),
+        )
+    }
+
+    fn new_parallel_fetch(workdir: &WorkDir, size: usize, chunk: usize) -> ParallelFetch {
+        let mut chunks = VecDeque::new();
+        let mut offset = 0;
+        while offset < size {
+            chunks.push_back((offset, ::std::cmp::min(chunk, size - offset)));
+            offset += chunk;
+        }
+        ParallelFetch {
+            builder: DataBuilder::new(workdir, DataType::Blob, Some(size)),
+            chunks,
+            remaining: size,
+        }
+    }
+
+    #[test]
+    fn source_fails_mid_transfer() {
+        let dir = TempDir::new("rain-test").unwrap();
+        let workdir = WorkDir::new(dir.path().to_path_buf());
+        let mut core = Core::new().unwrap();
+        let data: Rc<Vec<u8>> = Rc::new((0..20_000).map(|i| (i % 251) as u8).collect());
+        let parallel = Rc::new(RefCell::new(new_parallel_fetch(&workdir, data.len(), 1000)));
+
+        let sources = vec![new_source(&data, 3), new_source(&data, 100)];
+        core.run(fetch_from_sources(
+            sources,
+            DataObjectId::new(1, 1),
+            parallel.clone(),
+        ))
+        .unwrap();
+
+        let mut p = parallel.borrow_mut();
+        assert_eq!(p.remaining, 0);
+        assert!(p.chunks.is_empty());
+        match p.builder.build(&workdir).storage() {
+            &::governor::data::Storage::Memory(ref mem) => assert!(mem[..] == data[..]),
+            _ => panic!("Small data are expected in memory"),
+        }
+    }
+
+    #[test]
+    fn all_sources_fail() {
+        let dir = TempDir::new("rain-test").unwrap();
+        let workdir = WorkDir::new(dir.path().to_path_buf());
+        let mut core = Core::new().unwrap();
+        let data: Rc<Vec<u8>> = Rc::new(vec![1; 10_000]);
+        let parallel = Rc::new(RefCell::new(new_parallel_fetch(&workdir, data.len(), 1000)));
+
+        let sources = vec![new_source(&data, 2), new_source(&data, 3)];
+        core.run(fetch_from_sources(
+            sources,
+            DataObjectId::new(1, 1),
+            parallel.clone(),
+        ))
+        .unwrap();
+
+        let p = parallel.borrow();
+        assert_eq!(p.remaining, 5000);
+        assert_eq!(p.chunks.len(), 5);
+    }
+
+    #[test]
+    fn replicated_blobs_are_not_compressed() {
+        let mut spec = ObjectSpec::default();
+        assert!(compress_transfer(&spec));
+        spec.replicas = Some(2);
+        assert!(!compress_transfer(&spec));
+        spec.data_type = DataType::Directory;
+        assert!(compress_transfer(&spec));
+    }
+}
@@ -7,14 +7,16 @@ use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::rc::Rc;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use error_chain::bail;
 
 use common::Monitor;
-use common::{create_protocol_stream, new_rpc_system, Connection};
+use common::{accept_stream, connect_stream, create_protocol_stream, new_rpc_system, Connection,
+             RpcStream, TlsConfig, GOVERNOR_TLS_NAME};
 
 use governor::data::transport::TransportView;
-use governor::data::Data;
+use governor::data::{Data, Storage};
 use governor::fs::workdir::WorkDir;
 use governor::graph::executor::get_log_tails;
 use governor::graph::{executor_command, DataObject, DataObjectRef, DataObjectState, ExecutorRef,
@@ -40,6 +42,14 @@ const MONITORING_INTERVAL: u64 = 5; // Monitoring interval in seconds
 const DELETE_WAIT_LIST_INTERVAL: u64 = 2; // How often is delete_wait_list checked in seconds
 const DEFAULT_DELETE_LIST_MAX_TIMEOUT: u32 = 5;
 const DEFAULT_TRANSPORT_VIEW_TIMEOUT: u32 = 10;
+const DISK_QUOTA_INTERVAL: u64 = 2; // How often is the disk quota checked in seconds
+
+fn transport_view_timeout() -> Instant {
+    Instant::now() + Duration::from_secs(DEFAULT_TRANSPORT_VIEW_TIMEOUT as u64)
+}
+
+/// Fraction of the disk quota above which objects are evicted and disk pressure is reported
+const DISK_PRESSURE_THRESHOLD: f64 = 0.9;
 
 pub struct State {
     pub(super) graph: Graph,
@@ -47,6 +57,9 @@ pub struct State {
     /// If true, next "turn" the scheduler is executed
     need_scheduling: bool,
 
+    /// The governor was decommissioned by the server and terminates
+    decommissioned: bool,
+
     /// Tokio core handle
     handle: Handle,
 
@@ -61,8 +74,39 @@ pub struct State {
     updated_objects: RcSet<DataObjectRef>,
     updated_tasks: RcSet<TaskRef>,
 
-    /// Transport views (2nd element of tuple is timeout)
-    transport_views: HashMap<DataObjectId, (Rc<TransportView>, ::std::time::Instant)>,
+    /// Transport views by object and requested compression (2nd element of tuple is timeout)
+    transport_views:
+        HashMap<(DataObjectId, Compression), (Rc<TransportView>, ::std::time::Instant)>,
+
+    /// Requests waiting for transport views that are being compressed
+    pending_transport_views: HashMap<
+        (DataObjectId, Compression),
+        Vec<::futures::unsync::oneshot::Sender<Rc<TransportView>>>,
+    >,
+
+    /// Threads compressing transport views and spilling objects to disk,
+    /// both would block the reactor
+    compression_pool: ::tokio::executor::thread_pool::ThreadPool,
+
+    /// Limit of the total size of finished objects held in memory; the least recently
+    /// used objects are spilled to the working directory above it. `None` means no limit.
+    memory_budget: Option<usize>,
+
+    /// Finished objects held in memory with the time of their last use
+    memory_objects: HashMap<DataObjectId, Instant>,
+
+    /// Total size of the objects in `memory_objects`
+    memory_used: usize,
+
+    /// Limit of the total size of finished objects in the working directory;
+    /// `None` means no limit
+    disk_quota: Option<usize>,
+
+    /// Disk pressure last reported to the server
+    disk_pressure: bool,
+
+    /// An eviction request is waiting for the answer of the server
+    eviction_requested: bool,
 
     /// A governor assigned to this governor
     governor_id: GovernorId,
@@ -76,6 +120,9 @@ pub struct State {
 
     free_resources: Resources,
 
+    /// Labels announced to the server, used for placement constraints of tasks
+    labels: HashMap<String, String>,
+
     /// Path to working directory
     work_dir: WorkDir,
 
@@ -89,11 +136,33 @@ pub struct State {
     // e.g. "py" => ["python", "-m", "rain.executor"]
     executor_args: HashMap<String, Vec<String>>,
 
+    /// TLS of connections to the server and other governors; `None` for plain TCP
+    tls: Option<TlsConfig>,
+
     self_ref: Option<StateRef>,
 }
 
 pub type StateRef = WrappedRcRefCell<State>;
 
+/// Pick objects (given by their sizes on disk) to free at least `excess` bytes,
+/// the largest objects first.
+fn pick_eviction_candidates(
+    mut candidates: Vec<(usize, DataObjectId)>,
+    mut excess: usize,
+) -> Vec<DataObjectId> {
+    candidates.retain(|&(size, _)| size > 0);
+    candidates.sort_by_key(|&(size, id)| (::std::cmp::Reverse(size), id));
+    let mut ids = Vec::new();
+    for (size, id) in candidates {
+        if excess == 0 {
+            break;
+        }
+        ids.push(id);
+        excess = excess.saturating_sub(size);
+    }
+    ids
+}
+
 impl State {
     #[inline]
     pub fn work_dir(&self) -> &WorkDir {
@@ -129,25 +198,102 @@ impl State {
         self.need_scheduling = true;
     }
 
-    pub fn get_transport_view(&mut self, id: DataObjectId) -> Option<Rc<TransportView>> {
-        let now = ::std::time::Instant::now();
-        let new_timeout =
-            now + ::std::time::Duration::from_secs(DEFAULT_TRANSPORT_VIEW_TIMEOUT as u64);
+    /// Terminate the governor in the next turn
+    pub fn decommission(&mut self) {
+        self.decommissioned = true;
+    }
 
-        if let ::std::collections::hash_map::Entry::Occupied(mut e) = self.transport_views.entry(id)
-        {
+    /// Get a view for sending the object, compressed according to its spec if
+    /// `compress` is set. Resolves to `None` when the object is not here.
+    ///
+    /// Compressed views are created in the compression thread pool, requests for
+    /// a view that is being compressed wait for the running compression.
+    pub fn get_transport_view(
+        &mut self,
+        id: DataObjectId,
+        compress: bool,
+    ) -> Box<Future<Item = Option<Rc<TransportView>>, Error = Error>> {
+        let obj_ref = match self.graph.objects.get(&id) {
+            Some(obj_ref) => obj_ref.clone(),
+            None => return Box::new(Ok(None).into_future()),
+        };
+        self.touch_object(id);
+        let compression = if compress {
+            obj_ref.get().spec.transfer_compression()
+        } else {
+            Compression::None
+        };
+        let key = (id, compression);
+
+        if let Some(&mut (ref tw, ref mut timeout)) = self.transport_views.get_mut(&key) {
             log::debug!("Getting transport view from cache id={}", id);
-            let &mut (ref tw, ref mut timeout) = e.get_mut();
-            *timeout = new_timeout;
-            return Some(tw.clone());
+            *timeout = transport_view_timeout();
+            return Box::new(Ok(Some(tw.clone())).into_future());
         }
-        self.graph.objects.get(&id).cloned().map(|obj_ref| {
+        if let Some(waiting) = self.pending_transport_views.get_mut(&key) {
+            log::debug!("Waiting for transport view id={}", id);
+            let (sender, receiver) = ::futures::unsync::oneshot::channel();
+            waiting.push(sender);
+            return Box::new(
+                receiver.map(Some).map_err(move |_| {
+                    Error::from(format!("Transport view of object {} failed", id))
+                }),
+            );
+        }
+
+        let data = obj_ref.get().data().clone();
+        if !TransportView::should_compress(&data, compression) {
             log::debug!("Creating new transport view for object id={}", id);
-            let transport_view = Rc::new(TransportView::from(self, obj_ref.get().data()).unwrap());
+            let transport_view = match TransportView::from(self, &data) {
+                Ok(view) => Rc::new(view),
+                Err(e) => return Box::new(Err(e).into_future()),
+            };
             self.transport_views
-                .insert(id, (transport_view.clone(), new_timeout));
-            transport_view
-        })
+                .insert(key, (transport_view.clone(), transport_view_timeout()));
+            return Box::new(Ok(Some(transport_view)).into_future());
+        }
+
+        log::debug!(
+            "Compressing transport view for object id={} ({})",
+            id,
+            compression
+        );
+        self.pending_transport_views.insert(key, Vec::new());
+        let (sender, receiver) = ::futures::sync::oneshot::channel();
+        let temp_file = self.work_dir.make_temp_file();
+        let compressed_data = data.clone();
+        self.compression_pool
+            .spawn(::futures::future::lazy(move || {
+                let _ = sender.send(TransportView::compressed(&compressed_data, temp_file));
+                Ok(())
+            }));
+
+        let state = self.self_ref();
+        Box::new(
+            receiver
+                .map_err(|_| Error::from("Compression thread failed"))
+                .and_then(move |compressed| -> Result<Option<Rc<TransportView>>> {
+                    let mut s = state.get_mut();
+                    // Dropping the senders on error fails the waiting requests
+                    let waiting = s.pending_transport_views.remove(&key).unwrap_or_default();
+                    let transport_view = match compressed? {
+                        Some(view) => Rc::new(view),
+                        None => {
+                            log::debug!("Data does not compress, sending uncompressed");
+                            Rc::new(TransportView::from(&s, &data)?)
+                        }
+                    };
+                    // The object may have been removed in the meantime
+                    if s.graph.objects.contains_key(&id) {
+                        s.transport_views
+                            .insert(key, (transport_view.clone(), transport_view_timeout()));
+                    }
+                    for sender in waiting {
+                        let _ = sender.send(transport_view.clone());
+                    }
+                    Ok(Some(transport_view))
+                }),
+        )
     }
 
     pub fn add_task(
@@ -191,7 +337,226 @@ impl State {
             self.need_scheduling();
         }
 
+        if let Storage::Memory(_) = *dataobject.data().storage() {
+            self.memory_used += dataobject.data().size();
+            self.memory_objects
+                .insert(dataobject.spec.id, Instant::now());
+        }
+
         self.remove_dataobj_if_not_needed(&mut dataobject);
+        drop(dataobject);
+        self.check_memory_budget();
+    }
+
+    /// Record a use of the object; the least recently used objects are spilled first.
+    pub fn touch_object(&mut self, id: DataObjectId) {
+        if let Some(last_use) = self.memory_objects.get_mut(&id) {
+            *last_use = Instant::now();
+        }
+    }
+
+    /// Spill the least recently used objects held in memory to the working directory
+    /// until they fit into the memory budget.
+    fn check_memory_budget(&mut self) {
+        let budget = match self.memory_budget {
+            Some(budget) => budget,
+            None => return,
+        };
+        if self.memory_used <= budget {
+            return;
+        }
+        let mut objects: Vec<(DataObjectId, Instant)> = self
+            .memory_objects
+            .iter()
+            .map(|(id, last_use)| (*id, *last_use))
+            .collect();
+        objects.sort_by_key(|&(_, last_use)| last_use);
+        for (id, _) in objects {
+            if self.memory_used <= budget {
+                break;
+            }
+            if let Err(e) = self.spill_object(id) {
+                log::error!("Spilling object {} to disk failed: {}", id, e);
+                break;
+            }
+        }
+    }
+
+    /// Move the data of a finished object from memory to the working directory.
+    /// The data is written on the compression pool, the object is switched to the file
+    /// when the write completes and it no longer counts to `memory_used` in the meantime.
+    /// Tasks and transfers that already hold the data in memory are not affected.
+    fn spill_object(&mut self, id: DataObjectId) -> Result<()> {
+        let data = self.object_by_id(id)?.get().data().clone();
+        self.memory_objects.remove(&id);
+        self.memory_used -= data.size();
+
+        let (sender, receiver) = ::futures::sync::oneshot::channel();
+        let path = self.work_dir.new_path_for_dataobject();
+        let spilled_data = data.clone();
+        self.compression_pool
+            .spawn(::futures::future::lazy(move || {
+                let _ = sender.send(spilled_data.spill_to_path(path));
+                Ok(())
+            }));
+
+        let state = self.self_ref();
+        self.handle.spawn(
+            receiver
+                .map_err(|_| Error::from("Spilling thread failed"))
+                .and_then(|spilled| spilled)
+                .then(move |spilled| {
+                    state.get_mut().object_spilled(id, &data, spilled);
+                    Ok(())
+                }),
+        );
+        Ok(())
+    }
+
+    /// Finish `spill_object`. The written file is dropped (and deleted) when the object
+    /// was removed in the meantime.
+    fn object_spilled(&mut self, id: DataObjectId, data: &Arc<Data>, spilled: Result<Data>) {
+        let object_ref = match self.graph.objects.get(&id) {
+            Some(object_ref) => object_ref.clone(),
+            None => return,
+        };
+        let mut object = object_ref.get_mut();
+        let unchanged = match object.state {
+            DataObjectState::Finished(ref current) => Arc::ptr_eq(current, data),
+            _ => false,
+        };
+        if !unchanged {
+            return;
+        }
+        match spilled {
+            Ok(spilled) => {
+                log::debug!(
+                    "Object id={} ({} bytes) spilled to disk",
+                    id,
+                    spilled.size()
+                );
+                self.transport_views.retain(|key, _| key.0 != id);
+                object.state = DataObjectState::Finished(Arc::new(spilled));
+            }
+            Err(e) => {
+                log::error!("Spilling object {} to disk failed: {}", id, e);
+                self.memory_objects.insert(id, Instant::now());
+                self.memory_used += data.size();
+            }
+        }
+    }
+
+    /// Total size of the finished objects in the working directory
+    fn disk_usage(&self) -> usize {
+        self.graph
+            .objects
+            .values()
+            .map(|o| o.get().disk_size())
+            .sum()
+    }
+
+    /// Keep the finished objects in the working directory below the disk quota.
+    /// Objects that are not needed anymore are deleted at once, objects assigned
+    /// by the server are offered to the server for eviction. Changes of the disk
+    /// pressure are reported to the server.
+    fn check_disk_quota(&mut self) {
+        let quota = match self.disk_quota {
+            Some(quota) => quota,
+            None => return,
+        };
+        if self.upstream.is_none() {
+            return;
+        }
+        let limit = (quota as f64 * DISK_PRESSURE_THRESHOLD) as usize;
+        let mut used = self.disk_usage();
+
+        if used > limit {
+            let mut waiting: Vec<(DataObjectRef, Instant)> = self
+                .graph
+                .delete_wait_list
+                .iter()
+                .map(|(obj, timeout)| (obj.clone(), *timeout))
+                .collect();
+            waiting.sort_by_key(|&(_, timeout)| timeout);
+            for (obj, _) in waiting {
+                if used <= limit {
+                    break;
+                }
+                {
+                    let mut o = obj.get_mut();
+                    used -= o.disk_size();
+                    self.remove_object(&mut o);
+                    let id = o.spec.id;
+                    self.transport_views.retain(|key, _| key.0 != id);
+                }
+                self.graph.delete_wait_list.remove(&obj);
+            }
+        }
+
+        if used > limit && !self.eviction_requested {
+            // Only objects not used by local tasks
+            let candidates: Vec<(usize, DataObjectId)> = self
+                .graph
+                .objects
+                .values()
+                .map(|o| o.get())
+                .filter(|o| o.assigned && o.consumers.is_empty())
+                .map(|o| (o.disk_size(), o.spec.id))
+                .collect();
+            let ids = pick_eviction_candidates(candidates, used - limit);
+            if !ids.is_empty() {
+                self.request_eviction(ids);
+            }
+        }
+
+        let pressure = used > limit;
+        if pressure != self.disk_pressure {
+            if pressure {
+                log::warn!(
+                    "Data objects take {} bytes, near the disk quota of {} bytes",
+                    used,
+                    quota
+                );
+            } else {
+                log::info!("Data objects take {} bytes, disk pressure is over", used);
+            }
+            self.disk_pressure = pressure;
+            let mut req = self.upstream.as_ref().unwrap().set_disk_pressure_request();
+            req.get().set_pressure(pressure);
+            self.spawn_panic_on_error(req.send().promise.map(|_| ()).map_err(|e| e.into()));
+        }
+    }
+
+    /// Offer objects to the server for eviction. The server unassigns the objects
+    /// it releases; they are then removed as any other unassigned objects.
+    fn request_eviction(&mut self, ids: Vec<DataObjectId>) {
+        log::info!(
+            "Offering {} object(s) to the server for eviction",
+            ids.len()
+        );
+        let mut req = self.upstream.as_ref().unwrap().evict_objects_request();
+        {
+            let mut objects = req.get().init_objects(ids.len() as u32);
+            for (i, id) in ids.iter().enumerate() {
+                id.to_capnp(&mut objects.reborrow().get(i as u32));
+            }
+        }
+        self.eviction_requested = true;
+        let state_ref = self.self_ref();
+        self.spawn_panic_on_error(
+            req.send()
+                .promise
+                .map(move |response| {
+                    let evicted = response
+                        .get()
+                        .and_then(|r| r.get_evicted())
+                        .map(|l| l.len())
+                        .unwrap_or(0);
+                    log::debug!("Server evicted {} object(s)", evicted);
+                    state_ref.get_mut().eviction_requested = false;
+                })
+                .map_err(|e| e.into()),
+        );
     }
 
     /// Send status of updated elements (updated_tasks/updated_objects) and then clear this sets
@@ -475,6 +840,9 @@ impl State {
 
     pub fn remove_object(&mut self, object: &mut DataObject) {
         log::debug!("Removing object {}", object.spec.id);
+        if self.memory_objects.remove(&object.spec.id).is_some() {
+            self.memory_used -= object.data().size();
+        }
         let id_list = [object.spec.id];
         for sw in ::std::mem::replace(&mut object.executor_cache, Default::default()) {
             sw.get().send_remove_cached_objects(&id_list);
@@ -570,9 +938,11 @@ impl State {
         assert!(self.free_slots > 0);
         self.free_slots -= 1;
         log::debug!(
-            "{} cpus allocated, free now: {}",
+            "{} cpus and {} bytes of memory allocated, free now: {} cpus, {} bytes",
             resources.cpus(),
-            self.free_resources.cpus()
+            resources.memory(),
+            self.free_resources.cpus(),
+            self.free_resources.memory()
         );
     }
 
@@ -581,9 +951,11 @@ impl State {
         self.free_slots += 1;
         self.need_scheduling();
         log::debug!(
-            "{} cpus disposed, free now: {}",
+            "{} cpus and {} bytes of memory disposed, free now: {} cpus, {} bytes",
             resources.cpus(),
-            self.free_resources.cpus()
+            resources.memory(),
+            self.free_resources.cpus(),
+            self.free_resources.memory()
         );
     }
 
@@ -591,24 +963,31 @@ impl State {
         TaskInstance::start(self, task_ref);
     }
 
+    /// Start ready tasks that fit into the free resources, tasks with higher priority first.
+    /// Tasks with the same priority are started in the order in which they became ready.
     pub fn schedule(&mut self) {
-        let mut i = 0;
-        while i < self.graph.ready_tasks.len() {
-            if self.free_slots == 0 {
-                break;
-            }
-            let n_cpus = self.free_resources.cpus;
-            let j = self.graph.ready_tasks[i..]
-                .iter()
-                .position(|task| n_cpus >= task.get().spec.resources.cpus);
-            if j.is_none() {
-                break;
+        // The sort is stable, so the ready order is kept among the same priorities.
+        // Free resources only shrink during the walk, a task that does not fit
+        // now would not fit later in this pass either.
+        let mut tasks = ::std::mem::replace(&mut self.graph.ready_tasks, Vec::new());
+        tasks.sort_by_key(|t| ::std::cmp::Reverse(t.get().spec.priority));
+        let mut waiting = Vec::new();
+        for task_ref in tasks {
+            let fits = self.free_slots > 0
+                && task_ref
+                    .get()
+                    .spec
+                    .resources
+                    .is_subset_of(&self.free_resources);
+            if fits {
+                self.start_task(task_ref);
+            } else {
+                waiting.push(task_ref);
             }
-            let j = j.unwrap();
-            let task_ref = self.graph.ready_tasks.remove(i + j);
-            self.start_task(task_ref.clone());
-            i += j;
         }
+        // Tasks that became ready while starting the others
+        waiting.extend(self.graph.ready_tasks.drain(..));
+        self.graph.ready_tasks = waiting;
     }
 
     pub fn wait_for_remote_governor(
@@ -626,9 +1005,11 @@ impl State {
 
         let state = self.self_ref();
         let governor_id = governor_id.clone();
+        let tls = self.tls.clone();
 
         Box::new(
             TcpStream::connect(&governor_id, &self.handle)
+                .and_then(move |stream| connect_stream(tls.as_ref(), GOVERNOR_TLS_NAME, stream))
                 .map(move |stream| {
                     log::debug!("Connection to governor {} established", governor_id);
                     let mut rpc_system = new_rpc_system(stream, None);
@@ -674,16 +1055,20 @@ impl StateRef {
         handle: Handle,
         work_dir: PathBuf,
         log_dir: PathBuf,
-        n_cpus: u32,
+        resources: Resources,
+        labels: HashMap<String, String>,
         executors: HashMap<String, Vec<String>>,
+        tls: Option<TlsConfig>,
+        memory_budget: Option<usize>,
+        disk_quota: Option<usize>,
     ) -> Self {
-        let resources = Resources { cpus: n_cpus };
 
         let state = Self::wrap(State {
             handle,
-            free_slots: 4 * n_cpus,
+            free_slots: 4 * resources.cpus,
             resources: resources.clone(),
             free_resources: resources,
+            labels,
             upstream: None,
             remote_governors: HashMap::new(),
             updated_objects: Default::default(),
@@ -693,14 +1078,26 @@ impl StateRef {
             governor_id: empty_governor_id(),
             graph: Graph::new(),
             need_scheduling: false,
+            decommissioned: false,
             monitor: Monitor::new(),
             executor_args: executors,
+            tls,
             self_ref: None,
             delete_list_max_timeout: ::std::env::var("RAIN_DELETE_LIST_TIMEOUT")
                 .ok()
                 .and_then(|s| s.parse().ok())
                 .unwrap_or(DEFAULT_DELETE_LIST_MAX_TIMEOUT),
             transport_views: Default::default(),
+            pending_transport_views: Default::default(),
+            compression_pool: ::tokio::executor::thread_pool::Builder::new()
+                .name_prefix("compression-")
+                .build(),
+            memory_budget,
+            memory_objects: Default::default(),
+            memory_used: 0,
+            disk_quota,
+            disk_pressure: false,
+            eviction_requested: false,
         });
         state.get_mut().self_ref = Some(state.clone());
         state
@@ -713,23 +1110,31 @@ impl StateRef {
         log::info!("New connection from {}", address);
         stream.set_nodelay(true).unwrap();
 
-        let bootstrap = ::rain_core::governor_capnp::governor_bootstrap::ToClient::new(
-            ::governor::rpc::bootstrap::GovernorBootstrapImpl::new(self),
-        ).from_server::<::capnp_rpc::Server>();
-        let rpc_system = new_rpc_system(stream, Some(bootstrap.client));
-        self.get()
-            .spawn_panic_on_error(rpc_system.map_err(|e| e.into()));
+        let state = self.clone();
+        let future = accept_stream(self.get().tls.as_ref(), stream)
+            .map(move |stream| {
+                let bootstrap = ::rain_core::governor_capnp::governor_bootstrap::ToClient::new(
+                    ::governor::rpc::bootstrap::GovernorBootstrapImpl::new(&state),
+                )
+                .from_server::<::capnp_rpc::Server>();
+                let rpc_system = new_rpc_system(stream, Some(bootstrap.client));
+                state
+                    .get()
+                    .spawn_panic_on_error(rpc_system.map_err(|e| e.into()));
+            })
+            .map_err(move |e| log::warn!("TLS handshake with {} failed: {}", address, e));
+        self.get().handle.spawn(future);
     }
 
     // This is called when governor connection to server is established
     pub fn on_connected_to_server(
         &self,
-        stream: TcpStream,
+        stream: Box<RpcStream>,
         listen_address: SocketAddr,
         ready_file: Option<String>,
+        token: String,
     ) {
         log::info!("Connected to server; registering as governor");
-        stream.set_nodelay(true).unwrap();
         let mut rpc_system = new_rpc_system(stream, None);
         let bootstrap: ::rain_core::server_capnp::server_bootstrap::Client =
             rpc_system.bootstrap(rpc_twoparty_capnp::Side::Server);
@@ -741,11 +1146,21 @@ impl StateRef {
         let mut req = bootstrap.register_as_governor_request();
 
         req.get().set_version(GOVERNOR_PROTOCOL_VERSION);
+        req.get().set_token(&token);
         req.get().set_control(governor_control);
         listen_address.to_capnp(&mut req.get().get_address().unwrap());
         self.get()
             .resources
             .to_capnp(&mut req.get().get_resources().unwrap());
+        {
+            let inner = self.get();
+            let mut labels = req.get().init_labels(inner.labels.len() as u32);
+            for (i, (key, value)) in inner.labels.iter().enumerate() {
+                let mut label = labels.reborrow().get(i as u32);
+                label.set_key(key);
+                label.set_value(value);
+            }
+        }
 
         let state = self.clone();
         let future = req.send()
@@ -777,11 +1192,14 @@ impl StateRef {
             .spawn(rpc_system.map_err(|e| log::error!("RPC error: {:?}", e)));
     }
 
+    /// `server_name` is the host name of the server, used to verify its TLS certificate.
     pub fn start(
         &self,
         server_address: SocketAddr,
+        server_name: String,
         mut listen_address: SocketAddr,
         ready_file: Option<&str>,
+        token: String,
     ) {
         let handle = self.get().handle.clone();
 
@@ -850,32 +1268,43 @@ impl StateRef {
                     {
                         let mut o = obj.get_mut();
                         s.remove_object(&mut o);
-                        s.transport_views.remove(&o.spec.id);
+                        s.transport_views.retain(|key, _| key.0 != o.spec.id);
                     }
                     s.graph.delete_wait_list.remove(&obj);
                 }
 
-                let to_delete: Vec<DataObjectId> = s.transport_views
-                    .iter()
-                    .filter(|pair| (pair.1).1 < now)
-                    .map(|pair| *pair.0)
-                    .collect();
-
-                for id in to_delete {
-                    s.transport_views.remove(&id);
-                }
+                s.transport_views.retain(|_, value| value.1 >= now);
                 Ok(())
             })
             .map_err(|e| panic!("Error during checking wait list {}", e));
         handle.spawn(check_list);
 
+        // --- Start checking disk quota ----
+        if self.get().disk_quota.is_some() {
+            let state = self.clone();
+            let interval =
+                ::tokio_timer::Interval::new(now, Duration::from_secs(DISK_QUOTA_INTERVAL));
+            let check_quota = interval
+                .for_each(move |_| {
+                    state.get_mut().check_disk_quota();
+                    Ok(())
+                })
+                .map_err(|e| panic!("Error during checking disk quota {}", e));
+            handle.spawn(check_quota);
+        }
+
         // --- Start connection to server ----
         let core1 = self.clone();
         let ready_file = ready_file.map(|f| f.to_string());
         log::info!("Connecting to server addr={}", server_address);
+        let tls = self.get().tls.clone();
         let connect = TcpStream::connect(&server_address, &handle)
             .and_then(move |stream| {
-                core1.on_connected_to_server(stream, listen_address, ready_file);
+                stream.set_nodelay(true).unwrap();
+                connect_stream(tls.as_ref(), &server_name, stream)
+            })
+            .and_then(move |stream| {
+                core1.on_connected_to_server(stream, listen_address, ready_file, token);
                 Ok(())
             })
             .map_err(|e| {
@@ -885,7 +1314,8 @@ impl StateRef {
         handle.spawn(connect);
     }
 
-    pub fn turn(&self) {
+    /// Main loop State entry. Returns `false` when the governor should stop.
+    pub fn turn(&self) -> bool {
         let mut state = self.get_mut();
         if state.need_scheduling {
             state.need_scheduling = false;
@@ -896,5 +1326,216 @@ impl StateRef {
         if !state.updated_objects.is_empty() || !state.updated_tasks.is_empty() {
             state.send_update()
         }
+        !state.decommissioned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+    use tokio_core::reactor::Core;
+    #[test]
+    fn eviction_candidates() {
+        let id = |i| DataObjectId::new(1, i);
+        let candidates = vec![(10, id(1)), (0, id(2)), (50, id(3)), (30, id(4))];
+        assert!(pick_eviction_candidates(candidates.clone(), 0).is_empty());
+        assert_eq!(
+            pick_eviction_candidates(candidates.clone(), 40),
+            vec![id(3)]
+        );
+        assert_eq!(
+            pick_eviction_candidates(candidates.clone(), 70),
+            vec![id(3), id(4)]
+        );
+        // Objects in memory take no disk space and are never offered
+        assert_eq!(
+            pick_eviction_candidates(candidates, 1000),
+            vec![id(3), id(4), id(1)]
+        );
+    }
+
+    fn create_state(
+        dir: &TempDir,
+        resources: Resources,
+        memory_budget: Option<usize>,
+    ) -> (Core, StateRef) {
+        let work_dir = dir.path().join("work");
+        let log_dir = dir.path().join("logs");
+        ::std::fs::create_dir(&work_dir).unwrap();
+        ::std::fs::create_dir(&log_dir).unwrap();
+        let core = Core::new().unwrap();
+        let state = StateRef::new(
+            core.handle(),
+            work_dir,
+            log_dir,
+            resources,
+            Default::default(),
+            Default::default(),
+            None,
+            memory_budget,
+            None,
+        );
+        (core, state)
+    }
+
+    /// Runs the reactor until the condition holds, at most for 10 seconds
+    fn turn_until<F: Fn() -> bool>(core: &mut Core, condition: F) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !condition() && Instant::now() < deadline {
+            core.turn(Some(Duration::from_millis(10)));
+        }
+        assert!(condition());
+    }
+
+    /// Adds a finished blob object with the given id
+    fn add_input(s: &mut State, id: Id) -> DataObjectRef {
+        let spec = ObjectSpec {
+            id: DataObjectId::new(1, id),
+            ..Default::default()
+        };
+        let data = Data::new(Storage::Memory(b"data".to_vec()), DataType::Blob);
+        let oref = s.add_dataobject(spec, DataObjectState::Finished(Arc::new(data)), true);
+        s.object_is_finished(&oref);
+        oref
+    }
+
+    /// Adds a ready "buildin/sleep" task with a single input and output
+    fn add_sleep_task(s: &mut State, id: Id, cpus: u32, priority: i32) -> TaskRef {
+        let input = add_input(s, 100 + id);
+        let output_spec = ObjectSpec {
+            id: DataObjectId::new(1, 200 + id),
+            ..Default::default()
+        };
+        let output = s.add_dataobject(output_spec, DataObjectState::Assigned, true);
+        let spec = TaskSpec {
+            id: TaskId::new(1, id),
+            task_type: "buildin/sleep".into(),
+            config: Some(::serde_json::json!(100.0)),
+            resources: Resources {
+                cpus,
+                ..Default::default()
+            },
+            priority,
+            ..Default::default()
+        };
+        s.add_task(spec, vec![input], vec![output])
+    }
+
+    #[test]
+    fn schedule_by_priority_with_backfilling() {
+        let dir = TempDir::new("rain-test").unwrap();
+        let resources = Resources {
+            cpus: 4,
+            ..Default::default()
+        };
+        let (_core, state) = create_state(&dir, resources, None);
+        let mut s = state.get_mut();
+
+        let low = add_sleep_task(&mut *s, 1, 2, 0);
+        let high = add_sleep_task(&mut *s, 2, 3, 5);
+        let small = add_sleep_task(&mut *s, 3, 1, 0);
+        s.schedule();
+
+        // The high priority task overtakes the older one, which then does not fit
+        // into the single remaining cpu, but the small task behind it does
+        let running = |t: &TaskRef| t.get().state == TaskState::Running;
+        assert!(running(&high) && running(&small) && !running(&low));
+        assert!(s.graph.ready_tasks == vec![low]);
+        assert_eq!(s.free_resources.cpus(), 0);
+    }
+
+    #[test]
+    fn task_timeout_kills_process() {
+        let dir = TempDir::new("rain-test").unwrap();
+        let (mut core, state) = create_state(&dir, Resources::default(), None);
+        let pid_path = dir.path().join("pid");
+        let script = format!("echo $$ > {}; exec sleep 100", pid_path.display());
+        let task = {
+            let spec = TaskSpec {
+                id: TaskId::new(1, 1),
+                task_type: "buildin/run".into(),
+                config: Some(::serde_json::json!({
+                    "args": ["sh", "-c", script],
+                    "in_paths": [],
+                    "out_paths": [],
+                })),
+                timeout: Some(0.5),
+                ..Default::default()
+            };
+            let mut s = state.get_mut();
+            let task = s.add_task(spec, Vec::new(), Vec::new());
+            s.schedule();
+            task
+        };
+
+        turn_until(&mut core, || task.get().state == TaskState::Failed);
+        assert_eq!(task.get().info.error, "Task timed out after 0.5 s");
+
+        // The process is killed when the task future is dropped
+        let pid = ::std::fs::read_to_string(&pid_path).unwrap();
+        let stat_path = format!("/proc/{}/stat", pid.trim());
+        let is_alive = || match ::std::fs::read_to_string(&stat_path) {
+            // A killed process that was not reaped yet is a zombie
+            Ok(stat) => !stat.contains(") Z "),
+            Err(_) => false,
+        };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while is_alive() && Instant::now() < deadline {
+            ::std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!is_alive());
+    }
+
+    #[test]
+    fn spill_least_recently_used() {
+        let dir = TempDir::new("rain-test").unwrap();
+        let (mut core, state) = create_state(&dir, Resources::default(), Some(10));
+
+        let add_object = |id: Id, byte: u8| {
+            let spec = ObjectSpec {
+                id: DataObjectId::new(1, id),
+                ..Default::default()
+            };
+            let data = Data::new(Storage::Memory(vec![byte; 4]), DataType::Blob);
+            let mut s = state.get_mut();
+            let oref = s.add_dataobject(spec, DataObjectState::Finished(Arc::new(data)), true);
+            s.object_is_finished(&oref);
+            // Distinct times of the last use
+            ::std::thread::sleep(Duration::from_millis(2));
+            oref
+        };
+        let in_memory = |oref: &DataObjectRef| match *oref.get().data().storage() {
+            Storage::Memory(_) => true,
+            Storage::Path(_) => false,
+        };
+
+        let first = add_object(1, 1);
+        let second = add_object(2, 2);
+        state.get_mut().touch_object(DataObjectId::new(1, 1));
+        assert!(in_memory(&first) && in_memory(&second));
+        assert_eq!(state.get().memory_used, 8);
+
+        // Over the budget, the least recently used object goes to disk
+        let third = add_object(3, 3);
+        assert_eq!(state.get().memory_used, 8);
+        turn_until(&mut core, || !in_memory(&second));
+        assert!(in_memory(&first) && in_memory(&third));
+
+        let fourth = add_object(4, 4);
+        assert_eq!(state.get().memory_used, 8);
+        turn_until(&mut core, || !in_memory(&first));
+        assert!(in_memory(&third) && in_memory(&fourth));
+
+        for (i, oref) in [first, second, third, fourth].iter().enumerate() {
+            let id = oref.get().spec.id;
+            let view = state
+                .get_mut()
+                .get_transport_view(id, false)
+                .wait()
+                .unwrap()
+                .unwrap();
+            assert_eq!(view.get_slice(), &[i as u8 + 1; 4][..]);
+        }
     }
 }
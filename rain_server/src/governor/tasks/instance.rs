@@ -1,6 +1,7 @@
 use chrono::{DateTime, Utc};
 use futures::Future;
 use rain_core::{comm::*, errors::*};
+use std::time::{Duration, Instant};
 use error_chain::bail;
 
 use governor::graph::{ExecutorRef, TaskRef, TaskState};
@@ -26,6 +27,15 @@ pub struct TaskInstance {
 pub type TaskFuture = Future<Item = (), Error = Error>;
 pub type TaskResult = Result<Box<TaskFuture>>;
 
+/// How the run of a task ended
+enum TaskEnd {
+    Finished,
+    /// Stopped by the server
+    Terminated,
+    /// Reached `TaskSpec::timeout` (in seconds)
+    TimedOut(f32),
+}
+
 fn fail_unknown_type(_state: &mut State, task_ref: TaskRef) -> TaskResult {
     bail!("Unknown task type {}", task_ref.get().spec.task_type)
 }
@@ -62,6 +72,9 @@ impl TaskInstance {
             state.alloc_resources(&task.spec.resources);
             task.state = TaskState::Running;
             state.task_updated(&task_ref);
+            for input in &task.inputs {
+                state.touch_object(input.get().spec.id);
+            }
         }
 
         let task_fn = {
@@ -95,6 +108,25 @@ impl TaskInstance {
 
         let (sender, receiver) = ::futures::unsync::oneshot::channel::<()>();
 
+        // Dropping the task future on termination or timeout also kills the executor
+        // (see `KillOnDrop`), so a stuck executor is replaced by a new one
+        let stop: Box<Future<Item = TaskEnd, Error = Error>> = {
+            let cancel = receiver
+                .map(|()| TaskEnd::Terminated)
+                .map_err(|_| -> Error { unreachable!() });
+            match task_ref.get().spec.timeout {
+                Some(timeout) => {
+                    let deadline =
+                        Instant::now() + Duration::from_millis((timeout.max(0.0) * 1000.0) as u64);
+                    let timer = ::tokio_timer::Delay::new(deadline)
+                        .map(move |()| TaskEnd::TimedOut(timeout))
+                        .map_err(Error::from);
+                    Box::new(cancel.select(timer).map(|(r, _)| r).map_err(|(e, _)| e))
+                }
+                None => Box::new(cancel),
+            }
+        };
+
         let task_id = task_ref.get().spec.id;
         let instance = TaskInstance {
             task_ref: task_ref,
@@ -106,8 +138,8 @@ impl TaskInstance {
 
         state.spawn_panic_on_error(
             future
-                .map(|()| true)
-                .select(receiver.map(|()| false).map_err(|_| unreachable!()))
+                .map(|()| TaskEnd::Finished)
+                .select(stop)
                 .then(move |r| {
                     let mut state = state_ref.get_mut();
                     let instance = state.graph.running_tasks.remove(&task_id).unwrap();
@@ -125,7 +157,7 @@ impl TaskInstance {
                     );
 
                     match r {
-                        Ok((true, _)) => {
+                        Ok((TaskEnd::Finished, _)) => {
                             let all_finished = task.outputs.iter().all(|o| o.get().is_finished());
                             if !all_finished {
                                 task.set_failed("Some of outputs were not produced".into());
@@ -137,10 +169,13 @@ impl TaskInstance {
                                 task.state = TaskState::Finished;
                             }
                         }
-                        Ok((false, _)) => {
+                        Ok((TaskEnd::Terminated, _)) => {
                             log::debug!("Task {} was terminated", task.spec.id);
                             task.set_failed("Task terminated by server".into());
                         }
+                        Ok((TaskEnd::TimedOut(timeout), _)) => {
+                            task.set_failed(format!("Task timed out after {} s", timeout));
+                        }
                         Err((e, _)) => {
                             task.set_failed(e.description().to_string());
                         }
@@ -31,10 +31,12 @@ extern crate tokio;
 extern crate tokio_core;
 extern crate tokio_io;
 extern crate tokio_process;
+extern crate tokio_rustls;
 extern crate tokio_timer;
 extern crate tokio_uds;
 extern crate toml;
 extern crate walkdir;
+extern crate zstd;
 
 extern crate rain_core;
 
@@ -44,7 +46,7 @@ mod server;
 mod start;
 mod wrapped;
 
-use clap::{App, Arg, ArgMatches, SubCommand, value_t, value_t_or_exit};
+use clap::{App, Arg, ArgMatches, SubCommand, value_t, value_t_or_exit, values_t_or_exit};
 use nix::unistd::getpid;
 use std::collections::HashMap;
 use std::error::Error;
@@ -57,6 +59,7 @@ use serde_derive::Deserialize;
 use error_chain::bail;
 
 use rain_core::sys::{create_ready_file, get_hostname};
+use rain_core::types::Resources;
 use rain_core::{errors::*, utils::*};
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -64,6 +67,119 @@ const DEFAULT_SERVER_PORT: u16 = 7210;
 const DEFAULT_GOVERNOR_PORT: u16 = 0;
 const DEFAULT_HTTP_SERVER_PORT: u16 = 7222;
 
+/// Environment variable with the token sent to the server by governors and clients
+/// (and the shared token accepted by the server when no --auth-file is given)
+const TOKEN_ENV_VAR: &str = "RAIN_TOKEN";
+
+fn env_token() -> String {
+    ::std::env::var(TOKEN_ENV_VAR).unwrap_or_default()
+}
+
+/// TLS arguments; `listening` for the server and governors that accept connections.
+fn tls_args(listening: bool) -> Vec<Arg<'static, 'static>> {
+    let mut args = vec![
+        Arg::with_name("TLS_CA")
+            .long("--tls-ca")
+            .value_name("FILE")
+            .help("Use TLS, verify peers by the CA certificates in the PEM file")
+            .takes_value(true),
+        Arg::with_name("TLS_CERT")
+            .long("--tls-cert")
+            .value_name("FILE")
+            .help("Certificate chain (PEM) of this node")
+            .takes_value(true),
+        Arg::with_name("TLS_KEY")
+            .long("--tls-key")
+            .value_name("FILE")
+            .help("Private key (PEM) of the certificate")
+            .takes_value(true),
+    ];
+    if listening {
+        args.push(
+            Arg::with_name("TLS_VERIFY_CLIENTS")
+                .long("--tls-verify-clients")
+                .help("Accept only connections with a certificate signed by the CA"),
+        );
+    }
+    args
+}
+
+fn parse_tls_args(cmd_args: &ArgMatches, listening: bool) -> Option<common::TlsConfig> {
+    let ca = match cmd_args.value_of("TLS_CA") {
+        Some(ca) => ca,
+        None => {
+            if cmd_args.is_present("TLS_CERT")
+                || cmd_args.is_present("TLS_KEY")
+                || cmd_args.is_present("TLS_VERIFY_CLIENTS")
+            {
+                log::error!("TLS options need --tls-ca");
+                exit(1);
+            }
+            return None;
+        }
+    };
+    let cert = match (cmd_args.value_of("TLS_CERT"), cmd_args.value_of("TLS_KEY")) {
+        (Some(cert), Some(key)) => Some((Path::new(cert), Path::new(key))),
+        (None, None) if !listening => None,
+        _ => {
+            log::error!("TLS needs both --tls-cert and --tls-key");
+            exit(1);
+        }
+    };
+    let verify_clients = cmd_args.is_present("TLS_VERIFY_CLIENTS");
+    let tls = common::TlsConfig::new(Path::new(ca), cert, verify_clients).unwrap_or_else(|e| {
+        log::error!("TLS configuration failed: {}", e);
+        exit(1);
+    });
+    log::info!(
+        "TLS enabled, CA: {}, verification of clients: {}",
+        ca,
+        if verify_clients { "on" } else { "off" }
+    );
+    Some(tls)
+}
+
+/// Host part of "host:port" (without the brackets of an IPv6 address)
+fn server_host(server_address: &str) -> &str {
+    let host = server_address
+        .rsplitn(2, ':')
+        .nth(1)
+        .unwrap_or(server_address);
+    host.trim_matches(|c| c == '[' || c == ']')
+}
+
+/// Argument overriding the name of the server in its TLS certificate
+fn server_name_arg() -> Arg<'static, 'static> {
+    Arg::with_name("SERVER_NAME")
+        .long("--server-name")
+        .value_name("NAME")
+        .help("Name of the server in its TLS certificate (default = host of SERVER_ADDRESS)")
+        .takes_value(true)
+}
+
+/// Name that the certificate of the server has to be issued for, `--server-name`
+/// or the host of the server address. Certificates are matched by DNS names only,
+/// so a server given by its IP address needs `--server-name` when TLS is used.
+fn server_tls_name(
+    server_address: &str,
+    server_name: Option<&str>,
+    tls: Option<&common::TlsConfig>,
+) -> String {
+    if let Some(name) = server_name {
+        return name.to_string();
+    }
+    let host = server_host(server_address);
+    if tls.is_some() && host.parse::<IpAddr>().is_ok() {
+        log::error!(
+            "TLS needs a DNS name matching the server certificate, \
+             use --server-name with the IP address {}",
+            host
+        );
+        exit(1);
+    }
+    host.to_string()
+}
+
 fn parse_listen_arg(key: &str, args: &ArgMatches, default_port: u16) -> SocketAddr {
     if !args.is_present(key) {
         return SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), default_port);
@@ -78,6 +194,24 @@ fn parse_listen_arg(key: &str, args: &ArgMatches, default_port: u16) -> SocketAd
     })
 }
 
+/// Parse a memory size in bytes with an optional suffix K, M, G or T (powers of 1024)
+fn parse_memory_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (number, multiplier) = match value.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&value[..value.len() - 1], 1u64 << 10),
+        Some('M') => (&value[..value.len() - 1], 1u64 << 20),
+        Some('G') => (&value[..value.len() - 1], 1u64 << 30),
+        Some('T') => (&value[..value.len() - 1], 1u64 << 40),
+        _ => (value, 1u64),
+    };
+    number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|n| *n >= 0f64)
+        .map(|n| (n * multiplier as f64) as u64)
+}
+
 fn run_server(_global_args: &ArgMatches, cmd_args: &ArgMatches) {
     let listen_address = parse_listen_arg("LISTEN_ADDRESS", cmd_args, DEFAULT_SERVER_PORT);
     let http_listen_address =
@@ -116,12 +250,75 @@ fn run_server(_global_args: &ArgMatches, cmd_args: &ArgMatches) {
         log::info!("TESTING mode enabled");
     }
 
+    let scheduler_name = cmd_args
+        .value_of("SCHEDULER")
+        .unwrap_or(server::scheduler::SCHEDULER_NAMES[0]);
+    let fair_share = server::scheduler::FairShareMode::from_name(
+        cmd_args
+            .value_of("FAIR_SHARE")
+            .unwrap_or(server::scheduler::FAIR_SHARE_MODES[0]),
+    )
+    .unwrap_or_else(|e| {
+        log::error!("{}", e);
+        exit(1);
+    });
+    let scheduler =
+        server::scheduler::create_scheduler(scheduler_name, &log_dir.join("events.db"), fair_share)
+            .unwrap_or_else(|e| {
+                log::error!("{}", e);
+                exit(1);
+            });
+    log::info!(
+        "Scheduler: {}, fair sharing: {:?}",
+        scheduler.name(),
+        fair_share
+    );
+
+    let heartbeat_timeout = value_t_or_exit!(cmd_args, "HEARTBEAT_TIMEOUT", u64);
+    let heartbeat_timeout = if heartbeat_timeout > 0 {
+        log::info!("Heartbeat timeout: {} s", heartbeat_timeout);
+        Some(::std::time::Duration::from_secs(heartbeat_timeout))
+    } else {
+        log::info!("Heartbeats disabled");
+        None
+    };
+
+    let auth = match cmd_args.value_of("AUTH_FILE") {
+        Some(path) => {
+            let tokens = server::auth::AuthTokens::read_file(Path::new(path)).unwrap_or_else(|e| {
+                log::error!("Reading auth file {} failed: {}", path, e);
+                exit(1);
+            });
+            log::info!("Authentication enabled, tokens read from {}", path);
+            Some(tokens)
+        }
+        None => {
+            let token = env_token();
+            if token.is_empty() {
+                log::info!("Authentication disabled");
+                None
+            } else {
+                log::info!(
+                    "Authentication enabled, shared token from {}",
+                    TOKEN_ENV_VAR
+                );
+                Some(server::auth::AuthTokens::shared(&token))
+            }
+        }
+    };
+
     let state = server::state::StateRef::new(
         tokio_core.handle(),
         listen_address,
         http_listen_address,
         log_dir,
         test_mode,
+        cmd_args.is_present("RECOVER"),
+        scheduler,
+        cmd_args.is_present("REBALANCE"),
+        heartbeat_timeout,
+        auth,
+        parse_tls_args(cmd_args, true),
     );
     state.start();
 
@@ -177,7 +374,14 @@ struct ExecutorConfig {
 
 #[derive(Deserialize)]
 struct GovernorConfig {
+    #[serde(default)]
     executors: HashMap<String, ExecutorConfig>,
+    /// User-defined resources provided by the governor, e.g. `license_matlab = 4`
+    #[serde(default)]
+    resources: HashMap<String, u32>,
+    /// Labels announced to the server, e.g. `rack = "a"`
+    #[serde(default)]
+    labels: HashMap<String, String>,
 }
 
 impl GovernorConfig {
@@ -213,6 +417,12 @@ fn run_governor(_global_args: &ArgMatches, cmd_args: &ArgMatches) {
             Some(ref addr) => *addr,
         },
     };
+    let tls = parse_tls_args(cmd_args, true);
+    let server_name = server_tls_name(
+        &server_address,
+        cmd_args.value_of("SERVER_NAME"),
+        tls.as_ref(),
+    );
 
     let state = {
         let config = cmd_args.value_of("GOVERNOR_CONFIG").map(|path| {
@@ -253,6 +463,43 @@ fn run_governor(_global_args: &ArgMatches, cmd_args: &ArgMatches) {
         };
         assert!(cpus >= 0);
 
+        fn detect_memory() -> u64 {
+            log::debug!("Detecting size of memory");
+            match sys_info::mem_info() {
+                Ok(info) => info.total * 1024,
+                Err(e) => {
+                    log::error!(
+                        "Autodetection of memory failed ({:?}). Use --memory with a size.",
+                        e
+                    );
+                    exit(1);
+                }
+            }
+        }
+
+        let memory = match cmd_args.value_of("MEMORY") {
+            Some("detect") | None => detect_memory(),
+            Some(value) if value.starts_with('-') => {
+                let size = parse_memory_size(&value[1..]).unwrap_or_else(|| {
+                    log::error!("Invalid value of --memory: {}", value);
+                    exit(1);
+                });
+                let memory = detect_memory();
+                if memory <= size {
+                    log::error!(
+                        "{} bytes of memory detected and {} is subtracted via --memory. No memory left.",
+                        memory, size
+                    );
+                    exit(1);
+                }
+                memory - size
+            }
+            Some(value) => parse_memory_size(value).unwrap_or_else(|| {
+                log::error!("Invalid value of --memory: {}", value);
+                exit(1);
+            }),
+        };
+
         let work_dir = cmd_args
             .value_of("WORK_DIR")
             .map(PathBuf::from)
@@ -273,7 +520,58 @@ fn run_governor(_global_args: &ArgMatches, cmd_args: &ArgMatches) {
             exit(1);
         });
 
-        log::info!("Resources: {} cpus", cpus);
+        let named_resources = config
+            .as_ref()
+            .map(|c| c.resources.clone())
+            .unwrap_or_default();
+        if named_resources.contains_key("cpus") || named_resources.contains_key("memory") {
+            log::error!("Resources 'cpus' and 'memory' cannot be set in the config file, use --cpus and --memory");
+            exit(1);
+        }
+
+        let mut labels = config
+            .as_ref()
+            .map(|c| c.labels.clone())
+            .unwrap_or_default();
+        for label in cmd_args.values_of("LABEL").into_iter().flat_map(|v| v) {
+            let mut parts = label.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if !key.is_empty() => {
+                    labels.insert(key.to_string(), value.to_string());
+                }
+                _ => {
+                    log::error!("Invalid label '{}', expected KEY=VALUE", label);
+                    exit(1);
+                }
+            }
+        }
+
+        log::info!("Resources: {} cpus, {} bytes of memory", cpus, memory);
+
+        let memory_budget = cmd_args.value_of("MEMORY_BUDGET").map(|value| {
+            parse_memory_size(value).unwrap_or_else(|| {
+                log::error!("Invalid value of --memory-budget: {}", value);
+                exit(1);
+            }) as usize
+        });
+        if let Some(budget) = memory_budget {
+            log::info!("Memory budget of data objects: {} bytes", budget);
+        }
+        let disk_quota = cmd_args.value_of("DISK_QUOTA").map(|value| {
+            parse_memory_size(value).unwrap_or_else(|| {
+                log::error!("Invalid value of --disk-quota: {}", value);
+                exit(1);
+            }) as usize
+        });
+        if let Some(quota) = disk_quota {
+            log::info!("Disk quota of data objects: {} bytes", quota);
+        }
+        for (name, amount) in &named_resources {
+            log::info!("Resource {}: {}", name, amount);
+        }
+        for (key, value) in &labels {
+            log::info!("Label {}={}", key, value);
+        }
         log::info!("Working directory: {:?}", work_dir);
         log::info!(
             "Server address {} was resolved as {}",
@@ -307,17 +605,33 @@ fn run_governor(_global_args: &ArgMatches, cmd_args: &ArgMatches) {
             tokio_core.handle(),
             work_dir,
             log_dir,
-            cpus as u32,
+            Resources {
+                cpus: cpus as u32,
+                memory,
+                named: named_resources,
+            },
+            labels,
             // Python executor
             executors,
+            tls,
+            memory_budget,
+            disk_quota,
         )
     };
 
-    state.start(server_addr, listen_address, ready_file);
+    state.start(
+        server_addr,
+        server_name,
+        listen_address,
+        ready_file,
+        env_token(),
+    );
 
     loop {
         tokio_core.turn(None);
-        state.turn();
+        if !state.turn() {
+            break;
+        }
     }
 }
 
@@ -454,6 +768,134 @@ fn init_log() {
     }
 }
 
+fn run_simulator(_global_args: &ArgMatches, cmd_args: &ArgMatches) {
+    let events_db = PathBuf::from(cmd_args.value_of("EVENTS_DB").unwrap());
+    let cluster = server::simulator::ClusterSpec {
+        governors: value_t_or_exit!(cmd_args, "GOVERNORS", usize),
+        cpus: value_t_or_exit!(cmd_args, "CPUS", u32),
+        memory: cmd_args.value_of("MEMORY").map(|value| {
+            parse_memory_size(value).unwrap_or_else(|| {
+                log::error!("Invalid memory size: {}", value);
+                exit(1);
+            })
+        }),
+        bandwidth: value_t_or_exit!(cmd_args, "BANDWIDTH", f64) * (1u64 << 20) as f64,
+    };
+    if cluster.governors == 0 || cluster.cpus == 0 || cluster.bandwidth <= 0f64 {
+        log::error!(
+            "The virtual cluster needs at least one governor, cpu and a positive bandwidth"
+        );
+        exit(1);
+    }
+
+    let mut sessions = server::simulator::load_recorded_sessions(&events_db).unwrap_or_else(|e| {
+        log::error!("Cannot load sessions: {}", e);
+        exit(1);
+    });
+    if cmd_args.is_present("SESSION") {
+        let ids = values_t_or_exit!(cmd_args, "SESSION", i32);
+        sessions.retain(|s| ids.contains(&s.id));
+    }
+    if sessions.is_empty() {
+        log::error!("No sessions with tasks found in {}", events_db.display());
+        exit(1);
+    }
+
+    let scheduler_names: Vec<&str> = cmd_args
+        .values_of("SCHEDULER")
+        .map(|v| v.collect())
+        .unwrap_or_else(|| server::scheduler::SCHEDULER_NAMES.to_vec());
+
+    let print_row = |session: &str, scheduler: &str, r: &server::simulator::SimulationReport| {
+        println!(
+            "{:>8} {:>8}  {:<12} {:>12.2} {:>14.1} {:>11.1}",
+            session,
+            r.tasks,
+            scheduler,
+            r.makespan,
+            r.transferred as f64 / (1u64 << 20) as f64,
+            r.utilization() * 100f64
+        );
+    };
+    println!(
+        "{:>8} {:>8}  {:<12} {:>12} {:>14} {:>11}",
+        "SESSION", "TASKS", "SCHEDULER", "MAKESPAN [s]", "TRANSFER [MiB]", "UTILIZ. [%]"
+    );
+    for name in scheduler_names {
+        // Sessions are simulated one by one, so there is nothing to share
+        let fair_share = server::scheduler::FairShareMode::Off;
+        let mut scheduler = server::scheduler::create_scheduler(name, &events_db, fair_share)
+            .unwrap_or_else(|e| {
+                log::error!("{}", e);
+                exit(1);
+            });
+        let mut total = server::simulator::SimulationReport::default();
+        for session in &sessions {
+            match server::simulator::simulate(session, &cluster, &mut *scheduler) {
+                Ok(report) => {
+                    print_row(&session.id.to_string(), name, &report);
+                    total.add(&report);
+                }
+                Err(e) => log::warn!("Session {} skipped: {}", session.id, e),
+            }
+        }
+        print_row("total", name, &total);
+    }
+}
+
+fn run_drain(_global_args: &ArgMatches, cmd_args: &ArgMatches) {
+    use capnp_rpc::rpc_twoparty_capnp;
+    use futures::Future;
+
+    let mut server_address = cmd_args.value_of("SERVER_ADDRESS").unwrap().to_string();
+    if !server_address.contains(':') {
+        server_address = format!("{}:{}", server_address, DEFAULT_SERVER_PORT);
+    }
+    let server_addr = server_address
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .unwrap_or_else(|| {
+            log::error!("Cannot resolve server address {}", server_address);
+            exit(1);
+        });
+    let governor_id = value_t_or_exit!(cmd_args, "GOVERNOR_ID", SocketAddr);
+    let tls = parse_tls_args(cmd_args, false);
+    let server_name = server_tls_name(
+        &server_address,
+        cmd_args.value_of("SERVER_NAME"),
+        tls.as_ref(),
+    );
+
+    let mut tokio_core = tokio_core::reactor::Core::new().unwrap();
+    let handle = tokio_core.handle();
+    let connect = tokio_core::net::TcpStream::connect(&server_addr, &handle)
+        .and_then(move |stream| common::connect_stream(tls.as_ref(), &server_name, stream));
+    let stream = tokio_core.run(connect).unwrap_or_else(|e| {
+        log::error!("Cannot connect to server {}: {}", server_addr, e);
+        exit(1);
+    });
+    let mut rpc_system = common::new_rpc_system(stream, None);
+    let bootstrap: rain_core::server_capnp::server_bootstrap::Client =
+        rpc_system.bootstrap(rpc_twoparty_capnp::Side::Server);
+    handle.spawn(rpc_system.map_err(|e| log::error!("RPC error: {:?}", e)));
+
+    let mut req = bootstrap.register_as_client_request();
+    req.get().set_version(rain_core::CLIENT_PROTOCOL_VERSION);
+    req.get().set_token(&env_token());
+    let drain = req.send().promise.and_then(move |response| {
+        let service = capnp_rpc::pry!(capnp_rpc::pry!(response.get()).get_service());
+        let mut req = service.drain_governor_request();
+        governor_id.to_capnp(&mut req.get().init_governor_id());
+        ::capnp::capability::Promise::from_future(req.send().promise.map(|_| ()))
+    });
+    if let Err(e) = tokio_core.run(drain) {
+        log::error!("Draining governor {} failed: {}", governor_id, e);
+        exit(1);
+    }
+    println!("Governor {} is draining", governor_id);
+}
+
 fn main() {
     init_log();
 
@@ -465,6 +907,11 @@ fn main() {
         .subcommand( // ---- SERVER ----
             SubCommand::with_name("server")
                 .about("Rain server")
+                .arg(Arg::with_name("AUTH_FILE")
+                    .long("--auth-file")
+                    .value_name("FILE")
+                    .help("Accept only clients and governors with a token from the file (lines 'NAME TOKEN')")
+                    .takes_value(true))
                 .arg(Arg::with_name("LISTEN_ADDRESS")
                     .short("l")
                     .long("--listen")
@@ -482,7 +929,31 @@ fn main() {
                 .arg(Arg::with_name("READY_FILE")
                     .long("--ready-file")
                     .help("Create a file when server is initialized and ready to accept connections")
-                    .takes_value(true)))
+                    .takes_value(true))
+                .arg(Arg::with_name("RECOVER")
+                    .long("--recover")
+                    .help("Recover unclosed sessions from the event log in the logging directory and store client data for a later recovery"))
+                .arg(Arg::with_name("SCHEDULER")
+                    .long("--scheduler")
+                    .value_name("NAME")
+                    .possible_values(&server::scheduler::SCHEDULER_NAMES)
+                    .help(format!("Task scheduler (default {})", server::scheduler::SCHEDULER_NAMES[0]).as_str())
+                    .takes_value(true))
+                .arg(Arg::with_name("FAIR_SHARE")
+                    .long("--fair-share")
+                    .value_name("MODE")
+                    .possible_values(&server::scheduler::FAIR_SHARE_MODES)
+                    .help("Share cpus fairly between sessions or clients according to session weights (default off)")
+                    .takes_value(true))
+                .arg(Arg::with_name("REBALANCE")
+                    .long("--rebalance")
+                    .help("Move tasks waiting on busy governors to idle governors"))
+                .arg(Arg::with_name("HEARTBEAT_TIMEOUT")
+                    .long("--heartbeat-timeout")
+                    .value_name("SECONDS")
+                    .help("Declare a governor lost when it does not answer heartbeats for this time, 0 disables heartbeats")
+                    .default_value("30"))
+                .args(&tls_args(true)))
         .subcommand( // ---- GOVERNOR ----
             SubCommand::with_name("governor")
                 .about("Rain governor")
@@ -500,6 +971,28 @@ fn main() {
                     .help("Number of cpus or 'detect' (default = detect)")
                     .value_name("N")
                     .default_value("detect"))
+                .arg(Arg::with_name("MEMORY")
+                    .long("--memory")
+                    .help("Size of memory (e.g. 2048M, 64G; a negative value is subtracted from the detected size) or 'detect' (default = detect)")
+                    .value_name("SIZE")
+                    .default_value("detect"))
+                .arg(Arg::with_name("MEMORY_BUDGET")
+                    .long("--memory-budget")
+                    .help("Memory for data objects (e.g. 4G); the least recently used objects above it are moved to the working directory (default = no limit)")
+                    .value_name("SIZE")
+                    .takes_value(true))
+                .arg(Arg::with_name("DISK_QUOTA")
+                    .long("--disk-quota")
+                    .help("Disk space for data objects in the working directory (e.g. 100G); objects are evicted near it (default = no limit)")
+                    .value_name("SIZE")
+                    .takes_value(true))
+                .arg(Arg::with_name("LABEL")
+                    .long("--label")
+                    .help("Label announced to the server for task placement (e.g. --label rack=a), can be used multiple times")
+                    .value_name("KEY=VALUE")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1))
                 .arg(Arg::with_name("GOVERNOR_CONFIG")
                     .long("--config")
                     .help("Path to configuration file")
@@ -517,7 +1010,9 @@ fn main() {
                     .long("--ready-file")
                     .value_name("DIR")
                     .help("Create a file when governor is initialized and connected to the server")
-                    .takes_value(true)))
+                    .takes_value(true))
+                .args(&tls_args(true))
+                .arg(server_name_arg()))
         .subcommand( // ---- START ----
             SubCommand::with_name("start")
                 .about("Start server & governors at once")
@@ -575,15 +1070,92 @@ fn main() {
                     .long("--logdir")
                     .help("Logging directory for governors & server (default /tmp/rain-logs/run-$HOSTANE-$PID)")
                     .takes_value(true)))
+        .subcommand( // ---- DRAIN ----
+            SubCommand::with_name("drain")
+                .about("Drain a governor and take it out of service")
+                .arg(Arg::with_name("SERVER_ADDRESS")
+                    .help("Address of the server (default port is 7210)")
+                    .required(true))
+                .arg(Arg::with_name("GOVERNOR_ID")
+                    .help("Id of the governor (its address:port as shown in the dashboard)")
+                    .required(true))
+                .args(&tls_args(false))
+                .arg(server_name_arg()))
+        .subcommand( // ---- SIMULATE ----
+            SubCommand::with_name("simulate")
+                .about("Replay sessions recorded in a server event log on a virtual cluster")
+                .arg(Arg::with_name("EVENTS_DB")
+                    .help("Event database of a server (events.db in its logging directory)")
+                    .required(true))
+                .arg(Arg::with_name("SCHEDULER")
+                    .long("--scheduler")
+                    .value_name("NAME")
+                    .possible_values(&server::scheduler::SCHEDULER_NAMES)
+                    .help("Scheduler to simulate, can be used multiple times (default all)")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1))
+                .arg(Arg::with_name("SESSION")
+                    .long("--session")
+                    .value_name("ID")
+                    .help("Simulate only the given session, can be used multiple times (default all)")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1))
+                .arg(Arg::with_name("GOVERNORS")
+                    .long("--governors")
+                    .value_name("N")
+                    .help("Number of virtual governors")
+                    .default_value("4"))
+                .arg(Arg::with_name("CPUS")
+                    .long("--cpus")
+                    .value_name("N")
+                    .help("Number of cpus of a virtual governor")
+                    .default_value("8"))
+                .arg(Arg::with_name("MEMORY")
+                    .long("--memory")
+                    .value_name("SIZE")
+                    .help("Memory of a virtual governor (e.g. 64G); memory requirements of tasks are ignored when not set")
+                    .takes_value(true))
+                .arg(Arg::with_name("BANDWIDTH")
+                    .long("--bandwidth")
+                    .value_name("MiB/s")
+                    .help("Network bandwidth of a virtual governor in MiB/s")
+                    .default_value("100")))
         .get_matches();
 
     match args.subcommand() {
         ("server", Some(cmd_args)) => run_server(&args, cmd_args),
         ("governor", Some(cmd_args)) => run_governor(&args, cmd_args),
         ("start", Some(cmd_args)) => run_starter(&args, cmd_args),
+        ("drain", Some(cmd_args)) => run_drain(&args, cmd_args),
+        ("simulate", Some(cmd_args)) => run_simulator(&args, cmd_args),
         _ => {
             log::error!("No subcommand provided.");
             ::std::process::exit(1);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_host_of_address() {
+        assert_eq!(server_host("myserver:7210"), "myserver");
+        assert_eq!(server_host("10.0.0.1:7210"), "10.0.0.1");
+        assert_eq!(server_host("[::1]:7210"), "::1");
+        assert!(server_host("[::1]:7210").parse::<IpAddr>().is_ok());
+    }
+
+    #[test]
+    fn server_tls_name_of_address() {
+        assert_eq!(server_tls_name("myserver:7210", None, None), "myserver");
+        assert_eq!(server_tls_name("10.0.0.1:7210", None, None), "10.0.0.1");
+        assert_eq!(
+            server_tls_name("10.0.0.1:7210", Some("myserver"), None),
+            "myserver"
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use rain_core::errors::*;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use error_chain::bail;
+
+/// Name of the shared token given by the environment variable
+const SHARED_TOKEN_NAME: &str = "shared";
+
+/// Tokens accepted from clients and governors at the registration.
+///
+/// Each token has a name (e.g. of a user) used in the log.
+#[derive(Default, Clone, Debug)]
+pub struct AuthTokens {
+    /// Token -> name
+    tokens: HashMap<String, String>,
+}
+
+impl AuthTokens {
+    /// Parse tokens, one per line as `NAME TOKEN` or just `TOKEN`.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut tokens = HashMap::new();
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (name, token) = match fields.len() {
+                1 => (SHARED_TOKEN_NAME, fields[0]),
+                2 => (fields[0], fields[1]),
+                _ => bail!("Invalid token on line {}, expected 'NAME TOKEN'", i + 1),
+            };
+            tokens.insert(token.to_string(), name.to_string());
+        }
+        if tokens.is_empty() {
+            bail!("No tokens found");
+        }
+        Ok(AuthTokens { tokens })
+    }
+
+    pub fn read_file(path: &Path) -> Result<Self> {
+        let mut content = String::new();
+        ::std::fs::File::open(path)?.read_to_string(&mut content)?;
+        Self::parse(&content)
+    }
+
+    /// A single shared token
+    pub fn shared(token: &str) -> Self {
+        let mut tokens = HashMap::new();
+        tokens.insert(token.to_string(), SHARED_TOKEN_NAME.to_string());
+        AuthTokens { tokens }
+    }
+
+    /// Return the name of the token if it is valid.
+    pub fn check(&self, token: &str) -> Option<&str> {
+        // Compare all tokens in constant time to not leak their prefixes
+        let mut found = None;
+        for (t, name) in &self.tokens {
+            if constant_time_eq(t.as_bytes(), token.as_bytes()) {
+                found = Some(name.as_str());
+            }
+        }
+        found
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter()
+        .zip(b.iter())
+        .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+        == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auth_tokens() {
+        let tokens = AuthTokens::parse("# users\nalice secret1\n\nsecret2\n").unwrap();
+        assert_eq!(tokens.check("secret1"), Some("alice"));
+        assert_eq!(tokens.check("secret2"), Some(SHARED_TOKEN_NAME));
+        assert_eq!(tokens.check("secret"), None);
+        assert_eq!(tokens.check(""), None);
+        assert!(AuthTokens::parse("a b c").is_err());
+        assert!(AuthTokens::parse("# nothing").is_err());
+    }
+}
@@ -9,6 +9,8 @@ use wrapped::WrappedRcRefCell;
 pub struct Client {
     pub(in super::super) id: ClientId,
     pub(in super::super) sessions: RcSet<SessionRef>,
+    /// Name of the authentication token of the client, `None` without authentication
+    pub(in super::super) user: Option<String>,
 }
 
 pub type ClientRef = WrappedRcRefCell<Client>;
@@ -19,6 +21,7 @@ impl ClientRef {
         ClientRef::wrap(Client {
             id: address.clone(),
             sessions: Default::default(),
+            user: None,
         })
     }
 
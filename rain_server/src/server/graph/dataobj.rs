@@ -4,6 +4,7 @@ use rain_core::{errors::*, types::*, utils::*};
 use error_chain::bail;
 
 use super::{GovernorRef, SessionRef, TaskRef, TaskState};
+use server::scheduler::SchedulerExtra;
 use wrapped::WrappedRcRefCell;
 
 #[derive(Debug)]
@@ -46,6 +47,9 @@ pub struct DataObject {
     /// Optinal *final* data when submitted from client or downloaded
     /// by the server (for any reason thinkable).
     pub(in super::super) data: Option<Vec<u8>>,
+
+    /// Scheduler-specific data
+    pub(in super::super) scheduler_extra: SchedulerExtra,
 }
 
 impl DataObject {
@@ -94,6 +98,57 @@ impl DataObject {
         self.client_keep || !self.need_by.is_empty()
     }
 
+    /// Can the copy on the governor be dropped to free its disk space?
+    /// Copies used by tasks scheduled on the governor are kept. A needed object must
+    /// have another copy (on another governor or on the server); an object that is not
+    /// needed anymore can be recomputed by its producer if it is needed again.
+    pub fn can_evict_from(&self, governor: &GovernorRef) -> bool {
+        if self.state != DataObjectState::Finished || !self.located.contains(governor) {
+            return false;
+        }
+        if self
+            .need_by
+            .iter()
+            .any(|t| t.get().scheduled.as_ref() == Some(governor))
+        {
+            return false;
+        }
+        self.data.is_some()
+            || self.located.len() > 1
+            || (!self.is_needed() && self.producer.is_some())
+    }
+
+    /// The size counted in the kept bytes of the session.
+    pub fn kept_size(&self) -> u64 {
+        if self.client_keep && self.state == DataObjectState::Finished {
+            self.info.size.unwrap_or(0) as u64
+        } else {
+            0
+        }
+    }
+
+    /// Set the state, updating the kept bytes of the session.
+    pub fn set_state(&mut self, state: DataObjectState) {
+        let kept_size = self.kept_size();
+        self.state = state;
+        self.update_kept_bytes(kept_size);
+    }
+
+    /// Set the keep flag, updating the kept bytes of the session.
+    pub fn set_client_keep(&mut self, client_keep: bool) {
+        let kept_size = self.kept_size();
+        self.client_keep = client_keep;
+        self.update_kept_bytes(kept_size);
+    }
+
+    fn update_kept_bytes(&self, old_kept_size: u64) {
+        let kept_size = self.kept_size();
+        if kept_size != old_kept_size {
+            let mut s = self.session.get_mut();
+            s.kept_bytes = s.kept_bytes + kept_size - old_kept_size;
+        }
+    }
+
     #[inline]
     pub fn id(&self) -> DataObjectId {
         self.spec.id
@@ -130,13 +185,18 @@ impl DataObjectRef {
             client_keep: client_keep,
             finish_hooks: Vec::new(),
             data: data,
+            scheduler_extra: Default::default(),
         });
 
         if size.is_some() {
             obj.get_mut().info.size = size;
         }
         // add to session
-        session.get_mut().objects.insert(obj.clone());
+        {
+            let mut s = session.get_mut();
+            s.objects.insert(obj.clone());
+            s.kept_bytes += obj.get().kept_size();
+        }
         obj
     }
 
@@ -170,7 +230,12 @@ impl DataObjectRef {
             "Can only remove objects without a producer."
         );
         // remove from owner
-        assert!(inner.session.get_mut().objects.remove(&self));
+        let kept_size = inner.kept_size();
+        {
+            let mut s = inner.session.get_mut();
+            assert!(s.objects.remove(&self));
+            s.kept_bytes -= kept_size;
+        }
         // clear finish_hooks
         inner.finish_hooks.clear();
     }
@@ -296,3 +361,93 @@ impl ::std::fmt::Debug for DataObjectRef {
         write!(f, "DataObjectRef {}", self.get().spec.id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use server::graph::ClientRef;
+
+    fn new_session() -> SessionRef {
+        let client = ClientRef::new("127.0.0.1:1234".parse().unwrap());
+        let spec = SessionSpec {
+            name: "test".to_string(),
+            ..Default::default()
+        };
+        SessionRef::new(1, &client, spec)
+    }
+
+    fn new_governor(address: &str) -> GovernorRef {
+        GovernorRef::new(
+            address.parse().unwrap(),
+            None,
+            Resources::empty(),
+            Default::default(),
+        )
+    }
+
+    /// A finished object produced by a task and located on the governor
+    fn new_object(session: &SessionRef, id: i32, governor: &GovernorRef) -> DataObjectRef {
+        let spec = ObjectSpec {
+            id: DataObjectId::new(session.get_id(), id),
+            ..Default::default()
+        };
+        let oref = DataObjectRef::new(session, spec, false, None);
+        let task_spec = TaskSpec {
+            id: TaskId::new(session.get_id(), id + 100),
+            outputs: vec![oref.get().id()],
+            ..Default::default()
+        };
+        let producer = TaskRef::new(session, task_spec, Vec::new(), vec![oref.clone()]).unwrap();
+        producer.get_mut().state = TaskState::Finished;
+        {
+            let mut o = oref.get_mut();
+            o.state = DataObjectState::Finished;
+            o.assigned.insert(governor.clone());
+            o.located.insert(governor.clone());
+        }
+        oref
+    }
+
+    #[test]
+    fn eviction() {
+        let session = new_session();
+        let w1 = new_governor("10.0.0.1:7211");
+        let w2 = new_governor("10.0.0.2:7211");
+
+        // Not needed anymore, the producer can recompute it
+        let o = new_object(&session, 1, &w1);
+        assert!(o.get().can_evict_from(&w1));
+        assert!(!o.get().can_evict_from(&w2));
+
+        // The only copy of a kept object
+        o.get_mut().client_keep = true;
+        assert!(!o.get().can_evict_from(&w1));
+
+        // Another copy exists
+        o.get_mut().located.insert(w2.clone());
+        assert!(o.get().can_evict_from(&w1));
+
+        // Used by a task scheduled on the governor
+        let spec = TaskSpec {
+            id: TaskId::new(session.get_id(), 10),
+            inputs: vec![TaskSpecInput {
+                id: o.get().id(),
+                label: Default::default(),
+            }],
+            ..Default::default()
+        };
+        let consumer = TaskRef::new(&session, spec, vec![o.clone()], Vec::new()).unwrap();
+        consumer.get_mut().scheduled = Some(w1.clone());
+        assert!(!o.get().can_evict_from(&w1));
+        assert!(o.get().can_evict_from(&w2));
+
+        // A needed object uploaded by the client is kept by the server
+        let spec = ObjectSpec {
+            id: DataObjectId::new(session.get_id(), 2),
+            ..Default::default()
+        };
+        let uploaded = DataObjectRef::new(&session, spec, true, Some(vec![1, 2, 3]));
+        uploaded.get_mut().located.insert(w1.clone());
+        assert!(uploaded.get().can_evict_from(&w1));
+    }
+}
@@ -1,14 +1,18 @@
+use std::collections::HashMap;
 use std::fmt;
 use std::net::SocketAddr;
 use std::rc::Rc;
+use std::time::Instant;
 use error_chain::bail;
 
-use common::new_rpc_system;
+use common::{connect_stream, new_rpc_system, GOVERNOR_TLS_NAME};
 use futures::Future;
+use rain_core::logging::events::MonitoringEvent;
 use rain_core::{errors::*, types::*, utils::*};
 
 use super::super::state::StateRef;
 use super::{DataObjectRef, TaskRef};
+use server::scheduler::SchedulerExtra;
 use wrapped::WrappedRcRefCell;
 
 pub struct Governor {
@@ -25,9 +29,11 @@ pub struct Governor {
     /// `assigned_tasks`, subset of `scheduled_tasks`.
     pub(in super::super) scheduled_ready_tasks: RcSet<TaskRef>,
 
+    /// Scheduled tasks with anti-affinity constraints. Subset of `scheduled_tasks`.
+    pub(in super::super) avoiding_tasks: RcSet<TaskRef>,
+
     // The sum of resources of scheduled tasks that may run (or are running)
-    // (TODO: Generalize for Resource not only cpus)
-    pub(in super::super) active_resources: u32,
+    pub(in super::super) active_resources: Resources,
 
     /// Obects fully located on the governor.
     pub(in super::super) located_objects: RcSet<DataObjectRef>,
@@ -46,6 +52,25 @@ pub struct Governor {
         Option<AsyncInitWrapper<::rain_core::governor_capnp::governor_bootstrap::Client>>,
 
     pub(in super::super) resources: Resources,
+
+    /// Labels announced by the governor (e.g. `rack=a`)
+    pub(in super::super) labels: HashMap<String, String>,
+
+    /// The governor is being drained; it gets no new tasks and it is decommissioned
+    /// when its running tasks finish and its needed objects are copied elsewhere
+    pub(in super::super) draining: bool,
+
+    /// The governor is near its disk quota; schedulers steer new outputs elsewhere
+    pub(in super::super) disk_pressure: bool,
+
+    /// Time of the last answered heartbeat (or of the registration)
+    pub(in super::super) last_heartbeat: Instant,
+
+    /// The last monitoring event sent by the governor
+    pub(in super::super) last_monitoring: Option<MonitoringEvent>,
+
+    /// Scheduler-specific data
+    pub(in super::super) scheduler_extra: SchedulerExtra,
 }
 
 pub type GovernorRef = WrappedRcRefCell<Governor>;
@@ -71,10 +96,14 @@ impl Governor {
         let governor_ref = governor_ref.clone();
         let state_ref2 = state_ref.clone();
         let state = state_ref.get();
+        let tls = state.tls().cloned();
         Box::new(
             ::tokio_core::net::TcpStream::connect(&self.id, state.handle())
-                .map(move |stream| {
+                .and_then(move |stream| {
                     stream.set_nodelay(true).unwrap();
+                    connect_stream(tls.as_ref(), GOVERNOR_TLS_NAME, stream)
+                })
+                .map(move |stream| {
                     let mut rpc_system = new_rpc_system(stream, None);
                     let bootstrap: ::rain_core::governor_capnp::governor_bootstrap::Client =
                         rpc_system.bootstrap(::capnp_rpc::rpc_twoparty_capnp::Side::Server);
@@ -101,19 +130,27 @@ impl GovernorRef {
         address: SocketAddr,
         control: Option<::rain_core::governor_capnp::governor_control::Client>,
         resources: Resources,
+        labels: HashMap<String, String>,
     ) -> Self {
         GovernorRef::wrap(Governor {
             id: address,
             assigned_tasks: Default::default(),
             scheduled_tasks: Default::default(),
             scheduled_ready_tasks: Default::default(),
+            avoiding_tasks: Default::default(),
             located_objects: Default::default(),
             assigned_objects: Default::default(),
             scheduled_objects: Default::default(),
             control: control,
-            active_resources: 0,
+            active_resources: Resources::empty(),
             resources: resources,
             data_connection: None,
+            labels,
+            draining: false,
+            disk_pressure: false,
+            last_heartbeat: Instant::now(),
+            last_monitoring: None,
+            scheduler_extra: Default::default(),
         })
     }
 
@@ -129,9 +166,9 @@ impl ConsistencyCheck for GovernorRef {
     fn check_consistency(&self) -> Result<()> {
         let s = self.get();
 
-        if s.scheduled_tasks.is_empty() && s.active_resources != 0 {
+        if s.scheduled_tasks.is_empty() && s.active_resources != Resources::empty() {
             bail!(
-                "Invalid active resources: active_resources = {}",
+                "Invalid active resources: active_resources = {:?}",
                 s.active_resources
             );
         }
@@ -162,6 +199,11 @@ impl ConsistencyCheck for GovernorRef {
                 bail!("scheduled task ref {:?} inconsistency in {:?}", tref, s)
             }
         }
+        for tref in s.avoiding_tasks.iter() {
+            if !s.scheduled_tasks.contains(tref) || !tref.get().has_anti_affinity() {
+                bail!("avoiding task ref {:?} inconsistency in {:?}", tref, s)
+            }
+        }
         for tref in s.scheduled_ready_tasks.iter() {
             if tref.get().scheduled != Some(self.clone()) {
                 bail!(
@@ -189,6 +231,7 @@ impl fmt::Debug for Governor {
             .field("located", &self.located_objects)
             .field("assigned", &self.assigned_objects)
             .field("resources", &self.resources)
+            .field("labels", &self.labels)
             .finish()
     }
 }
@@ -27,6 +27,12 @@ impl Graph {
         self.session_id_counter += 1;
         self.session_id_counter
     }
+
+    /// Is this very governor registered? A governor that was removed (e.g. after missed
+    /// heartbeats) and re-registered at the same address is a different `GovernorRef`.
+    pub fn is_registered(&self, governor: &GovernorRef) -> bool {
+        self.governors.get(&governor.get_id()) == Some(governor)
+    }
 }
 
 #[cfg(test)]
@@ -51,13 +57,24 @@ mod tests {
             GovernorRef::new(
                 format!("0.0.0.{}:67", wi + 1).parse().unwrap(),
                 None,
-                Resources { cpus: 8 },
+                Resources {
+                    cpus: 8,
+                    ..Default::default()
+                },
+                Default::default(),
             );
         }
         for ci in 0..clients {
             let c = ClientRef::new(format!("0.0.0.{}:42", ci + 1).parse().unwrap());
             for si in 0..sessions {
-                let s = SessionRef::new(si as i32, &c, SessionSpec { name: "test".to_string(), user: Default::default() });
+                let s = SessionRef::new(
+                    si as i32,
+                    &c,
+                    SessionSpec {
+                        name: "test".to_string(),
+                        ..Default::default()
+                    },
+                );
                 let mut objs = Vec::new();
 
                 for oi in 0..objects {
@@ -67,6 +84,8 @@ mod tests {
                         user: Default::default(),
                         data_type: DataType::Blob,
                         content_type: "".into(),
+                        compression: None,
+                        replicas: None,
                     };
 
                     let o = DataObjectRef::new(&s, spec, false, None);
@@ -95,9 +114,16 @@ mod tests {
                         outputs: outputs,
                         task_type: "TType".to_string(),
                         name: "".to_string(),
-                        resources: Resources { cpus: 1 },
+                        resources: Resources {
+                            cpus: 1,
+                            ..Default::default()
+                        },
                         config: None,
                         user: Default::default(),
+                        retry: None,
+                        timeout: None,
+                        placement: None,
+                        priority: 0,
                     };
 
                     TaskRef::new(&s, spec, input_objs, output_objs).unwrap();
@@ -108,6 +134,26 @@ mod tests {
         g
     }
 
+    #[test]
+    fn reregistered_governor() {
+        let mut g = Graph::new(1);
+        let resources = Resources {
+            cpus: 1,
+            ..Default::default()
+        };
+        let addr = "0.0.0.1:67".parse().unwrap();
+        let old = GovernorRef::new(addr, None, resources.clone(), Default::default());
+        g.governors.insert(addr, old.clone());
+        assert!(g.is_registered(&old));
+
+        // Missed heartbeats remove the governor, then it connects again
+        g.governors.remove(&addr);
+        let new = GovernorRef::new(addr, None, resources, Default::default());
+        g.governors.insert(addr, new.clone());
+        assert!(!g.is_registered(&old));
+        assert!(g.is_registered(&new));
+    }
+
     #[test]
     #[ignore]
     fn graph_create_delete() {
@@ -9,5 +9,5 @@ pub use self::client::{Client, ClientRef};
 pub use self::dataobj::{DataObject, DataObjectRef, DataObjectState};
 pub use self::governor::{Governor, GovernorRef};
 pub use self::graph::Graph;
-pub use self::session::{Session, SessionRef};
+pub use self::session::{new_session_token, Session, SessionRef};
 pub use self::task::{Task, TaskRef, TaskState};
@@ -1,6 +1,8 @@
 use futures::unsync::oneshot::Receiver;
 use rain_core::{errors::*, types::*, utils::*};
 use std::fmt;
+use std::io::Read;
+use std::time::Instant;
 use error_chain::bail;
 
 use super::{ClientRef, DataObjectRef, DataObjectState, TaskRef, TaskState};
@@ -33,6 +35,21 @@ pub struct Session {
     pub(in super::super) finish_hooks: Vec<FinishHook>,
 
     pub(in super::super) spec: SessionSpec,
+
+    /// Secret for reattaching the session by another client,
+    /// present for sessions that survive their client
+    pub(in super::super) token: Option<String>,
+
+    /// Authenticated user that created the session; only this user may attach
+    /// the session after a server restart
+    pub(in super::super) user: Option<String>,
+
+    /// Time when the client of the session disconnected
+    pub(in super::super) detached_since: Option<Instant>,
+
+    /// Total size of the finished objects kept by the client,
+    /// see `DataObject::kept_size`
+    pub(in super::super) kept_bytes: u64,
 }
 
 pub type SessionRef = WrappedRcRefCell<Session>;
@@ -47,6 +64,22 @@ impl Session {
     pub fn is_failed(&self) -> bool {
         self.error.is_some()
     }
+
+    /// The highest task or object id used in the session
+    pub fn max_id(&self) -> Id {
+        self.tasks
+            .iter()
+            .map(|t| t.get().id().get_id())
+            .chain(self.objects.iter().map(|o| o.get().id().get_id()))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Total size of the finished objects kept by the client
+    #[inline]
+    pub fn kept_bytes(&self) -> u64 {
+        self.kept_bytes
+    }
 }
 
 impl Session {
@@ -73,6 +106,13 @@ impl Session {
     }
 }
 
+/// Generate a random token for reattaching a session.
+pub fn new_session_token() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    ::std::fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
 impl SessionRef {
     /// Create new session object and link it to the owning client.
     pub fn new(id: SessionId, client: &ClientRef, spec: SessionSpec) -> Self {
@@ -85,6 +125,10 @@ impl SessionRef {
             unfinished_tasks: 0,
             finish_hooks: Default::default(),
             error: None,
+            token: None,
+            user: client.get().user.clone(),
+            detached_since: None,
+            kept_bytes: 0,
         });
         // add to client
         client.get_mut().sessions.insert(s.clone());
@@ -128,6 +172,10 @@ impl ConsistencyCheck for SessionRef {
         if !s.client.get().sessions.contains(self) {
             bail!("owning client does not contain {:?}", s);
         }
+        let kept_bytes: u64 = s.objects.iter().map(|o| o.get().kept_size()).sum();
+        if kept_bytes != s.kept_bytes {
+            bail!("kept bytes {} inconsistency in {:?}", kept_bytes, s);
+        }
         // finished?
         if !s.finish_hooks.is_empty()
             && s.tasks
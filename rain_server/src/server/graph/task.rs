@@ -2,9 +2,11 @@ use futures::unsync::oneshot;
 pub use rain_core::common_capnp::TaskState;
 use rain_core::{errors::*, types::*, utils::*};
 use std::fmt;
+use std::time::Instant;
 use error_chain::bail;
 
 use super::{DataObjectRef, DataObjectState, GovernorRef, SessionRef};
+use server::scheduler::SchedulerExtra;
 use wrapped::WrappedRcRefCell;
 
 #[derive(Debug)]
@@ -40,6 +42,15 @@ pub struct Task {
 
     /// Task info
     pub(in super::super) info: TaskInfo,
+
+    /// Number of failed attempts to run the task (see `TaskSpec::retry`)
+    pub(in super::super) failed_attempts: u32,
+
+    /// Time when the task was last moved to another governor by rebalancing
+    pub(in super::super) last_move: Option<Instant>,
+
+    /// Scheduler-specific data
+    pub(in super::super) scheduler_extra: SchedulerExtra,
 }
 
 pub type TaskRef = WrappedRcRefCell<Task>;
@@ -60,6 +71,15 @@ impl Task {
         &self.spec
     }
 
+    /// Does the task refuse to share a governor with some other tasks?
+    pub fn has_anti_affinity(&self) -> bool {
+        self.spec
+            .placement
+            .as_ref()
+            .map(|p| !p.anti_affinity.is_empty())
+            .unwrap_or(false)
+    }
+
     #[inline]
     pub fn is_finished(&self) -> bool {
         match self.state {
@@ -168,6 +188,9 @@ impl TaskRef {
             scheduled: None,
             session: session.clone(),
             finish_hooks: Default::default(),
+            failed_attempts: 0,
+            last_move: None,
+            scheduler_extra: Default::default(),
         });
         {
             // add to session
@@ -195,12 +218,13 @@ impl TaskRef {
         let mut inner = self.get_mut();
         if let Some(ref w) = inner.scheduled {
             assert!(w.get_mut().scheduled_tasks.remove(&self));
+            w.get_mut().avoiding_tasks.remove(&self);
             if inner.state == TaskState::Ready {
                 assert!(w.get_mut().scheduled_ready_tasks.remove(&self));
             }
 
             if inner.state != TaskState::NotAssigned {
-                w.get_mut().active_resources -= inner.spec().resources.cpus();
+                w.get_mut().active_resources.remove(&inner.spec().resources);
             }
         }
         inner.scheduled = None;
@@ -267,8 +291,10 @@ impl ConsistencyCheck for TaskRef {
             if o.state == DataObjectState::Removed && s.state != TaskState::Finished {
                 bail!("waiting for removed object {:?} in {:?}", o, s);
             }
-            if (o.state == DataObjectState::Finished || o.state == DataObjectState::Removed)
-                == (s.waiting_for.contains(&i))
+            // A finished task does not wait for its inputs even when they are being recomputed
+            if s.state != TaskState::Finished
+                && (o.state == DataObjectState::Finished || o.state == DataObjectState::Removed)
+                    == (s.waiting_for.contains(&i))
             {
                 bail!(
                     "waiting_for all unfinished inputs invalid woth {:?} in {:?}",
@@ -69,7 +69,7 @@ fn lite_dashboard(state: &StateRef) -> ResponseFuture {
     <p>{time}</p>
     <h2>Governors</h2>
     <table>
-    <thead><tr><th>ID<th>cpus</tr>
+    <thead><tr><th>ID<th>cpus<th>memory</tr>
     </thead>
     {governor_tab}
     </table>
@@ -81,7 +81,11 @@ fn lite_dashboard(state: &StateRef) -> ResponseFuture {
                 "<tr>",
                 "</tr>",
                 state.get().graph.governors.iter().map(|(id, ref wref)| {
-                    format!("<td>{}</td><td>{}</td>", id, wref.get().resources.cpus)
+                    let w = wref.get();
+                    format!(
+                        "<td>{}</td><td>{}</td><td>{}</td>",
+                        id, w.resources.cpus, w.resources.memory
+                    )
                 }),
             )
     ))))
@@ -1,5 +1,6 @@
 use chrono::{DateTime, Utc};
 use futures::Future;
+use std::net::SocketAddr;
 use serde_derive::Deserialize;
 
 use rain_core::errors::Error;
@@ -41,10 +42,18 @@ pub trait Logger {
         self.add_event(Event::GovernorNew(events::GovernorNewEvent { governor }));
     }
 
-    fn add_governor_removed_event(&mut self, governor: GovernorId, error_msg: String) {
+    fn add_governor_removed_event(
+        &mut self,
+        governor: GovernorId,
+        reason: events::GovernorRemovedReason,
+        error_msg: String,
+        last_monitoring: Option<events::MonitoringEvent>,
+    ) {
         self.add_event(Event::GovernorRemoved(events::GovernorRemovedEvent {
             governor,
+            reason,
             error_msg,
+            last_monitoring,
         }));
     }
 
@@ -69,6 +78,13 @@ pub trait Logger {
         ));
     }
 
+    fn add_auth_failed_event(&mut self, address: SocketAddr, role: &str) {
+        self.add_event(Event::AuthFailed(events::AuthFailedEvent {
+            address,
+            role: role.to_string(),
+        }));
+    }
+
     fn add_client_unkeep_event(&mut self, dataobjs: Vec<DataObjectId>) {
         self.add_event(Event::ClientUnkeep(events::ClientUnkeepEvent { dataobjs }));
     }
@@ -84,6 +100,14 @@ pub trait Logger {
         self.add_event(Event::TaskFinished(events::TaskFinishedEvent { task, info }));
     }
 
+    fn add_task_retry_event(&mut self, task: TaskId, attempt: u32, info: TaskInfo) {
+        self.add_event(Event::TaskRetry(events::TaskRetryEvent {
+            task,
+            attempt,
+            info,
+        }));
+    }
+
     fn add_dataobject_finished_event(
         &mut self,
         dataobject: DataObjectId,
@@ -101,18 +125,31 @@ pub trait Logger {
         self.add_event(Event::Dummy(DummyEvent {dummy_value: 1}));
     }
 
-    fn add_client_submit_event(&mut self, tasks: Vec<TaskSpec>, dataobjs: Vec<ObjectSpec>) {
+    fn add_client_submit_event(
+        &mut self,
+        tasks: Vec<TaskSpec>,
+        dataobjs: Vec<ObjectSpec>,
+        kept: Vec<DataObjectId>,
+    ) {
         self.add_event(Event::ClientSubmit(events::ClientSubmitEvent {
             tasks,
             dataobjs,
+            kept,
         }));
     }
 
-    fn add_new_session_event(&mut self, session: SessionId, client: ClientId, spec: SessionSpec) {
+    fn add_new_session_event(
+        &mut self,
+        session: SessionId,
+        client: ClientId,
+        spec: SessionSpec,
+        user: Option<String>,
+    ) {
         self.add_event(Event::SessionNew(events::SessionNewEvent {
             session,
             client,
             spec,
+            user,
         }));
     }
 
@@ -4,16 +4,18 @@ use futures::Future;
 use futures::Stream;
 use rusqlite::Connection;
 use serde_json;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use error_chain::bail;
 
 
 use rain_core::errors::{Error, Result};
 use rain_core::logging::{Event, events};
-use rain_core::types::SessionId;
+use rain_core::types::{SId, SessionId};
 
 
 use super::logger::{Logger, QueryEvents, SearchCriteria};
+use server::recovery::RecoveredSession;
 
 #[derive(Clone, Debug)]
 pub struct EventWrapper {
@@ -77,6 +79,126 @@ fn cleanup_old_sessions(mut conn: &mut Connection)
     }
 }
 
+/// Load all events of sessions that were not closed by the previous server instance.
+/// Events that cannot be decoded are skipped, so are the sessions without
+/// a decodable `SessionNew` event.
+fn load_unclosed_sessions(conn: &mut Connection) -> Result<Vec<RecoveredSession>> {
+    let session_ids: Vec<SessionId> = {
+        let mut q = conn.prepare("SELECT session FROM events WHERE event_type = 'SessionNew' AND session NOT IN (SELECT session FROM events WHERE event_type = 'SessionClosed') ORDER BY session;")?;
+        let ids = q
+            .query_map(&[], |row| row.get(0))?
+            .collect::<::std::result::Result<_, _>>()?;
+        ids
+    };
+    let mut q = conn.prepare("SELECT event FROM events WHERE session = ? ORDER BY id;")?;
+    let mut sessions = Vec::new();
+    for id in session_ids {
+        let rows: Vec<String> = q
+            .query_map(&[&id], |row| row.get(0))?
+            .collect::<::std::result::Result<_, _>>()?;
+        let events: Vec<Event> = rows
+            .iter()
+            .filter_map(|row| match serde_json::from_str(row) {
+                Ok(event) => Some(event),
+                Err(e) => {
+                    log::error!("Skipping undecodable event of session {}: {}", id, e);
+                    None
+                }
+            })
+            .collect();
+        let new_session = events
+            .iter()
+            .filter_map(|e| match e {
+                &Event::SessionNew(ref e) => Some(e.clone()),
+                _ => None,
+            })
+            .next();
+        match new_session {
+            Some(e) => sessions.push(RecoveredSession {
+                id,
+                spec: e.spec,
+                user: e.user,
+                events,
+            }),
+            None => log::error!(
+                "Session {} cannot be recovered without its SessionNew event",
+                id
+            ),
+        }
+    }
+    Ok(sessions)
+}
+
+/// Load all events of the given types from the event database, in the logged order.
+pub fn load_events_of_types(events_db: &Path, event_types: &[&str]) -> Result<Vec<Event>> {
+    let conn = Connection::open(events_db)?;
+    let placeholders: Vec<_> = event_types.iter().map(|_| "?").collect();
+    let mut q = conn.prepare(&format!(
+        "SELECT event FROM events WHERE event_type IN ({}) ORDER BY id;",
+        placeholders.join(", ")
+    ))?;
+    let args: Vec<&::rusqlite::types::ToSql> = event_types
+        .iter()
+        .map(|t| t as &::rusqlite::types::ToSql)
+        .collect();
+    let events = q
+        .query_map(&args, |row| row.get::<_, String>(0))?
+        .map(|r| -> Result<Event> { Ok(serde_json::from_str(&r?)?) })
+        .collect::<Result<_>>()?;
+    Ok(events)
+}
+
+/// Number of the most recently finished tasks used by `load_task_durations`
+const TASK_DURATIONS_WINDOW: i64 = 10_000;
+
+/// Load the sum of durations (in seconds) and the number of successfully finished tasks
+/// by task type, over the `TASK_DURATIONS_WINDOW` most recently finished tasks in the event
+/// database. A missing database gives no durations.
+pub fn load_task_durations(events_db: &Path) -> Result<HashMap<String, (f64, u32)>> {
+    let mut totals = HashMap::new();
+    if !events_db.exists() {
+        return Ok(totals);
+    }
+    let conn = Connection::open(events_db)?;
+    let mut durations = HashMap::new();
+    {
+        let mut q = conn.prepare(
+            "SELECT event FROM events WHERE event_type = 'TaskFinished' ORDER BY id DESC LIMIT ?;",
+        )?;
+        for row in q.query_map(&[&TASK_DURATIONS_WINDOW], |row| row.get::<_, String>(0))? {
+            if let Event::TaskFinished(e) = serde_json::from_str::<Event>(&row?)? {
+                if !e.info.error.is_empty() {
+                    continue;
+                }
+                if let Some(duration) = e.info.duration {
+                    durations.insert(e.task, duration);
+                }
+            }
+        }
+    }
+    let mut sessions: Vec<SessionId> = durations.keys().map(|id| id.get_session_id()).collect();
+    sessions.sort();
+    sessions.dedup();
+
+    // Task types are known only from the submits of the sessions
+    let mut q = conn
+        .prepare("SELECT event FROM events WHERE event_type = 'ClientSubmit' AND session = ?;")?;
+    for session in sessions {
+        for row in q.query_map(&[&session], |row| row.get::<_, String>(0))? {
+            if let Event::ClientSubmit(e) = serde_json::from_str::<Event>(&row?)? {
+                for t in e.tasks {
+                    if let Some(duration) = durations.get(&t.id) {
+                        let entry = totals.entry(t.task_type).or_insert((0.0, 0));
+                        entry.0 += *duration as f64;
+                        entry.1 += 1;
+                    }
+                }
+            }
+        }
+    }
+    Ok(totals)
+}
+
 fn load_events(conn: &mut Connection, search_criteria: &SearchCriteria) -> Result<QueryEvents> {
     let mut args: Vec<&::rusqlite::types::ToSql> = Vec::new();
     let mut where_conds = Vec::new();
@@ -123,7 +245,15 @@ fn load_events(conn: &mut Connection, search_criteria: &SearchCriteria) -> Resul
 }
 
 impl SQLiteLogger {
-    pub fn new(log_dir: &PathBuf) -> Result<(Self, SessionId)> {
+    /// Open (or create) the event log in the given directory.
+    ///
+    /// Returns the logger, the highest session id used in the log and, when `recover` is set,
+    /// the sessions left open by the previous server instance. Without `recover`, such
+    /// sessions are closed with `SessionClosedReason::ServerLost`.
+    pub fn new(
+        log_dir: &PathBuf,
+        recover: bool,
+    ) -> Result<(Self, SessionId, Vec<RecoveredSession>)> {
         let mut conn = Connection::open(log_dir.join("events.db"))?;
 
         // There are basically two type of queries
@@ -154,11 +284,19 @@ impl SQLiteLogger {
             r.next().unwrap().unwrap()
         };
 
+        let recovered = if recover {
+            load_unclosed_sessions(&mut conn)?
+        } else {
+            Vec::new()
+        };
+
         let (sx, rx) = mpsc::unbounded();
 
         ::std::thread::spawn(move || {
             log::debug!("Logger thread started");
-            cleanup_old_sessions(&mut conn);
+            if !recover {
+                cleanup_old_sessions(&mut conn);
+            }
             let mut core = ::tokio_core::reactor::Core::new().unwrap();
             let future = rx.for_each(move |m| {
                 match m {
@@ -183,6 +321,7 @@ impl SQLiteLogger {
                 queue: sx,
             },
             last_session,
+            recovered,
         ))
     }
 }
@@ -225,6 +364,8 @@ impl Logger for SQLiteLogger {
 
 mod tests {
     use super::*;
+    use rain_core::types::SessionSpec;
+    use tempdir::TempDir;
 
     /*
     use std::net::{IpAddr, Ipv4Addr};
@@ -234,7 +375,7 @@ mod tests {
     }*/
 
     fn create_logger() -> SQLiteLogger {
-        SQLiteLogger::new(&PathBuf::from("/tmp")).unwrap().0
+        SQLiteLogger::new(&PathBuf::from("/tmp"), false).unwrap().0
     }
 
     #[test]
@@ -244,6 +385,97 @@ mod tests {
         assert_eq!(logger.events.len(), 1);
     }
 
+    #[test]
+    fn recover_with_undecodable_events() {
+        let dir = TempDir::new("rain-test").unwrap();
+        let log_dir = dir.path().to_path_buf();
+        SQLiteLogger::new(&log_dir, true).unwrap();
+
+        let new_session = Event::SessionNew(events::SessionNewEvent {
+            session: 1,
+            client: "127.0.0.1:1001".parse().unwrap(),
+            spec: SessionSpec {
+                name: "test".to_string(),
+                ..Default::default()
+            },
+            user: Some("alice".to_string()),
+        });
+        let mut conn = Connection::open(log_dir.join("events.db")).unwrap();
+        let wrapper = EventWrapper {
+            event: new_session,
+            timestamp: Utc::now(),
+        };
+        save_events(&mut conn, vec![wrapper]).unwrap();
+        for &(session, event_type, event) in &[
+            (1, "ClientSubmit", "{\"ClientSubmit\": 42}"),
+            (2, "SessionNew", "not json"),
+        ] {
+            conn.execute(
+                "INSERT INTO events (timestamp, event_type, session, event) VALUES (?, ?, ?, ?)",
+                &[&Utc::now(), &event_type, &session, &event],
+            )
+            .unwrap();
+        }
+
+        let (_, _, recovered) = SQLiteLogger::new(&log_dir, true).unwrap();
+        assert_eq!(recovered.len(), 1);
+        assert_eq!(recovered[0].id, 1);
+        assert_eq!(recovered[0].user, Some("alice".to_string()));
+        assert_eq!(recovered[0].events.len(), 1);
+    }
+
+    #[test]
+    fn task_durations_by_type() {
+        use rain_core::types::{TaskId, TaskInfo, TaskSpec};
+
+        let dir = TempDir::new("rain-test").unwrap();
+        let log_dir = dir.path().to_path_buf();
+        SQLiteLogger::new(&log_dir, false).unwrap();
+
+        let task = |id, task_type: &str| TaskSpec {
+            id: TaskId::new(1, id),
+            task_type: task_type.to_string(),
+            ..Default::default()
+        };
+        let finished = |id, error: &str, duration| {
+            Event::TaskFinished(events::TaskFinishedEvent {
+                task: TaskId::new(1, id),
+                info: TaskInfo {
+                    error: error.to_string(),
+                    duration: Some(duration),
+                    ..Default::default()
+                },
+            })
+        };
+        let submit = Event::ClientSubmit(events::ClientSubmitEvent {
+            tasks: vec![task(1, "a"), task(2, "a"), task(3, "b"), task(4, "b")],
+            dataobjs: Vec::new(),
+            kept: Vec::new(),
+        });
+        let events = vec![
+            submit,
+            finished(1, "", 1.0),
+            finished(2, "", 3.0),
+            finished(3, "failed", 5.0),
+        ];
+        let events_db = log_dir.join("events.db");
+        let mut conn = Connection::open(&events_db).unwrap();
+        let wrappers = events
+            .into_iter()
+            .map(|event| EventWrapper {
+                event,
+                timestamp: Utc::now(),
+            })
+            .collect();
+        save_events(&mut conn, wrappers).unwrap();
+
+        let durations = load_task_durations(&events_db).unwrap();
+        assert_eq!(durations.len(), 1);
+        assert_eq!(durations["a"], (4.0, 2));
+        let missing_db = log_dir.join("missing.db");
+        assert!(load_task_durations(&missing_db).unwrap().is_empty());
+    }
+
     #[test]
     fn test_flush_events() {
         let mut logger = create_logger();
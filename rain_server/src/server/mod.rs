@@ -1,7 +1,10 @@
+pub mod auth;
 pub mod graph;
 pub mod http;
 pub mod logging;
+pub mod recovery;
 pub mod rpc;
 pub mod scheduler;
+pub mod simulator;
 pub mod state;
 pub mod testmode;
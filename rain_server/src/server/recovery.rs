@@ -0,0 +1,88 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use rain_core::logging::Event;
+use rain_core::{errors::*, types::*};
+
+/// Session that was not closed when the previous server instance terminated,
+/// together with all its logged events in the original order.
+#[derive(Debug)]
+pub struct RecoveredSession {
+    pub id: SessionId,
+    pub spec: SessionSpec,
+    /// Authenticated user that created the session
+    pub user: Option<String>,
+    pub events: Vec<Event>,
+}
+
+/// Persistent storage of object data uploaded by clients.
+///
+/// Submitted data are not part of the event log, so the server keeps a copy in the
+/// logging directory to be able to rebuild the sessions after a restart.
+/// Data of each session are stored in a separate directory.
+pub struct DataStore {
+    path: PathBuf,
+}
+
+impl DataStore {
+    pub fn new(path: PathBuf) -> Result<Self> {
+        ::std::fs::create_dir_all(&path)?;
+        Ok(DataStore { path })
+    }
+
+    fn object_path(&self, id: DataObjectId) -> PathBuf {
+        self.path
+            .join(id.get_session_id().to_string())
+            .join(id.get_id().to_string())
+    }
+
+    pub fn save(&self, id: DataObjectId, data: &[u8]) -> Result<()> {
+        let path = self.object_path(id);
+        ::std::fs::create_dir_all(path.parent().unwrap())?;
+        File::create(path)?.write_all(data)?;
+        Ok(())
+    }
+
+    pub fn load(&self, id: DataObjectId) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        File::open(self.object_path(id))
+            .map_err(|e| format!("Data of object {} are not stored: {}", id, e))?
+            .read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn token_path(&self, session_id: SessionId) -> PathBuf {
+        self.path.join(session_id.to_string()).join("token")
+    }
+
+    /// Store the reattach token of the session. Tokens are not part of the event log
+    /// as the log is exposed to everyone by the dashboard.
+    pub fn save_token(&self, session_id: SessionId, token: &str) -> Result<()> {
+        let path = self.token_path(session_id);
+        ::std::fs::create_dir_all(path.parent().unwrap())?;
+        File::create(path)?.write_all(token.as_bytes())?;
+        Ok(())
+    }
+
+    /// Load the reattach token of the session, `None` if it was not stored.
+    pub fn load_token(&self, session_id: SessionId) -> Result<Option<String>> {
+        let path = self.token_path(session_id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let mut token = String::new();
+        File::open(path)?.read_to_string(&mut token)?;
+        Ok(Some(token))
+    }
+
+    /// Remove all stored data of the session. Missing data are ignored.
+    pub fn remove_session(&self, session_id: SessionId) {
+        let path = self.path.join(session_id.to_string());
+        if path.exists() {
+            if let Err(e) = ::std::fs::remove_dir_all(&path) {
+                log::warn!("Cannot remove stored data of session {}: {}", session_id, e);
+            }
+        }
+    }
+}
@@ -4,6 +4,7 @@ use capnp_rpc::pry;
 use futures::Future;
 use rain_core::server_capnp::server_bootstrap;
 use rain_core::{types::*, utils::*};
+use std::collections::HashMap;
 use std::net::SocketAddr;
 
 use super::{ClientServiceImpl, GovernorUpstreamImpl};
@@ -57,10 +58,18 @@ impl server_bootstrap::Server for ServerBootstrapImpl {
             return Promise::err(capnp::Error::failed(format!("Client protocol mismatch, expected {}, got {}", CLIENT_PROTOCOL_VERSION, params.get_version())));
         }
 
+        let user = match self.state
+            .get_mut()
+            .authenticate(&self.address, "client", pry!(params.get_token()))
+        {
+            Ok(user) => user,
+            Err(e) => return Promise::err(capnp::Error::failed(e.to_string())),
+        };
+
         self.registered = true;
 
         let service = ::rain_core::client_capnp::client_service::ToClient::new(pry!(
-            ClientServiceImpl::new(&self.state, &self.address)
+            ClientServiceImpl::new(&self.state, &self.address, user)
         )).from_server::<::capnp_rpc::Server>();
 
         log::info!("Connection {} registered as client", self.address);
@@ -87,6 +96,14 @@ impl server_bootstrap::Server for ServerBootstrapImpl {
             return Promise::err(capnp::Error::failed(format!("Protocol mismatch")));
         }
 
+        if let Err(e) =
+            self.state
+                .get_mut()
+                .authenticate(&self.address, "governor", pry!(params.get_token()))
+        {
+            return Promise::err(capnp::Error::failed(e.to_string()));
+        }
+
         self.registered = true;
 
         // If governor fully specifies its address, then we use it as governor_id
@@ -99,10 +116,20 @@ impl server_bootstrap::Server for ServerBootstrapImpl {
         };
 
         let resources = Resources::from_capnp(&pry!(params.get_resources()));
+        let mut labels = HashMap::new();
+        for label in pry!(params.get_labels()).iter() {
+            labels.insert(
+                pry!(label.get_key()).to_string(),
+                pry!(label.get_value()).to_string(),
+            );
+        }
 
         log::info!(
-            "Connection {} registered as governor {} with {:?}",
-            self.address, governor_id, resources
+            "Connection {} registered as governor {} with {:?}, labels {:?}",
+            self.address,
+            governor_id,
+            resources,
+            labels
         );
 
         let control = pry!(params.get_control());
@@ -119,6 +146,7 @@ impl server_bootstrap::Server for ServerBootstrapImpl {
                 governor_id,
                 Some(control),
                 resources,
+                labels,
             ));
             let upstream = ::rain_core::governor_capnp::governor_upstream::ToClient::new(
                 GovernorUpstreamImpl::new(&state, &governor),
@@ -15,10 +15,10 @@ pub struct ClientServiceImpl {
 }
 
 impl ClientServiceImpl {
-    pub fn new(state: &StateRef, address: &SocketAddr) -> Result<Self> {
+    pub fn new(state: &StateRef, address: &SocketAddr, user: Option<String>) -> Result<Self> {
         Ok(Self {
             state: state.clone(),
-            client: state.get_mut().add_client(address.clone())?,
+            client: state.get_mut().add_client(address.clone(), user)?,
         })
     }
 }
@@ -84,10 +84,55 @@ impl client_service::Server for ClientServiceImpl {
         let spec = ::serde_json::from_str(pry!(params.get_spec())).unwrap();
         let session = pry!(s.add_session(&self.client, spec));
         results.get().set_session_id(session.get_id());
+        if let Some(ref token) = session.get().token {
+            results.get().set_token(token);
+        }
         log::debug!("Client asked for a new session, got {:?}", session.get_id());
         Promise::ok(())
     }
 
+    fn attach_session(
+        &mut self,
+        params: client_service::AttachSessionParams,
+        mut results: client_service::AttachSessionResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let params = pry!(params.get());
+        let mut s = self.state.get_mut();
+        let session = pry!(s.session_by_id(params.get_session_id()));
+        let max_id = pry!(s.attach_session(&self.client, &session));
+        results.get().set_max_id(max_id);
+        Promise::ok(())
+    }
+
+    fn reattach_session(
+        &mut self,
+        params: client_service::ReattachSessionParams,
+        mut results: client_service::ReattachSessionResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let params = pry!(params.get());
+        let mut s = self.state.get_mut();
+        let session = pry!(s.session_by_id(params.get_session_id()));
+        let max_id = pry!(s.reattach_session(&self.client, &session, pry!(params.get_token())));
+        results.get().set_max_id(max_id);
+        Promise::ok(())
+    }
+
+    fn drain_governor(
+        &mut self,
+        params: client_service::DrainGovernorParams,
+        _: client_service::DrainGovernorResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let params = pry!(params.get());
+        let governor_id = GovernorId::from_capnp(&pry!(params.get_governor_id()));
+        log::info!(
+            "Client {} requested draining of governor {}",
+            self.client.get_id(),
+            governor_id
+        );
+        pry!(self.state.get_mut().drain_governor(&governor_id));
+        Promise::ok(())
+    }
+
     fn close_session(
         &mut self,
         params: client_service::CloseSessionParams,
@@ -103,7 +148,7 @@ impl client_service::Server for ClientServiceImpl {
     fn submit(
         &mut self,
         params: client_service::SubmitParams,
-        _: client_service::SubmitResults,
+        mut results: client_service::SubmitResults,
     ) -> Promise<(), ::capnp::Error> {
         let mut s = self.state.get_mut();
         let params = pry!(params.get());
@@ -155,9 +200,24 @@ impl client_service::Server for ClientServiceImpl {
                     .iter()
                     .map(|o| o.get().spec.clone())
                     .collect(),
+                created_objects
+                    .iter()
+                    .filter(|o| o.get().client_keep)
+                    .map(|o| o.get().id())
+                    .collect(),
             );
             // verify submit integrity
-            s.verify_submit(&created_tasks, &created_objects)
+            s.verify_submit(&created_tasks, &created_objects)?;
+            // keep the uploaded data for a recovery after a server restart
+            if let Some(ref store) = s.data_store {
+                for o in created_objects.iter() {
+                    let o = o.get();
+                    if let Some(ref data) = o.data {
+                        store.save(o.id(), data)?;
+                    }
+                }
+            }
+            Ok(())
         })();
         if res.is_err() {
             log::debug!("Error: {:?}", res);
@@ -167,8 +227,15 @@ impl client_service::Server for ClientServiceImpl {
             for o in created_objects {
                 pry!(s.remove_object(&o));
             }
-            pry!(res);
+            match res {
+                Err(Error(ErrorKind::SessionErr(ref e), _)) => {
+                    e.to_capnp(&mut results.get().init_error());
+                }
+                res => pry!(res),
+            }
+            return Promise::ok(());
         }
+        results.get().set_ok(());
         Promise::ok(())
     }
 
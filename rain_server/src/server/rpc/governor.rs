@@ -2,6 +2,7 @@ use capnp::capability::Promise;
 use capnp_rpc::pry;
 use chrono::TimeZone;
 use rain_core::governor_capnp::governor_upstream;
+use rain_core::logging::events;
 use rain_core::{types::*, utils::*};
 
 use server::graph::{Governor, GovernorRef};
@@ -23,10 +24,19 @@ impl GovernorUpstreamImpl {
 
 impl Drop for GovernorUpstreamImpl {
     fn drop(&mut self) {
-        log::error!("Connection to governor {} lost", self.governor.get_id());
+        let governor_id = self.governor.get_id();
         let mut s = self.state.get_mut();
-        s.remove_governor(&self.governor)
-            .expect("dropping governor upstream");
+        if !s.graph.is_registered(&self.governor) {
+            log::info!("Connection to removed governor {} closed", governor_id);
+            return;
+        }
+        log::error!("Connection to governor {} lost", governor_id);
+        s.remove_governor(
+            &self.governor,
+            events::GovernorRemovedReason::Lost,
+            "Connection lost".to_string(),
+        )
+        .expect("dropping governor upstream");
     }
 }
 
@@ -38,6 +48,14 @@ impl governor_upstream::Server for GovernorUpstreamImpl {
     ) -> Promise<(), ::capnp::Error> {
         let update = pry!(pry!(params.get()).get_update());
         let mut state = self.state.get_mut();
+        if !state.graph.is_registered(&self.governor) {
+            // The governor was declared lost (e.g. missed heartbeats), its work was moved
+            log::debug!(
+                "Ignoring update from removed governor {}",
+                self.governor.get_id()
+            );
+            return Promise::ok(());
+        }
 
         // TODO: Reserve vectors
         // For some reason collect over iterator do not work here !?
@@ -101,8 +119,12 @@ impl governor_upstream::Server for GovernorUpstreamImpl {
             let timestamp = pry!(cevent.get_timestamp());
             let seconds = timestamp.get_seconds() as i64;
             let subsec_nanos = timestamp.get_subsec_nanos();
+            let event: events::Event = ::serde_json::from_str(&event).unwrap();
+            if let events::Event::Monitoring(ref monitoring) = event {
+                self.governor.get_mut().last_monitoring = Some(monitoring.clone());
+            }
             state.logger.add_event_with_timestamp(
-                ::serde_json::from_str(&event).unwrap(),
+                event,
                 ::chrono::Utc.timestamp(seconds, subsec_nanos),
             );
         }
@@ -131,7 +153,21 @@ impl governor_upstream::Server for GovernorUpstreamImpl {
         };
 
         let obj = object_ref.get();
-        let data = obj.data.as_ref().unwrap();
+        let data = match obj.data {
+            Some(ref data) => data,
+            None => {
+                // The data is not on the server, send the governor to another copy
+                match obj.located.iter().find(|w| **w != self.governor) {
+                    Some(wref) => {
+                        log::debug!("Redirecting governor fetch id={} to {:?}", id, wref);
+                        wref.get_id()
+                            .to_capnp(&mut results.get_status().init_redirect());
+                    }
+                    None => results.get_status().set_removed(()),
+                }
+                return Promise::ok(());
+            }
+        };
         let offset = params.get_offset() as usize;
         let size = params.get_size() as usize;
 
@@ -151,6 +187,78 @@ impl governor_upstream::Server for GovernorUpstreamImpl {
 
         Promise::ok(())
     }
+
+    fn get_object_locations(
+        &mut self,
+        params: governor_upstream::GetObjectLocationsParams,
+        mut results: governor_upstream::GetObjectLocationsResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let params = pry!(params.get());
+        let id = DataObjectId::from_capnp(&pry!(params.get_id()));
+
+        let mut governors = Vec::new();
+        if let Ok(object_ref) = self.state.get().object_by_id(id) {
+            for wref in &object_ref.get().located {
+                if *wref != self.governor {
+                    governors.push(wref.get_id());
+                }
+            }
+        }
+
+        let mut list = results.get().init_governors(governors.len() as u32);
+        for (i, governor_id) in governors.iter().enumerate() {
+            governor_id.to_capnp(&mut list.reborrow().get(i as u32));
+        }
+        Promise::ok(())
+    }
+
+    fn evict_objects(
+        &mut self,
+        params: governor_upstream::EvictObjectsParams,
+        mut results: governor_upstream::EvictObjectsResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let params = pry!(params.get());
+        let mut state = self.state.get_mut();
+        if !state.graph.is_registered(&self.governor) {
+            return Promise::ok(());
+        }
+
+        let mut objects = Vec::new();
+        for cid in pry!(params.get_objects()).iter() {
+            let id = DataObjectId::from_capnp(&cid);
+            if let Ok(object) = state.object_by_id(id) {
+                objects.push(object);
+            }
+        }
+        let evicted = state.evict_objects(&self.governor, objects);
+
+        let mut list = results.get().init_evicted(evicted.len() as u32);
+        for (i, id) in evicted.iter().enumerate() {
+            id.to_capnp(&mut list.reborrow().get(i as u32));
+        }
+        Promise::ok(())
+    }
+
+    fn set_disk_pressure(
+        &mut self,
+        params: governor_upstream::SetDiskPressureParams,
+        _: governor_upstream::SetDiskPressureResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let pressure = pry!(params.get()).get_pressure();
+        if !self.state.get().graph.is_registered(&self.governor) {
+            return Promise::ok(());
+        }
+        if pressure {
+            log::warn!("Governor {} is near its disk quota", self.governor.get_id());
+        } else {
+            log::info!(
+                "Disk pressure of governor {} is over",
+                self.governor.get_id()
+            );
+        }
+        self.governor.get_mut().disk_pressure = pressure;
+        Promise::ok(())
+    }
 }
 
 impl Governor {}
@@ -0,0 +1,220 @@
+use error_chain::bail;
+use rain_core::{errors::*, types::*};
+use std::collections::{HashMap, VecDeque};
+
+use server::graph::{Graph, Task, TaskRef, TaskState};
+
+/// Names of the fair-share modes, the first one is the default.
+pub const FAIR_SHARE_MODES: [&str; 3] = ["off", "session", "client"];
+
+/// Grouping of tasks for the fair sharing of the cluster.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FairShareMode {
+    /// All tasks form a single group
+    Off,
+    /// Each session is a group with the weight from its spec
+    Session,
+    /// All sessions of a client form a group with the highest weight of the sessions
+    Client,
+}
+
+impl FairShareMode {
+    pub fn from_name(name: &str) -> Result<Self> {
+        Ok(match name {
+            "off" => FairShareMode::Off,
+            "session" => FairShareMode::Session,
+            "client" => FairShareMode::Client,
+            _ => bail!(
+                "Unknown fair-share mode '{}', available modes: {}",
+                name,
+                FAIR_SHARE_MODES.join(", ")
+            ),
+        })
+    }
+}
+
+impl Default for FairShareMode {
+    fn default() -> Self {
+        FairShareMode::Off
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum ShareGroup {
+    All,
+    Session(SessionId),
+    Client(ClientId),
+}
+
+/// Weighted fair sharing of cpus between sessions or clients.
+///
+/// The share of a group is the number of cpus used by its scheduled tasks divided
+/// by its weight. Ready tasks of the group with the lowest share are scheduled first,
+/// so a small session gets cpus promptly even when a large one is queued.
+#[derive(Default, Clone, Debug)]
+pub struct FairShare {
+    mode: FairShareMode,
+    /// Cpus used by the scheduled tasks of the groups
+    used: HashMap<ShareGroup, u32>,
+}
+
+/// Ready tasks split by their fair-share groups, see `FairShare::pop`.
+pub struct ShareQueues(Vec<VecDeque<TaskRef>>);
+
+impl FairShare {
+    pub fn new(mode: FairShareMode) -> Self {
+        FairShare {
+            mode,
+            used: Default::default(),
+        }
+    }
+
+    fn group(&self, t: &Task) -> ShareGroup {
+        match self.mode {
+            FairShareMode::Off => ShareGroup::All,
+            FairShareMode::Session => ShareGroup::Session(t.session.get_id()),
+            FairShareMode::Client => ShareGroup::Client(t.session.get().client.get().id),
+        }
+    }
+
+    fn weight(&self, t: &Task) -> f64 {
+        match self.mode {
+            FairShareMode::Off => 1f64,
+            FairShareMode::Session => t.session.get().spec.weight as f64,
+            FairShareMode::Client => t
+                .session
+                .get()
+                .client
+                .get()
+                .sessions
+                .iter()
+                .map(|s| s.get().spec.weight as f64)
+                .fold(0f64, f64::max),
+        }
+    }
+
+    /// Recount the cpus used by the tasks scheduled in the graph.
+    pub fn update(&mut self, graph: &Graph) {
+        self.used.clear();
+        if self.mode == FairShareMode::Off {
+            return;
+        }
+        for wref in graph.governors.values() {
+            for tref in &wref.get().scheduled_tasks {
+                let t = tref.get();
+                // Not assigned tasks wait for their inputs and do not use resources yet
+                if t.state != TaskState::NotAssigned {
+                    *self.used.entry(self.group(&t)).or_insert(0) += t.spec.resources.cpus();
+                }
+            }
+        }
+    }
+
+    /// Share of the group of the task; lower shares are served first.
+    pub fn share(&self, t: &Task) -> f64 {
+        if self.mode == FairShareMode::Off {
+            return 0f64;
+        }
+        self.used.get(&self.group(t)).cloned().unwrap_or(0) as f64 / self.weight(t)
+    }
+
+    /// Account the cpus of a newly scheduled task.
+    pub fn add(&mut self, t: &Task) {
+        if self.mode != FairShareMode::Off {
+            *self.used.entry(self.group(t)).or_insert(0) += t.spec.resources.cpus();
+        }
+    }
+
+    /// Split the tasks into queues of their groups, keeping the order of the tasks.
+    pub fn queues(&self, tasks: Vec<TaskRef>) -> ShareQueues {
+        let mut index = HashMap::new();
+        let mut queues: Vec<VecDeque<TaskRef>> = Vec::new();
+        for tref in tasks {
+            let group = self.group(&tref.get());
+            let i = *index.entry(group).or_insert_with(|| {
+                queues.push(VecDeque::new());
+                queues.len() - 1
+            });
+            queues[i].push_back(tref);
+        }
+        ShareQueues(queues)
+    }
+
+    /// Take the next task from the group with the lowest share.
+    pub fn pop(&self, queues: &mut ShareQueues) -> Option<TaskRef> {
+        let mut best: Option<(f64, usize)> = None;
+        for (i, queue) in queues.0.iter().enumerate() {
+            if let Some(tref) = queue.front() {
+                let share = self.share(&tref.get());
+                if best.map(|(s, _)| share < s).unwrap_or(true) {
+                    best = Some((share, i));
+                }
+            }
+        }
+        best.and_then(|(_, i)| queues.0[i].pop_front())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use server::graph::{ClientRef, GovernorRef, SessionRef};
+    use server::scheduler::{schedule_task, UpdatedOut};
+
+    fn new_task(session: &SessionRef, id: i32) -> TaskRef {
+        let spec = TaskSpec {
+            id: TaskId::new(session.get_id(), id),
+            task_type: "buildin/sleep".to_string(),
+            resources: Resources {
+                cpus: 2,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        TaskRef::new(session, spec, Vec::new(), Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn fair_share_order() {
+        let mut graph = Graph::new(0);
+        let address = "10.0.0.1:7211".parse().unwrap();
+        let resources = Resources {
+            cpus: 8,
+            ..Default::default()
+        };
+        let wref = GovernorRef::new(address, None, resources, Default::default());
+        graph.governors.insert(address, wref.clone());
+
+        let client = ClientRef::new("127.0.0.1:1234".parse().unwrap());
+        let session_spec = |weight| SessionSpec {
+            name: "test".to_string(),
+            weight,
+            ..Default::default()
+        };
+        let s1 = SessionRef::new(1, &client, session_spec(1.0));
+        let s2 = SessionRef::new(2, &client, session_spec(4.0));
+
+        // The first session already uses two cpus
+        let mut up_out = UpdatedOut::default();
+        schedule_task(&new_task(&s1, 1), &wref, &mut up_out);
+        let t1 = new_task(&s1, 2);
+        let t2 = new_task(&s2, 1);
+
+        let mut fair_share = FairShare::new(FairShareMode::Session);
+        fair_share.update(&graph);
+        assert_eq!(fair_share.share(&t1.get()), 2.0);
+        assert_eq!(fair_share.share(&t2.get()), 0.0);
+        let mut queues = fair_share.queues(vec![t1.clone(), t2.clone()]);
+        assert_eq!(fair_share.pop(&mut queues), Some(t2.clone()));
+        fair_share.add(&t2.get());
+        assert_eq!(fair_share.share(&t2.get()), 0.5);
+        assert_eq!(fair_share.pop(&mut queues), Some(t1.clone()));
+        assert_eq!(fair_share.pop(&mut queues), None);
+
+        // Both sessions belong to one client with the highest weight
+        let mut fair_share = FairShare::new(FairShareMode::Client);
+        fair_share.update(&graph);
+        assert_eq!(fair_share.share(&t1.get()), 0.5);
+        assert_eq!(fair_share.share(&t2.get()), 0.5);
+    }
+}
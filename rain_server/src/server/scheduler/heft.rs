@@ -0,0 +1,371 @@
+use rain_core::utils::*;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use super::{
+    can_run_on, is_preferred, schedule_task, FairShare, FairShareMode, Scheduler, SessionUsage,
+    UpdatedIn, UpdatedOut,
+};
+use server::graph::{GovernorRef, Graph, SessionRef, TaskRef, TaskState};
+
+/// Estimated duration (in seconds) of a task type without any history
+const DEFAULT_TASK_DURATION: f64 = 1.0;
+
+/// Bandwidth (bytes per second) assumed for the transfer costs in upward ranks
+const ASSUMED_BANDWIDTH: f64 = 100.0 * 1024.0 * 1024.0;
+
+/// Average runtimes of task types, built from durations of finished tasks.
+#[derive(Default, Clone, Debug)]
+pub struct RuntimeEstimates {
+    /// Task type -> (sum of durations, number of runs)
+    by_type: HashMap<String, (f64, u32)>,
+}
+
+impl RuntimeEstimates {
+    pub fn add(&mut self, task_type: &str, duration: f32) {
+        self.add_runs(task_type, duration as f64, 1);
+    }
+
+    /// Add `count` runs of the task type that took `total` seconds together
+    pub fn add_runs(&mut self, task_type: &str, total: f64, count: u32) {
+        let entry = self
+            .by_type
+            .entry(task_type.to_string())
+            .or_insert((0.0, 0));
+        entry.0 += total;
+        entry.1 += count;
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_type.len()
+    }
+
+    /// Average duration of the task type. Unknown types get the average over all
+    /// known types (or `DEFAULT_TASK_DURATION` when there is no history at all).
+    pub fn estimate(&self, task_type: &str) -> f64 {
+        if let Some(&(sum, count)) = self.by_type.get(task_type) {
+            return sum / count as f64;
+        }
+        if self.by_type.is_empty() {
+            return DEFAULT_TASK_DURATION;
+        }
+        self.by_type
+            .values()
+            .map(|&(sum, count)| sum / count as f64)
+            .sum::<f64>()
+            / self.by_type.len() as f64
+    }
+}
+
+/// Upward rank of a task, stored in the task's `SchedulerExtra`.
+#[derive(Default)]
+struct Rank(f64);
+
+/// Tasks consuming any output of the task.
+fn consumer_tasks(tref: &TaskRef) -> RcSet<TaskRef> {
+    let mut consumers = RcSet::new();
+    for oref in &tref.get().outputs {
+        consumers.extend(oref.get().consumers.iter().cloned());
+    }
+    consumers
+}
+
+/// Critical-path scheduler in the style of HEFT.
+///
+/// Ready tasks are scheduled in the order of their upward ranks, i.e. the estimated
+/// length of the longest path (task runtimes plus transfers of the known output sizes)
+/// from the task to the end of the session graph. Task runtimes are estimated
+/// per task type from the event log and from the tasks finished by this server.
+/// Each task is placed on the governor that holds the most of its input data.
+/// Task priorities take precedence over the ranks and fair sharing takes precedence
+/// over both.
+#[derive(Default, Clone, Debug)]
+pub struct HeftScheduler {
+    ready_tasks: RcSet<TaskRef>,
+    estimates: RuntimeEstimates,
+    fair_share: FairShare,
+    usage: SessionUsage,
+}
+
+impl HeftScheduler {
+    pub fn new(estimates: RuntimeEstimates, fair_share: FairShareMode) -> Self {
+        HeftScheduler {
+            ready_tasks: Default::default(),
+            estimates,
+            fair_share: FairShare::new(fair_share),
+            usage: Default::default(),
+        }
+    }
+
+    /// Compute upward ranks of all unfinished tasks in the session.
+    fn compute_ranks(&self, session: &SessionRef) {
+        // Kahn's algorithm along the consumers: a task is ranked only after
+        // all of its consumers have been ranked.
+        let mut pending: HashMap<TaskRef, usize> = HashMap::new();
+        let mut queue = Vec::new();
+        for tref in session.get().tasks.iter() {
+            let consumers = consumer_tasks(&tref);
+            if consumers.is_empty() {
+                queue.push(tref.clone());
+            } else {
+                pending.insert(tref.clone(), consumers.len());
+            }
+        }
+        while let Some(tref) = queue.pop() {
+            self.compute_rank(&tref);
+            let producers: RcSet<TaskRef> = tref
+                .get()
+                .inputs
+                .iter()
+                .filter_map(|o| o.get().producer.clone())
+                .collect();
+            for pref in producers {
+                let ready = match pending.get_mut(&pref) {
+                    Some(count) => {
+                        *count -= 1;
+                        *count == 0
+                    }
+                    None => false,
+                };
+                if ready {
+                    pending.remove(&pref);
+                    queue.push(pref);
+                }
+            }
+        }
+    }
+
+    /// Set the rank of the task from the ranks of its consumers.
+    fn compute_rank(&self, tref: &TaskRef) {
+        let mut t = tref.get_mut();
+        if t.state == TaskState::Finished {
+            return;
+        }
+        let mut longest_tail = 0f64;
+        for oref in &t.outputs {
+            let o = oref.get();
+            let transfer = o.info.size.unwrap_or(0) as f64 / ASSUMED_BANDWIDTH;
+            for cref in &o.consumers {
+                let rank = cref
+                    .get()
+                    .scheduler_extra
+                    .get::<Rank>()
+                    .map(|r| r.0)
+                    .unwrap_or(0.0);
+                longest_tail = longest_tail.max(transfer + rank);
+            }
+        }
+        let rank = self.estimates.estimate(&t.spec.task_type) + longest_tail;
+        t.scheduler_extra.set(Rank(rank));
+    }
+
+    /// Find the governor for the task with the most of the input data.
+    /// Governors near their disk quota are used only when no other governor fits.
+    fn pick_governor(&self, graph: &Graph, tref: &TaskRef) -> Option<GovernorRef> {
+        let t = tref.get();
+        let mut best: Option<(bool, bool, usize, GovernorRef)> = None;
+        for wref in graph.governors.values() {
+            let w = wref.get();
+            if !can_run_on(&t, &w) {
+                continue;
+            }
+            let local_size: usize = t
+                .inputs
+                .iter()
+                .map(|i| i.get())
+                .filter(|o| o.scheduled.contains(wref))
+                .map(|o| o.info.size.unwrap_or(0))
+                .sum();
+            let key = (is_preferred(&t, &w), !w.disk_pressure, local_size);
+            if best
+                .as_ref()
+                .map(|&(p, d, s, _)| (p, d, s) < key)
+                .unwrap_or(true)
+            {
+                best = Some((key.0, key.1, key.2, wref.clone()));
+            }
+        }
+        best.map(|(_, _, _, wref)| wref)
+    }
+}
+
+impl Scheduler for HeftScheduler {
+    fn name(&self) -> &'static str {
+        "heft"
+    }
+
+    fn is_ready(&self, tref: &TaskRef) -> bool {
+        self.ready_tasks.contains(tref)
+    }
+
+    fn clear_session(&mut self, session: &SessionRef) {
+        let s = session.get();
+        for tref in &s.tasks {
+            self.ready_tasks.remove(&tref);
+        }
+    }
+
+    fn schedule(&mut self, graph: &mut Graph, updated: &UpdatedIn) -> UpdatedOut {
+        let mut up_out: UpdatedOut = Default::default();
+
+        let mut sessions = RcSet::new();
+        for tref in &updated.new_tasks {
+            let t = tref.get();
+            sessions.insert(t.session.clone());
+            if t.state == TaskState::Ready {
+                log::debug!("Scheduler: New ready task {}", t.id());
+                let r = self.ready_tasks.insert(tref.clone());
+                assert!(r);
+            }
+        }
+
+        for tref in &updated.tasks {
+            let t = tref.get();
+            match t.state {
+                TaskState::Ready => {
+                    log::debug!("Scheduler: New ready task {}", t.id());
+                    let r = self.ready_tasks.insert(tref.clone());
+                    assert!(r);
+                }
+                TaskState::Finished => {
+                    if let Some(duration) = t.info.duration {
+                        self.estimates.add(&t.spec.task_type, duration);
+                    }
+                    self.ready_tasks.remove(tref);
+                }
+                _ => {
+                    self.ready_tasks.remove(tref);
+                }
+            }
+        }
+
+        for session in &sessions {
+            self.compute_ranks(session);
+        }
+
+        if graph.governors.is_empty() || self.ready_tasks.is_empty() {
+            return up_out;
+        }
+
+        log::debug!("Scheduler started");
+
+        let mut tasks: Vec<(i32, f64, TaskRef)> = self
+            .ready_tasks
+            .iter()
+            .map(|tref| {
+                let t = tref.get();
+                let rank = t.scheduler_extra.get::<Rank>().map(|r| r.0).unwrap_or(0.0);
+                // A NaN rank (e.g. from a NaN duration in the event log) counts as zero,
+                // so the ranks are totally ordered
+                let rank = if rank.is_nan() { 0.0 } else { rank };
+                (t.spec.priority, rank, tref.clone())
+            })
+            .collect();
+        // Highest priority and rank first; ties are broken by the task id
+        tasks.sort_by(|a, b| {
+            b.0.cmp(&a.0)
+                .then(b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal))
+                .then_with(|| a.2.get().id().cmp(&b.2.get().id()))
+        });
+
+        self.fair_share.update(graph);
+        self.usage.update(graph);
+        let mut queues = self
+            .fair_share
+            .queues(tasks.into_iter().map(|(_, _, tref)| tref).collect());
+        while let Some(tref) = self.fair_share.pop(&mut queues) {
+            if !self.usage.allows(&tref.get()) {
+                continue;
+            }
+            if let Some(wref) = self.pick_governor(graph, &tref) {
+                schedule_task(&tref, &wref, &mut up_out);
+                self.fair_share.add(&tref.get());
+                self.usage.add(&tref.get());
+                self.ready_tasks.remove(&tref);
+            }
+        }
+        up_out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rain_core::types::*;
+    use server::graph::{ClientRef, DataObjectRef};
+
+    #[test]
+    fn runtime_estimates() {
+        let mut estimates = RuntimeEstimates::default();
+        assert_eq!(estimates.estimate("buildin/run"), DEFAULT_TASK_DURATION);
+        estimates.add("buildin/run", 2.0);
+        estimates.add("buildin/run", 4.0);
+        estimates.add("py/", 1.0);
+        assert_eq!(estimates.estimate("buildin/run"), 3.0);
+        assert_eq!(estimates.estimate("py/"), 1.0);
+        assert_eq!(estimates.estimate("buildin/sleep"), 2.0);
+        estimates.add_runs("py/", 5.0, 2);
+        assert_eq!(estimates.estimate("py/"), 2.0);
+    }
+
+    fn new_object(session: &SessionRef, id: i32) -> DataObjectRef {
+        let spec = ObjectSpec {
+            id: DataObjectId::new(session.get_id(), id),
+            data_type: DataType::Blob,
+            ..Default::default()
+        };
+        DataObjectRef::new(session, spec, false, None)
+    }
+
+    fn new_task(
+        session: &SessionRef,
+        id: i32,
+        inputs: Vec<DataObjectRef>,
+        outputs: Vec<DataObjectRef>,
+    ) -> TaskRef {
+        let spec = TaskSpec {
+            id: TaskId::new(session.get_id(), id),
+            task_type: "buildin/sleep".to_string(),
+            inputs: inputs
+                .iter()
+                .map(|o| TaskSpecInput {
+                    id: o.get().id(),
+                    label: Default::default(),
+                })
+                .collect(),
+            outputs: outputs.iter().map(|o| o.get().id()).collect(),
+            ..Default::default()
+        };
+        TaskRef::new(session, spec, inputs, outputs).unwrap()
+    }
+
+    fn rank(tref: &TaskRef) -> f64 {
+        tref.get().scheduler_extra.get::<Rank>().unwrap().0
+    }
+
+    #[test]
+    fn ranks_with_shortcut() {
+        let client = ClientRef::new("127.0.0.1:1234".parse().unwrap());
+        let spec = SessionSpec {
+            name: "test".to_string(),
+            ..Default::default()
+        };
+        let session = SessionRef::new(1, &client, spec);
+
+        // root -> c1, root -> c2, c2 -> c1
+        let o_root = new_object(&session, 1);
+        let o_c2 = new_object(&session, 2);
+        let o_c1 = new_object(&session, 3);
+        let root = new_task(&session, 10, vec![], vec![o_root.clone()]);
+        let c2 = new_task(&session, 11, vec![o_root.clone()], vec![o_c2.clone()]);
+        let c1 = new_task(&session, 12, vec![o_root, o_c2], vec![o_c1]);
+
+        let mut estimates = RuntimeEstimates::default();
+        estimates.add("buildin/sleep", 1.0);
+        let scheduler = HeftScheduler::new(estimates, FairShareMode::Off);
+        scheduler.compute_ranks(&session);
+        assert_eq!(rank(&c1), 1.0);
+        assert_eq!(rank(&c2), 2.0);
+        assert_eq!(rank(&root), 3.0);
+    }
+}
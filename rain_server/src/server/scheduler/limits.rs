@@ -0,0 +1,52 @@
+use rain_core::types::*;
+use std::collections::HashMap;
+
+use server::graph::{Graph, Task, TaskState};
+
+/// Cpus and tasks used by the scheduled tasks of the sessions with limits.
+///
+/// Schedulers keep ready tasks of a session that reached its limits waiting;
+/// they are scheduled when some of the running tasks of the session finish.
+#[derive(Default, Clone, Debug)]
+pub struct SessionUsage {
+    /// Session id -> (used cpus, number of scheduled tasks)
+    used: HashMap<SessionId, (u32, u32)>,
+}
+
+impl SessionUsage {
+    /// Recount the usage of the tasks scheduled in the graph.
+    pub fn update(&mut self, graph: &Graph) {
+        self.used.clear();
+        for wref in graph.governors.values() {
+            for tref in &wref.get().scheduled_tasks {
+                let t = tref.get();
+                // Not assigned tasks wait for their inputs and do not use resources yet
+                if t.state != TaskState::NotAssigned {
+                    self.add(&t);
+                }
+            }
+        }
+    }
+
+    /// Check whether the task may be scheduled within the limits of its session.
+    pub fn allows(&self, t: &Task) -> bool {
+        let s = t.session.get();
+        match s.spec.limits {
+            Some(ref limits) => {
+                let (cpus, tasks) = self.used.get(&s.id).cloned().unwrap_or((0, 0));
+                limits.allows_task(t.spec.resources.cpus(), cpus, tasks)
+            }
+            None => true,
+        }
+    }
+
+    /// Account a newly scheduled task.
+    pub fn add(&mut self, t: &Task) {
+        let s = t.session.get();
+        if s.spec.limits.is_some() {
+            let entry = self.used.entry(s.id).or_insert((0, 0));
+            entry.0 += t.spec.resources.cpus();
+            entry.1 += 1;
+        }
+    }
+}
@@ -0,0 +1,372 @@
+use rain_core::{errors::*, types::TaskId, utils::*};
+use std::any::Any;
+use std::clone::Clone;
+use std::collections::hash_map::HashMap;
+use std::fmt;
+use std::path::Path;
+use error_chain::bail;
+
+use server::graph::{DataObjectRef, Governor, GovernorRef, Graph, SessionRef, Task, TaskRef,
+                    TaskState};
+use server::logging::sqlite_logger::load_task_durations;
+
+mod fairshare;
+mod heft;
+mod limits;
+mod reactive;
+mod roundrobin;
+
+pub use self::fairshare::{FairShare, FairShareMode, FAIR_SHARE_MODES};
+pub use self::heft::{HeftScheduler, RuntimeEstimates};
+pub use self::limits::SessionUsage;
+pub use self::reactive::ReactiveScheduler;
+pub use self::roundrobin::RoundRobinScheduler;
+
+/// Names of the available schedulers, the first one is the default.
+pub const SCHEDULER_NAMES: [&str; 3] = ["reactive", "round-robin", "heft"];
+
+#[derive(Default, Clone, Debug)]
+pub struct UpdatedOut {
+    /// Tasks with updatet state
+    pub(in super::super) tasks: RcSet<TaskRef>,
+    /// Governor-DataObject updated pairs, grouped by governor
+    pub(in super::super) objects: HashMap<GovernorRef, RcSet<DataObjectRef>>,
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct UpdatedIn {
+    /// Newly submitted Tasks.
+    pub(in super::super) new_tasks: RcSet<TaskRef>,
+    /// Newly submitted DataObjects.
+    pub(in super::super) new_objects: RcSet<DataObjectRef>,
+    /// Old Tasks with changed state. Includes changes originating from governors, clients
+    /// and the server assigning Task to Governor. Scheduler-requested operations
+    /// (unscheduled already Assigned or Running tasks) are not included.
+    pub(in super::super) tasks: RcSet<TaskRef>,
+    /// Old DataObjects with changed state. Includes changes originating from governors, clients
+    /// and the server assigning Object to Governor. Scheduler-requested operations
+    /// (unscheduled already Assigned or Finished object) are not included.
+    pub(in super::super) objects: HashMap<DataObjectRef, RcSet<GovernorRef>>,
+    /// Newly registered Governors.
+    pub(in super::super) new_governors: RcSet<GovernorRef>,
+}
+
+impl UpdatedIn {
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+            && self.objects.is_empty()
+            && self.new_tasks.is_empty()
+            && self.new_objects.is_empty()
+            && self.new_governors.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.new_tasks = Default::default();
+        self.new_objects = Default::default();
+        self.new_governors = Default::default();
+        self.tasks.clear();
+        self.objects.clear();
+    }
+
+    pub fn remove_task(&mut self, task_ref: &TaskRef) {
+        self.new_tasks.remove(task_ref);
+        self.tasks.remove(task_ref);
+    }
+}
+
+/// Scheduler interface.
+///
+/// The scheduler gets the changes since its last run in `UpdatedIn` and returns
+/// the tasks and objects it (re)scheduled in `UpdatedOut`. Any scheduler-specific
+/// data of a task, object or governor can be kept in its `SchedulerExtra`.
+pub trait Scheduler {
+    /// Name used to select the scheduler (see `SCHEDULER_NAMES`)
+    fn name(&self) -> &'static str;
+
+    fn schedule(&mut self, graph: &mut Graph, updated: &UpdatedIn) -> UpdatedOut;
+
+    /// Returns true if the task is known to the scheduler as ready and waits for scheduling.
+    fn is_ready(&self, tref: &TaskRef) -> bool;
+
+    /// Forget all tasks of the session.
+    fn clear_session(&mut self, session: &SessionRef);
+}
+
+/// Create a scheduler by its name.
+///
+/// The event database `events_db` provides the task runtime history for the "heft" scheduler.
+pub fn create_scheduler(
+    name: &str,
+    events_db: &Path,
+    fair_share: FairShareMode,
+) -> Result<Box<Scheduler>> {
+    Ok(match name {
+        "reactive" => Box::new(ReactiveScheduler::new(fair_share)),
+        "round-robin" => Box::new(RoundRobinScheduler::new(fair_share)),
+        "heft" => {
+            let mut estimates = RuntimeEstimates::default();
+            match load_task_durations(events_db) {
+                Ok(durations) => {
+                    for (task_type, (total, count)) in durations {
+                        estimates.add_runs(&task_type, total, count);
+                    }
+                }
+                Err(e) => log::warn!("Cannot load task durations from the event log: {}", e),
+            }
+            log::info!(
+                "Runtime estimates loaded for {} task type(s)",
+                estimates.len()
+            );
+            Box::new(HeftScheduler::new(estimates, fair_share))
+        }
+        _ => bail!(
+            "Unknown scheduler '{}', available schedulers: {}",
+            name,
+            SCHEDULER_NAMES.join(", ")
+        ),
+    })
+}
+
+/// Scheduler-specific attribute of a graph node.
+///
+/// The value is not interpreted outside of the scheduler. A value of another type
+/// than the requested one is treated as missing.
+#[derive(Default)]
+pub struct SchedulerExtra(Option<Box<Any>>);
+
+impl SchedulerExtra {
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.0.as_ref().and_then(|v| v.downcast_ref())
+    }
+
+    /// Get the value, a default value is created when missing.
+    pub fn get_mut<T: Any + Default>(&mut self) -> &mut T {
+        if self.get::<T>().is_none() {
+            self.0 = Some(Box::new(T::default()));
+        }
+        self.0.as_mut().unwrap().downcast_mut().unwrap()
+    }
+
+    pub fn set<T: Any>(&mut self, value: T) {
+        self.0 = Some(Box::new(value));
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = None;
+    }
+}
+
+impl fmt::Debug for SchedulerExtra {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "SchedulerExtra({})",
+            if self.0.is_some() { "set" } else { "empty" }
+        )
+    }
+}
+
+/// Returns true if the governor has enough free resources to run the task
+/// and the placement constraints of the task allow it. Draining governors
+/// do not take any tasks.
+pub fn can_run_on(t: &Task, w: &Governor) -> bool {
+    if w.draining {
+        return false;
+    }
+    let mut required = w.active_resources.clone();
+    required.add(&t.spec.resources);
+    required.is_subset_of(&w.resources) && placement_allows(t, w)
+}
+
+/// Check the label constraints of the task and anti-affinity with the tasks
+/// already scheduled on the governor (in both directions).
+fn placement_allows(t: &Task, w: &Governor) -> bool {
+    if let Some(ref placement) = t.spec.placement {
+        if !placement.labels_match(&w.labels) {
+            return false;
+        }
+    }
+    let avoids = |t: &Task, id: &TaskId| {
+        t.spec
+            .placement
+            .as_ref()
+            .map(|p| p.anti_affinity.contains(id))
+            .unwrap_or(false)
+    };
+    if t.has_anti_affinity()
+        && w.scheduled_tasks
+            .iter()
+            .any(|oref| avoids(t, &oref.get().id()))
+    {
+        return false;
+    }
+    let id = t.id();
+    !w.avoiding_tasks.iter().any(|oref| avoids(&oref.get(), &id))
+}
+
+/// Returns true if the governor is the preferred governor of the task.
+fn is_preferred(t: &Task, w: &Governor) -> bool {
+    t.spec
+        .placement
+        .as_ref()
+        .and_then(|p| p.preferred_governor)
+        .map(|id| id == *w.id())
+        .unwrap_or(false)
+}
+
+/// Schedule a ready task and its outputs to the governor.
+pub fn schedule_task(tref: &TaskRef, wref: &GovernorRef, up_out: &mut UpdatedOut) {
+    let mut w = wref.get_mut();
+    let mut t = tref.get_mut();
+
+    assert!(t.state == TaskState::Ready);
+    w.active_resources.add(&t.spec().resources);
+    w.scheduled_tasks.insert(tref.clone());
+    if t.has_anti_affinity() {
+        w.avoiding_tasks.insert(tref.clone());
+    }
+
+    // Scheduler "picks" only ready tasks, so we do need to test readiness of task
+    w.scheduled_ready_tasks.insert(tref.clone());
+
+    t.scheduled = Some(wref.clone());
+
+    log::debug!("Scheduler: {} -> {}", t.id(), w.id());
+    for oref in &t.outputs {
+        w.scheduled_objects.insert(oref.clone());
+        oref.get_mut().scheduled.insert(wref.clone());
+
+        up_out
+            .objects
+            .entry(wref.clone())
+            .or_insert(Default::default())
+            .insert(oref.clone());
+    }
+    up_out.tasks.insert(tref.clone());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rain_core::types::*;
+    use server::graph::ClientRef;
+    use tempdir::TempDir;
+
+    #[test]
+    fn create_scheduler_by_name() {
+        let dir = TempDir::new("rain-test").unwrap();
+        let events_db = dir.path().join("events.db");
+        for name in SCHEDULER_NAMES.iter() {
+            let scheduler = create_scheduler(name, &events_db, Default::default()).unwrap();
+            assert_eq!(scheduler.name(), *name);
+        }
+
+        let error = create_scheduler("fifo", &events_db, Default::default())
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("'fifo'"));
+        for name in SCHEDULER_NAMES.iter() {
+            assert!(error.contains(name));
+        }
+    }
+
+    #[test]
+    fn scheduler_extra_of_other_type() {
+        let mut extra = SchedulerExtra::default();
+        assert!(extra.get::<u32>().is_none());
+        extra.set(5u32);
+        assert_eq!(extra.get::<u32>(), Some(&5));
+        assert!(extra.get::<f64>().is_none());
+        // A value of another type is replaced by a default one
+        assert_eq!(*extra.get_mut::<f64>(), 0.0);
+        assert!(extra.get::<u32>().is_none());
+    }
+
+    #[test]
+    fn refused_without_enough_memory() {
+        let wref = GovernorRef::new(
+            "10.0.0.1:7211".parse().unwrap(),
+            None,
+            Resources {
+                cpus: 8,
+                memory: 1000,
+                named: Default::default(),
+            },
+            Default::default(),
+        );
+        let client = ClientRef::new("127.0.0.1:1234".parse().unwrap());
+        let session_spec = SessionSpec {
+            name: "test".to_string(),
+            ..Default::default()
+        };
+        let session = SessionRef::new(1, &client, session_spec);
+        let new_task = |id: Id, memory: u64| {
+            let spec = TaskSpec {
+                id: TaskId::new(1, id),
+                task_type: "buildin/sleep".to_string(),
+                resources: Resources {
+                    cpus: 1,
+                    memory,
+                    named: Default::default(),
+                },
+                ..Default::default()
+            };
+            TaskRef::new(&session, spec, Vec::new(), Vec::new()).unwrap()
+        };
+        let big = new_task(1, 2000);
+        let small = new_task(2, 600);
+        assert!(!can_run_on(&big.get(), &wref.get()));
+        assert!(can_run_on(&small.get(), &wref.get()));
+
+        // Cpus are still free, but the memory is taken by the scheduled task
+        schedule_task(&small, &wref, &mut UpdatedOut::default());
+        assert!(!can_run_on(&new_task(3, 600).get(), &wref.get()));
+        assert!(can_run_on(&new_task(4, 400).get(), &wref.get()));
+    }
+
+    #[test]
+    fn anti_affinity_both_directions() {
+        let resources = Resources {
+            cpus: 8,
+            ..Default::default()
+        };
+        let wref = GovernorRef::new(
+            "10.0.0.1:7211".parse().unwrap(),
+            None,
+            resources,
+            Default::default(),
+        );
+        let client = ClientRef::new("127.0.0.1:1234".parse().unwrap());
+        let session_spec = SessionSpec {
+            name: "test".to_string(),
+            ..Default::default()
+        };
+        let session = SessionRef::new(1, &client, session_spec);
+        let new_task = |id: Id, anti_affinity: Vec<TaskId>| {
+            let spec = TaskSpec {
+                id: TaskId::new(1, id),
+                task_type: "buildin/sleep".to_string(),
+                placement: Some(PlacementSpec {
+                    anti_affinity,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            TaskRef::new(&session, spec, Vec::new(), Vec::new()).unwrap()
+        };
+        let avoiding = new_task(1, vec![TaskId::new(1, 2)]);
+        let avoided = new_task(2, Vec::new());
+        let other = new_task(3, Vec::new());
+        let mut up_out = UpdatedOut::default();
+
+        schedule_task(&avoiding, &wref, &mut up_out);
+        assert!(!can_run_on(&avoided.get(), &wref.get()));
+        assert!(can_run_on(&other.get(), &wref.get()));
+
+        avoiding.unschedule();
+        assert!(wref.get().avoiding_tasks.is_empty());
+        schedule_task(&avoided, &wref, &mut up_out);
+        assert!(!can_run_on(&avoiding.get(), &wref.get()));
+    }
+}
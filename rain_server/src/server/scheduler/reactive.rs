@@ -0,0 +1,196 @@
+use rain_core::utils::*;
+
+use super::{
+    can_run_on, is_preferred, schedule_task, FairShare, FairShareMode, Scheduler, SessionUsage,
+    UpdatedIn, UpdatedOut,
+};
+use server::graph::{GovernorRef, Graph, SessionRef, TaskRef, TaskState};
+
+/// Score bonus of the preferred governor of a task, larger than any data-based score
+const PREFERRED_GOVERNOR_BONUS: i64 = 1 << 50;
+
+/// Score penalty of a governor near its disk quota, larger than any data-based score
+const DISK_PRESSURE_PENALTY: i64 = 1 << 45;
+
+/// Greedy scheduler that places the ready tasks close to their input data.
+/// With fair sharing, tasks of the group with the lowest share are preferred.
+#[derive(Default, Clone, Debug)]
+pub struct ReactiveScheduler {
+    ready_tasks: RcSet<TaskRef>,
+    fair_share: FairShare,
+    usage: SessionUsage,
+}
+
+impl ReactiveScheduler {
+    pub fn new(fair_share: FairShareMode) -> Self {
+        ReactiveScheduler {
+            ready_tasks: Default::default(),
+            fair_share: FairShare::new(fair_share),
+            usage: Default::default(),
+        }
+    }
+
+    fn pick_best(&self, graph: &mut Graph) -> Option<(TaskRef, GovernorRef)> {
+        let mut best_governor = None;
+        // Fair share of the task's group, priority of the task and the locality score
+        let mut best_score = (0f64, 0, 0);
+        let mut best_task = None;
+
+        let n_governors = graph.governors.len() as i64;
+
+        for tref in &self.ready_tasks {
+            let t = tref.get();
+            if !self.usage.allows(&t) {
+                continue;
+            }
+            let mut total_size = 0;
+            for input in &t.inputs {
+                let o = input.get();
+                total_size +=
+                    o.info.size.expect("missing info.size in finished object") * o.scheduled.len();
+            }
+            let neg_avg_size = -(total_size as i64) / n_governors;
+            //log::debug!("!!! {} AVG SIZE {}", t.id, -neg_avg_size);
+
+            for (_, wref) in &graph.governors {
+                let w = wref.get();
+                if can_run_on(&t, &w) {
+                    let cpus = t.spec.resources.cpus();
+                    let mut score = neg_avg_size + cpus as i64 * 5000i64;
+                    for input in &t.inputs {
+                        let o = input.get();
+                        if o.scheduled.contains(wref) {
+                            score += o.info.size.unwrap() as i64;
+                        }
+                    }
+                    if is_preferred(&t, &w) {
+                        score += PREFERRED_GOVERNOR_BONUS;
+                    }
+                    if w.disk_pressure {
+                        score -= DISK_PRESSURE_PENALTY;
+                    }
+                    let score = (-self.fair_share.share(&t), t.spec.priority, score);
+                    if best_score < score || best_governor.is_none() {
+                        best_score = score;
+                        best_governor = Some(wref.clone());
+                        best_task = Some(tref.clone());
+                    }
+                }
+            }
+        }
+        if let Some(wref) = best_governor {
+            Some((best_task.unwrap(), wref))
+        } else {
+            None
+        }
+    }
+}
+
+impl Scheduler for ReactiveScheduler {
+    fn name(&self) -> &'static str {
+        "reactive"
+    }
+
+    fn is_ready(&self, tref: &TaskRef) -> bool {
+        self.ready_tasks.contains(tref)
+    }
+
+    fn clear_session(&mut self, session: &SessionRef) {
+        let s = session.get();
+        for tref in &s.tasks {
+            self.ready_tasks.remove(&tref);
+        }
+    }
+
+    fn schedule(&mut self, graph: &mut Graph, updated: &UpdatedIn) -> UpdatedOut {
+        let mut up_out: UpdatedOut = Default::default();
+
+        for tref in &updated.new_tasks {
+            let t = tref.get();
+            if t.state == TaskState::Ready {
+                log::debug!("Scheduler: New ready task {}", t.id());
+                let r = self.ready_tasks.insert(tref.clone());
+                assert!(r);
+            }
+        }
+
+        for tref in &updated.tasks {
+            let t = tref.get();
+            if t.state == TaskState::Ready {
+                log::debug!("Scheduler: New ready task {}", t.id());
+                let r = self.ready_tasks.insert(tref.clone());
+                assert!(r);
+            } else {
+                // A task waiting for a recomputed input is not ready anymore
+                self.ready_tasks.remove(tref);
+            }
+        }
+
+        if graph.governors.is_empty() {
+            return up_out;
+        }
+
+        log::debug!("Scheduler started");
+
+        self.fair_share.update(graph);
+        self.usage.update(graph);
+        while let Some((tref, wref)) = self.pick_best(graph) {
+            schedule_task(&tref, &wref, &mut up_out);
+            self.fair_share.add(&tref.get());
+            self.usage.add(&tref.get());
+            self.ready_tasks.remove(&tref);
+        }
+        up_out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rain_core::types::*;
+    use server::graph::ClientRef;
+
+    #[test]
+    fn disk_pressure_steering() {
+        let mut graph = Graph::new(0);
+        let resources = Resources {
+            cpus: 8,
+            ..Default::default()
+        };
+        let mut governors = Vec::new();
+        for address in &["10.0.0.1:7211", "10.0.0.2:7211"] {
+            let address = address.parse().unwrap();
+            let wref = GovernorRef::new(address, None, resources.clone(), Default::default());
+            graph.governors.insert(address, wref.clone());
+            governors.push(wref);
+        }
+        let client = ClientRef::new("127.0.0.1:1234".parse().unwrap());
+        let session_spec = SessionSpec {
+            name: "test".to_string(),
+            ..Default::default()
+        };
+        let session = SessionRef::new(1, &client, session_spec);
+
+        for (i, pressured) in governors.iter().enumerate() {
+            pressured.get_mut().disk_pressure = true;
+            let spec = TaskSpec {
+                id: TaskId::new(session.get_id(), i as i32),
+                task_type: "buildin/sleep".to_string(),
+                resources: Resources {
+                    cpus: 1,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let tref = TaskRef::new(&session, spec, Vec::new(), Vec::new()).unwrap();
+            let mut updated = UpdatedIn::default();
+            updated.new_tasks.insert(tref.clone());
+
+            let mut scheduler = ReactiveScheduler::new(FairShareMode::Off);
+            scheduler.schedule(&mut graph, &updated);
+            let scheduled = tref.get().scheduled.clone().unwrap();
+            assert!(scheduled != *pressured);
+            pressured.get_mut().disk_pressure = false;
+        }
+    }
+}
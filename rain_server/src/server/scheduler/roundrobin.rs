@@ -0,0 +1,130 @@
+use rain_core::utils::*;
+
+use super::{
+    can_run_on, is_preferred, schedule_task, FairShare, FairShareMode, Scheduler, SessionUsage,
+    UpdatedIn, UpdatedOut,
+};
+use server::graph::{GovernorRef, Graph, SessionRef, TaskRef, TaskState};
+
+/// Order in which the task became ready, stored in the task's `SchedulerExtra`.
+#[derive(Default)]
+struct ReadyOrder(u64);
+
+/// Baseline scheduler that ignores data placement. Ready tasks are taken by priority
+/// and then in the order in which they became ready, and the governors are tried
+/// in a fixed cyclic order, each search starting after the governor that received
+/// the previous task.
+/// The preferred governor of a task is tried first and governors near their disk quota
+/// are tried last. With fair sharing, the groups
+/// with the lowest shares take their tasks first.
+#[derive(Default, Clone, Debug)]
+pub struct RoundRobinScheduler {
+    ready_tasks: RcSet<TaskRef>,
+    /// Counter for `ReadyOrder`
+    ready_counter: u64,
+    /// Position of the next governor in the cycle
+    next_governor: usize,
+    fair_share: FairShare,
+    usage: SessionUsage,
+}
+
+impl RoundRobinScheduler {
+    pub fn new(fair_share: FairShareMode) -> Self {
+        RoundRobinScheduler {
+            fair_share: FairShare::new(fair_share),
+            ..Default::default()
+        }
+    }
+
+    fn add_ready_task(&mut self, tref: &TaskRef) {
+        let mut t = tref.get_mut();
+        log::debug!("Scheduler: New ready task {}", t.id());
+        t.scheduler_extra.set(ReadyOrder(self.ready_counter));
+        self.ready_counter += 1;
+        let r = self.ready_tasks.insert(tref.clone());
+        assert!(r);
+    }
+}
+
+impl Scheduler for RoundRobinScheduler {
+    fn name(&self) -> &'static str {
+        "round-robin"
+    }
+
+    fn is_ready(&self, tref: &TaskRef) -> bool {
+        self.ready_tasks.contains(tref)
+    }
+
+    fn clear_session(&mut self, session: &SessionRef) {
+        let s = session.get();
+        for tref in &s.tasks {
+            self.ready_tasks.remove(&tref);
+        }
+    }
+
+    fn schedule(&mut self, graph: &mut Graph, updated: &UpdatedIn) -> UpdatedOut {
+        let mut up_out: UpdatedOut = Default::default();
+
+        for tref in updated.new_tasks.iter().chain(updated.tasks.iter()) {
+            if tref.get().state == TaskState::Ready {
+                self.add_ready_task(tref);
+            } else {
+                self.ready_tasks.remove(tref);
+            }
+        }
+
+        if graph.governors.is_empty() || self.ready_tasks.is_empty() {
+            return up_out;
+        }
+
+        let mut governors: Vec<GovernorRef> = graph.governors.values().cloned().collect();
+        governors.sort_by_key(|w| w.get_id());
+
+        let mut tasks: Vec<TaskRef> = self.ready_tasks.iter().cloned().collect();
+        tasks.sort_by_key(|t| {
+            let t = t.get();
+            (
+                -(t.spec.priority as i64),
+                t.scheduler_extra.get::<ReadyOrder>().map(|o| o.0),
+            )
+        });
+
+        self.fair_share.update(graph);
+        self.usage.update(graph);
+        let mut queues = self.fair_share.queues(tasks);
+        while let Some(tref) = self.fair_share.pop(&mut queues) {
+            if !self.usage.allows(&tref.get()) {
+                continue;
+            }
+            let n = governors.len();
+            let start = self.next_governor % n;
+            let found = {
+                let t = tref.get();
+                (0..n)
+                    .position(|i| {
+                        let w = governors[i].get();
+                        is_preferred(&t, &w) && can_run_on(&t, &w)
+                    })
+                    .or_else(|| {
+                        (0..n).map(|i| (start + i) % n).find(|&i| {
+                            let w = governors[i].get();
+                            !w.disk_pressure && can_run_on(&t, &w)
+                        })
+                    })
+                    .or_else(|| {
+                        (0..n)
+                            .map(|i| (start + i) % n)
+                            .find(|&i| can_run_on(&t, &governors[i].get()))
+                    })
+            };
+            if let Some(i) = found {
+                schedule_task(&tref, &governors[i], &mut up_out);
+                self.fair_share.add(&tref.get());
+                self.usage.add(&tref.get());
+                self.ready_tasks.remove(&tref);
+                self.next_governor = i + 1;
+            }
+        }
+        up_out
+    }
+}
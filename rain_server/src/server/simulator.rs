@@ -0,0 +1,476 @@
+use error_chain::bail;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::Path;
+
+use rain_core::logging::Event;
+use rain_core::{errors::*, types::*};
+
+use server::graph::{
+    ClientRef, DataObjectRef, DataObjectState, GovernorRef, Graph, SessionRef, TaskRef, TaskState,
+};
+use server::logging::sqlite_logger::load_events_of_types;
+use server::scheduler::{Scheduler, UpdatedIn};
+
+/// Session graph with the task durations and object sizes recorded in the event log.
+pub struct RecordedSession {
+    pub id: SessionId,
+    spec: SessionSpec,
+    tasks: HashMap<TaskId, TaskSpec>,
+    objects: HashMap<DataObjectId, ObjectSpec>,
+    /// Durations (in seconds) of successfully finished tasks
+    durations: HashMap<TaskId, f32>,
+    /// Sizes of finished objects
+    sizes: HashMap<DataObjectId, usize>,
+}
+
+impl RecordedSession {
+    fn new(id: SessionId, spec: SessionSpec) -> Self {
+        RecordedSession {
+            id,
+            spec,
+            tasks: Default::default(),
+            objects: Default::default(),
+            durations: Default::default(),
+            sizes: Default::default(),
+        }
+    }
+}
+
+/// Load all sessions with at least one submitted task from the event database.
+pub fn load_recorded_sessions(events_db: &Path) -> Result<Vec<RecordedSession>> {
+    if !events_db.exists() {
+        bail!("Event database {} does not exist", events_db.display());
+    }
+    let mut sessions: Vec<RecordedSession> = Vec::new();
+    let mut index: HashMap<SessionId, usize> = HashMap::new();
+    let event_types = [
+        "SessionNew",
+        "ClientSubmit",
+        "TaskFinished",
+        "ObjectFinished",
+    ];
+    for event in load_events_of_types(events_db, &event_types)? {
+        match event {
+            Event::SessionNew(e) => {
+                index.insert(e.session, sessions.len());
+                sessions.push(RecordedSession::new(e.session, e.spec));
+            }
+            Event::ClientSubmit(e) => {
+                // Sessions recovered after a restart log their submits again
+                for t in e.tasks {
+                    if let Some(&i) = index.get(&t.id.get_session_id()) {
+                        sessions[i].tasks.entry(t.id).or_insert(t);
+                    }
+                }
+                for o in e.dataobjs {
+                    if let Some(&i) = index.get(&o.id.get_session_id()) {
+                        sessions[i].objects.entry(o.id).or_insert(o);
+                    }
+                }
+            }
+            Event::TaskFinished(e) => {
+                if let (Some(&i), Some(duration)) =
+                    (index.get(&e.task.get_session_id()), e.info.duration)
+                {
+                    if e.info.error.is_empty() {
+                        sessions[i].durations.insert(e.task, duration);
+                    }
+                }
+            }
+            Event::DataObjectFinished(e) => {
+                if let Some(&i) = index.get(&e.dataobject.get_session_id()) {
+                    sessions[i].sizes.insert(e.dataobject, e.size);
+                }
+            }
+            _ => (),
+        }
+    }
+    sessions.retain(|s| !s.tasks.is_empty());
+    Ok(sessions)
+}
+
+/// Virtual cluster of identical governors.
+#[derive(Clone, Debug)]
+pub struct ClusterSpec {
+    pub governors: usize,
+    pub cpus: u32,
+    /// Memory of a governor in bytes, memory requirements of tasks are ignored when not set
+    pub memory: Option<u64>,
+    /// Network bandwidth of a governor in bytes per second
+    pub bandwidth: f64,
+}
+
+/// Results of a simulation.
+#[derive(Clone, Debug, Default)]
+pub struct SimulationReport {
+    pub tasks: usize,
+    /// Time from the submit to the end of the last task in seconds
+    pub makespan: f64,
+    /// Bytes fetched by governors (from other governors or from the server)
+    pub transferred: u64,
+    /// Cpu-seconds spent by running tasks
+    pub cpu_time: f64,
+    /// Cpu-seconds available in the cluster during the makespan
+    pub capacity: f64,
+}
+
+impl SimulationReport {
+    /// Fraction of the cluster cpu time used by tasks.
+    pub fn utilization(&self) -> f64 {
+        if self.capacity > 0f64 {
+            self.cpu_time / self.capacity
+        } else {
+            0f64
+        }
+    }
+
+    /// Add the report of a session that was simulated after the sessions of this report.
+    pub fn add(&mut self, other: &SimulationReport) {
+        self.tasks += other.tasks;
+        self.makespan += other.makespan;
+        self.transferred += other.transferred;
+        self.cpu_time += other.cpu_time;
+        self.capacity += other.capacity;
+    }
+}
+
+/// Replay the session on the virtual cluster with the given scheduler.
+///
+/// All tasks are submitted at time zero and take their recorded durations (zero when not
+/// recorded). Before a task starts, its inputs missing on the governor are fetched one by one
+/// with the governor bandwidth; transfers of different governors do not interfere.
+/// Requirements that refer to the real cluster (named resources, labels and preferred
+/// governors) are ignored.
+pub fn simulate(
+    session: &RecordedSession,
+    cluster: &ClusterSpec,
+    scheduler: &mut Scheduler,
+) -> Result<SimulationReport> {
+    let mut sim = Simulation::new(session, cluster)?;
+    loop {
+        let scheduled = scheduler.schedule(&mut sim.graph, &sim.updates);
+        sim.updates.clear();
+        for tref in &scheduled.tasks {
+            sim.start_task(tref);
+        }
+
+        let next = sim
+            .running
+            .iter()
+            .enumerate()
+            .min_by(|a, b| (a.1).0.partial_cmp(&(b.1).0).unwrap())
+            .map(|(i, _)| i);
+        match next {
+            Some(i) => {
+                let (time, tref, wref) = sim.running.swap_remove(i);
+                sim.now = time;
+                sim.finish_task(&tref, &wref);
+            }
+            None => break,
+        }
+    }
+    scheduler.clear_session(&sim.session_ref);
+
+    let unfinished = sim.session_ref.get().unfinished_tasks;
+    if unfinished > 0 {
+        bail!(
+            "{} task(s) of session {} cannot be scheduled on the virtual cluster",
+            unfinished,
+            session.id
+        );
+    }
+    let mut report = sim.report;
+    report.tasks = session.tasks.len();
+    report.makespan = sim.now;
+    report.capacity = sim.now * (cluster.governors as f64) * (cluster.cpus as f64);
+    Ok(report)
+}
+
+struct Simulation<'a> {
+    session: &'a RecordedSession,
+    cluster: &'a ClusterSpec,
+    graph: Graph,
+    session_ref: SessionRef,
+    updates: UpdatedIn,
+    /// Simulated time in seconds
+    now: f64,
+    /// Running tasks with their finish times
+    running: Vec<(f64, TaskRef, GovernorRef)>,
+    /// Times when objects are (or will be) available on governors
+    available: HashMap<(DataObjectId, GovernorId), f64>,
+    report: SimulationReport,
+}
+
+impl<'a> Simulation<'a> {
+    fn new(session: &'a RecordedSession, cluster: &'a ClusterSpec) -> Result<Self> {
+        let mut graph = Graph::new(session.id);
+        let mut updates = UpdatedIn::default();
+
+        for i in 0..cluster.governors {
+            let address = SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::new(10, (i >> 16) as u8, (i >> 8) as u8, i as u8)),
+                7211,
+            );
+            let resources = Resources {
+                cpus: cluster.cpus,
+                memory: cluster.memory.unwrap_or(0),
+                named: Default::default(),
+            };
+            let wref = GovernorRef::new(address, None, resources, Default::default());
+            graph.governors.insert(address, wref.clone());
+            updates.new_governors.insert(wref);
+        }
+
+        let client_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0);
+        let client = ClientRef::new(client_address);
+        graph.clients.insert(client_address, client.clone());
+        let session_ref = SessionRef::new(session.id, &client, session.spec.clone());
+        graph.sessions.insert(session.id, session_ref.clone());
+
+        let produced: HashSet<DataObjectId> = session
+            .tasks
+            .values()
+            .flat_map(|t| t.outputs.iter().cloned())
+            .collect();
+        let mut object_ids: Vec<_> = session.objects.keys().cloned().collect();
+        object_ids.sort();
+        for id in object_ids {
+            let oref = DataObjectRef::new(&session_ref, session.objects[&id].clone(), false, None);
+            if !produced.contains(&id) {
+                // Uploaded by the client, located on the server
+                let mut o = oref.get_mut();
+                o.state = DataObjectState::Finished;
+                o.info.size = Some(session.sizes.get(&id).cloned().unwrap_or(0));
+            }
+            graph.objects.insert(id, oref.clone());
+            updates.new_objects.insert(oref);
+        }
+
+        let mut task_ids: Vec<_> = session.tasks.keys().cloned().collect();
+        task_ids.sort();
+        for id in task_ids {
+            let spec = virtual_task_spec(&session.tasks[&id], cluster);
+            let (inputs, outputs) = {
+                let find_object = |object_id: &DataObjectId| {
+                    graph.objects.get(object_id).cloned().ok_or_else(|| {
+                        Error::from(format!(
+                            "Object {} of task {} is not in the log",
+                            object_id, id
+                        ))
+                    })
+                };
+                let inputs = spec
+                    .inputs
+                    .iter()
+                    .map(|i| find_object(&i.id))
+                    .collect::<Result<Vec<_>>>()?;
+                let outputs = spec
+                    .outputs
+                    .iter()
+                    .map(|o| find_object(o))
+                    .collect::<Result<Vec<_>>>()?;
+                (inputs, outputs)
+            };
+            let tref = TaskRef::new(&session_ref, spec, inputs, outputs)?;
+            graph.tasks.insert(id, tref.clone());
+            updates.new_tasks.insert(tref);
+        }
+
+        Ok(Simulation {
+            session,
+            cluster,
+            graph,
+            session_ref,
+            updates,
+            now: 0f64,
+            running: Vec::new(),
+            available: HashMap::new(),
+            report: Default::default(),
+        })
+    }
+
+    fn duration(&self, task_id: TaskId) -> f32 {
+        self.session
+            .durations
+            .get(&task_id)
+            .cloned()
+            .unwrap_or(0f32)
+    }
+
+    /// Start a task scheduled by the scheduler, fetching its missing inputs first.
+    fn start_task(&mut self, tref: &TaskRef) {
+        let wref = tref.get().scheduled.clone().unwrap();
+        let governor_id = wref.get_id();
+        let duration = self.duration(tref.get().id());
+        let mut t = tref.get_mut();
+
+        let mut fetched = self.now;
+        let mut inputs_ready = self.now;
+        for oref in &t.inputs {
+            let key = (oref.get().id(), governor_id);
+            if let Some(&time) = self.available.get(&key) {
+                inputs_ready = inputs_ready.max(time);
+                continue;
+            }
+            let size = oref.get().info.size.unwrap_or(0);
+            fetched += size as f64 / self.cluster.bandwidth;
+            self.report.transferred += size as u64;
+            self.available.insert(key, fetched);
+            oref.get_mut().located.insert(wref.clone());
+            wref.get_mut().located_objects.insert(oref.clone());
+        }
+
+        t.state = TaskState::Running;
+        t.assigned = Some(wref.clone());
+        {
+            let mut w = wref.get_mut();
+            w.scheduled_ready_tasks.remove(tref);
+            w.assigned_tasks.insert(tref.clone());
+        }
+        self.report.cpu_time += duration as f64 * t.spec.resources.cpus() as f64;
+        let finish = inputs_ready.max(fetched) + duration as f64;
+        self.running.push((finish, tref.clone(), wref));
+        self.updates.tasks.insert(tref.clone());
+    }
+
+    /// Finish a running task and make its consumers ready.
+    fn finish_task(&mut self, tref: &TaskRef, wref: &GovernorRef) {
+        let duration = self.duration(tref.get().id());
+        {
+            let mut t = tref.get_mut();
+            t.state = TaskState::Finished;
+            t.info.duration = Some(duration);
+            t.scheduled = None;
+            t.assigned = None;
+            t.session.get_mut().task_finished();
+            let mut w = wref.get_mut();
+            w.scheduled_tasks.remove(tref);
+            w.avoiding_tasks.remove(tref);
+            w.assigned_tasks.remove(tref);
+            w.active_resources.remove(&t.spec.resources);
+        }
+        self.updates.tasks.insert(tref.clone());
+
+        let governor_id = wref.get_id();
+        for oref in &tref.get().outputs {
+            let id = oref.get().id();
+            {
+                let mut o = oref.get_mut();
+                o.state = DataObjectState::Finished;
+                o.info.size = Some(self.session.sizes.get(&id).cloned().unwrap_or(0));
+                o.located.insert(wref.clone());
+            }
+            wref.get_mut().located_objects.insert(oref.clone());
+            self.available.insert((id, governor_id), self.now);
+            self.updates
+                .objects
+                .entry(oref.clone())
+                .or_insert(Default::default())
+                .insert(wref.clone());
+            for cref in &oref.get().consumers {
+                let mut c = cref.get_mut();
+                if c.waiting_for.remove(oref) && c.waiting_for.is_empty() {
+                    c.state = TaskState::Ready;
+                    self.updates.tasks.insert(cref.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Drop the requirements of the task that refer to the real cluster.
+fn virtual_task_spec(spec: &TaskSpec, cluster: &ClusterSpec) -> TaskSpec {
+    let mut spec = spec.clone();
+    spec.resources.named.clear();
+    if cluster.memory.is_none() {
+        spec.resources.memory = 0;
+    }
+    if let Some(ref mut placement) = spec.placement {
+        placement.labels.clear();
+        placement.preferred_governor = None;
+    }
+    spec
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use server::scheduler::ReactiveScheduler;
+
+    fn object(session: SessionId, id: i32) -> ObjectSpec {
+        ObjectSpec {
+            id: DataObjectId::new(session, id),
+            label: Default::default(),
+            user: Default::default(),
+            data_type: DataType::Blob,
+            content_type: "".into(),
+            compression: None,
+            replicas: None,
+        }
+    }
+
+    #[test]
+    fn simulate_chain() {
+        let spec = SessionSpec {
+            name: "test".to_string(),
+            ..Default::default()
+        };
+        let mut session = RecordedSession::new(1, spec);
+        let o1 = object(1, 1);
+        let o2 = object(1, 2);
+        let o3 = object(1, 3);
+        let tasks = vec![
+            // a -> o1 -> b -> o2, c -> o3
+            (10, vec![], vec![o1.id], 2.0),
+            (11, vec![o1.id], vec![o2.id], 3.0),
+            (12, vec![], vec![o3.id], 4.0),
+        ];
+        for (id, inputs, outputs, duration) in tasks {
+            let id = TaskId::new(1, id);
+            let inputs = inputs
+                .into_iter()
+                .map(|id| TaskSpecInput {
+                    id,
+                    label: Default::default(),
+                })
+                .collect();
+            session.tasks.insert(
+                id,
+                TaskSpec {
+                    id,
+                    inputs,
+                    outputs,
+                    task_type: "buildin/sleep".to_string(),
+                    ..Default::default()
+                },
+            );
+            session.durations.insert(id, duration);
+        }
+        for o in vec![o1, o2, o3] {
+            session.sizes.insert(o.id, 1 << 20);
+            session.objects.insert(o.id, o);
+        }
+
+        let cluster = ClusterSpec {
+            governors: 2,
+            cpus: 1,
+            memory: None,
+            bandwidth: (1 << 20) as f64,
+        };
+        let mut scheduler = ReactiveScheduler::default();
+        let report = simulate(&session, &cluster, &mut scheduler).unwrap();
+        // b runs after a on the same governor while c occupies the other one
+        assert_eq!(report.tasks, 3);
+        assert_eq!(report.makespan, 5.0);
+        assert_eq!(report.transferred, 0);
+        assert_eq!(report.utilization(), 0.9);
+
+        let cluster = ClusterSpec {
+            governors: 1,
+            cpus: 0,
+            ..cluster
+        };
+        assert!(simulate(&session, &cluster, &mut scheduler).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::time::{Duration, Instant};
@@ -11,14 +11,18 @@ use rain_core::{errors::*, sys::*, types::*, utils::*};
 use tokio_core::net::{TcpListener, TcpStream};
 use tokio_core::reactor::Handle;
 
-use common::new_rpc_system;
-use server::graph::{ClientRef, DataObjectRef, DataObjectState, GovernorRef, Graph, SessionRef,
-                    TaskRef, TaskState};
+use common::{accept_stream, new_rpc_system, TlsConfig};
+use server::auth::AuthTokens;
+use server::graph::{
+    new_session_token, ClientRef, DataObjectRef, DataObjectState, GovernorRef, Graph, SessionRef,
+    TaskRef, TaskState,
+};
 use server::http::RequestHandler;
 use server::logging::logger::Logger;
 use server::logging::sqlite_logger::SQLiteLogger;
+use server::recovery::{DataStore, RecoveredSession};
 use server::rpc::ServerBootstrapImpl;
-use server::scheduler::{ReactiveScheduler, UpdatedIn};
+use server::scheduler::{can_run_on, schedule_task, Scheduler, UpdatedIn, UpdatedOut};
 use server::testmode;
 use wrapped::WrappedRcRefCell;
 
@@ -27,6 +31,19 @@ const LOGGING_INTERVAL: u64 = 1; // Logging interval in seconds
 /// How long should be ID from governor ignored when it is task/object is unassigned
 const IGNORE_ID_TIME_SECONDS: u64 = 30;
 
+/// Interval between rebalancing passes in milliseconds
+const REBALANCE_INTERVAL_MS: u64 = 1000;
+
+/// A task moved by rebalancing is not moved again for this time
+const REBALANCE_COOLDOWN_SECONDS: u64 = 30;
+
+/// Recovered sessions without a grace period are closed when no client attaches them
+/// within this time
+const RECOVERED_SESSION_EXPIRY_SECONDS: u64 = 3600;
+
+/// Heartbeats are sent to governors this many times per heartbeat timeout
+const HEARTBEATS_PER_TIMEOUT: u32 = 3;
+
 pub struct State {
     // Contained objects
     pub(super) graph: Graph,
@@ -45,7 +62,35 @@ pub struct State {
     /// Governors that will checked by reactor in the next turn()
     underload_governors: RcSet<GovernorRef>,
 
-    scheduler: ReactiveScheduler,
+    scheduler: Box<Scheduler>,
+
+    /// Move waiting tasks to idle governors (see `rebalance`)
+    rebalance: bool,
+
+    /// Tasks that were waiting on their governors at the last rebalancing pass
+    rebalance_waiting: RcSet<TaskRef>,
+
+    last_rebalance: Instant,
+
+    /// Objects whose spec asks for more than one replica (see `check_replicas`)
+    replicated_objects: RcSet<DataObjectRef>,
+
+    /// Replicated objects that may have fewer copies than requested; a subset of
+    /// `replicated_objects` checked by `check_replicas`
+    underreplicated_objects: RcSet<DataObjectRef>,
+
+    /// A governor that does not answer heartbeats for this time is declared lost;
+    /// `None` disables heartbeats
+    heartbeat_timeout: Option<Duration>,
+
+    last_heartbeat: Instant,
+
+    /// Tokens accepted at the registration of clients and governors;
+    /// `None` disables authentication
+    auth: Option<AuthTokens>,
+
+    /// TLS of client and governor connections; `None` for plain TCP
+    tls: Option<TlsConfig>,
 
     // If testing_mode is true, then __test attributes are interpreted
     test_mode: bool,
@@ -59,61 +104,617 @@ pub struct State {
 
     /// Listening port for HTTP interface
     http_listen_address: SocketAddr,
+
+    /// Storage of client data, present when the server runs in recovery mode
+    pub(super) data_store: Option<DataStore>,
+
+    /// Placeholder owner of sessions recovered from the event log or detached
+    /// from their disconnected clients until they are attached by a client.
+    placeholder_client: Option<ClientRef>,
 }
 
 impl State {
+    /// Check the token of a connection registering as `role` ("client" or "governor").
+    /// Rejected connections are logged as events.
+    /// Check the token of a connection, returns the name of the token
+    /// (`None` when the authentication is disabled).
+    pub fn authenticate(
+        &mut self,
+        address: &SocketAddr,
+        role: &str,
+        token: &str,
+    ) -> Result<Option<String>> {
+        let name = match self.auth {
+            Some(ref auth) => auth.check(token).map(|name| name.to_string()),
+            None => return Ok(None),
+        };
+        match name {
+            Some(name) => {
+                log::info!(
+                    "Connection {} authenticated as {} ({})",
+                    address,
+                    role,
+                    name
+                );
+                Ok(Some(name))
+            }
+            None => {
+                log::warn!("Authentication of {} connection {} failed", role, address);
+                self.logger.add_auth_failed_event(*address, role);
+                bail!("Authentication failed")
+            }
+        }
+    }
+
     /// Add new governor, register it in the graph
     pub fn add_governor(
         &mut self,
         address: SocketAddr,
         control: Option<::rain_core::governor_capnp::governor_control::Client>,
         resources: Resources,
+        labels: HashMap<String, String>,
     ) -> Result<GovernorRef> {
         log::debug!("New governor {}", address);
         if self.graph.governors.contains_key(&address) {
             bail!("State already contains governor {}", address);
         }
-        let w = GovernorRef::new(address, control, resources);
+        let w = GovernorRef::new(address, control, resources, labels);
         self.graph.governors.insert(w.get_id(), w.clone());
         self.underload_governors.insert(w.clone());
+        self.updates.new_governors.insert(w.clone());
         self.logger.add_new_governor_event(w.get_id());
         Ok(w)
     }
 
     /// Remove the governor from the graph, forcefully unassigning all tasks and objects.
-    /// TODO: better specs and context of governor removal
-    pub fn remove_governor(&mut self, _governor: &GovernorRef) -> Result<()> {
-        unimplemented!() /*
-            pub fn delete(self, graph: &mut Graph) {
-        log::debug!("Deleting governor {}", self.get_id());
-        // remove from objects
-        for o in self.get_mut().assigned_objects.iter() {
-            assert!(o.get_mut().assigned.remove(&self));
+    ///
+    /// The governor is assumed to be inaccessible, so no RPC calls are sent to it.
+    /// Tasks scheduled or running on the governor go back to `Ready` (or stay `NotAssigned`)
+    /// and are passed to the scheduler again. Finished objects that were located only on
+    /// the governor are lost; if they are still needed, they are recomputed.
+    pub fn remove_governor(
+        &mut self,
+        governor: &GovernorRef,
+        reason: events::GovernorRemovedReason,
+        message: String,
+    ) -> Result<()> {
+        let governor_id = governor.get_id();
+        log::info!("Removing governor {}: {}", governor_id, message);
+        if self.graph.governors.remove(&governor_id).is_none() {
+            bail!("Governor {} is not registered", governor_id);
         }
-        for o in self.get_mut().located_objects.iter() {
-            assert!(o.get_mut().located.remove(&self));
+        self.underload_governors.remove(governor);
+        self.updates.new_governors.remove(governor);
+
+        // Return the tasks to the scheduler
+        let tasks: Vec<TaskRef> = governor.get().scheduled_tasks.iter().cloned().collect();
+        for tref in tasks {
+            {
+                let mut t = tref.get_mut();
+                t.assigned = None;
+                t.scheduled = None;
+                if t.state == TaskState::Assigned || t.state == TaskState::Running {
+                    t.state = TaskState::Ready;
+                }
+                log::debug!("Task {} returned to the scheduler as {:?}", t.id(), t.state);
+            }
+            self.updates.tasks.insert(tref);
         }
-        // remove from tasks
-        for t in self.get_mut().assigned_tasks.iter() {
-            t.get_mut().assigned = None;
+
+        // Unlink the objects, collect those that were lost with the governor
+        let objects: RcSet<DataObjectRef> = {
+            let w = governor.get();
+            w.assigned_objects
+                .iter()
+                .chain(w.scheduled_objects.iter())
+                .cloned()
+                .collect()
+        };
+        let mut lost_objects = Vec::new();
+        for oref in objects {
+            self.replicas_changed(&oref);
+            let mut o = oref.get_mut();
+            o.assigned.remove(governor);
+            o.located.remove(governor);
+            o.scheduled.remove(governor);
+            if o.state == DataObjectState::Finished && o.located.is_empty() && o.data.is_none() {
+                lost_objects.push(oref.clone());
+            }
+        }
+
+        {
+            let mut w = governor.get_mut();
+            w.scheduled_tasks.clear();
+            w.scheduled_ready_tasks.clear();
+            w.avoiding_tasks.clear();
+            w.assigned_tasks.clear();
+            w.active_resources = Resources::empty();
+            w.located_objects.clear();
+            w.assigned_objects.clear();
+            w.scheduled_objects.clear();
         }
-        for t in self.get_mut().scheduled_tasks.iter() {
-            t.get_mut().scheduled = None;
+
+        for oref in lost_objects {
+            self.object_lost(&oref);
         }
-        // remove from graph
-        graph.governors.remove(&self.get().id).unwrap();
-        // assert that we hold the last reference, then drop it
-        assert_eq!(self.get_num_refs(), 1);
-        */
+
+        let last_monitoring = governor.get_mut().last_monitoring.take();
+        self.logger
+            .add_governor_removed_event(governor_id, reason, message, last_monitoring);
+        Ok(())
+    }
+
+    /// Send heartbeats to the governors and remove the governors that did not answer
+    /// them within the heartbeat timeout. The removal is the same as for a lost
+    /// connection.
+    pub fn check_heartbeats(&mut self) {
+        let timeout = match self.heartbeat_timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        let now = Instant::now();
+        if now < self.last_heartbeat + timeout / HEARTBEATS_PER_TIMEOUT {
+            return;
+        }
+        self.last_heartbeat = now;
+
+        let mut lost = Vec::new();
+        for wref in self.graph.governors.values() {
+            if wref.get().last_heartbeat + timeout < now {
+                lost.push(wref.clone());
+                continue;
+            }
+            if let Some(ref control) = wref.get().control {
+                let wref2 = wref.clone();
+                self.handle.spawn(
+                    control
+                        .heartbeat_request()
+                        .send()
+                        .promise
+                        .map(move |_| wref2.get_mut().last_heartbeat = Instant::now())
+                        .map_err(|e| log::debug!("Heartbeat failed: {:?}", e)),
+                );
+            }
+        }
+
+        for wref in lost {
+            let governor_id = wref.get_id();
+            log::error!(
+                "Governor {} did not answer heartbeats for {} s",
+                governor_id,
+                timeout.as_secs()
+            );
+            self.remove_governor(
+                &wref,
+                events::GovernorRemovedReason::Lost,
+                format!("No heartbeat for {} s", timeout.as_secs()),
+            )
+            .unwrap();
+            // The governor may be only partitioned, ask it to terminate
+            if let Some(ref control) = wref.get().control {
+                self.handle.spawn(
+                    control
+                        .decommission_request()
+                        .send()
+                        .promise
+                        .map(|_| ())
+                        .map_err(move |e| {
+                            log::debug!("Decommission of governor {}: {:?}", governor_id, e)
+                        }),
+                );
+            }
+        }
+    }
+
+    /// Start draining the governor. The governor gets no new tasks, its tasks that
+    /// are not running yet are returned to the scheduler and the governor is
+    /// decommissioned by `check_drained_governors` when it is no longer needed.
+    pub fn drain_governor(&mut self, governor_id: &GovernorId) -> Result<()> {
+        let governor = match self.graph.governors.get(governor_id) {
+            Some(w) => w.clone(),
+            None => bail!("Governor {} is not registered", governor_id),
+        };
+        if governor.get().draining {
+            return Ok(());
+        }
+        log::info!("Draining governor {}", governor_id);
+        governor.get_mut().draining = true;
+        // Copies on draining governors do not count as replicas
+        let objects: Vec<DataObjectRef> =
+            governor.get().scheduled_objects.iter().cloned().collect();
+        for oref in objects {
+            self.replicas_changed(&oref);
+        }
+
+        let tasks: Vec<TaskRef> = governor.get().scheduled_tasks.iter().cloned().collect();
+        for tref in tasks {
+            if tref.get().state == TaskState::Running {
+                continue;
+            }
+            log::debug!("Task {} returned to the scheduler", tref.get().id());
+            tref.unschedule();
+            for oref in tref.get().outputs.clone() {
+                oref.unschedule();
+            }
+            if tref.get().assigned.is_some() {
+                self.unassign_task(&tref);
+            }
+            self.updates.tasks.insert(tref);
+        }
+        governor.check_consistency_opt().unwrap(); // non-recoverable
+        Ok(())
+    }
+
+    /// Copy the needed objects away from the draining governors and decommission
+    /// the governors that have no running tasks and no needed objects left.
+    pub fn check_drained_governors(&mut self) {
+        let draining: Vec<GovernorRef> = self
+            .graph
+            .governors
+            .values()
+            .filter(|w| w.get().draining)
+            .cloned()
+            .collect();
+        if draining.is_empty() {
+            return;
+        }
+        let mut targets: Vec<GovernorRef> = self
+            .graph
+            .governors
+            .values()
+            .filter(|w| !w.get().draining)
+            .cloned()
+            .collect();
+        targets.sort_by_key(|w| w.get_id());
+
+        for wref in draining {
+            if !wref.get().scheduled_tasks.is_empty() {
+                continue;
+            }
+            let mut drained = true;
+            let objects: Vec<DataObjectRef> = wref.get().located_objects.iter().cloned().collect();
+            for oref in objects {
+                let kept_elsewhere = {
+                    let o = oref.get();
+                    !o.is_needed()
+                        || o.data.is_some()
+                        || o.located.iter().any(|w| !w.get().draining)
+                };
+                if kept_elsewhere {
+                    continue;
+                }
+                drained = false;
+                if oref.get().scheduled.iter().any(|w| !w.get().draining) {
+                    // Copying is in progress
+                    continue;
+                }
+                // Move the schedule of the object to the least loaded governor
+                let target = match targets
+                    .iter()
+                    .min_by_key(|w| w.get().assigned_objects.len())
+                {
+                    Some(w) => w.clone(),
+                    None => continue,
+                };
+                log::debug!(
+                    "Copying object {} from draining governor {} to {}",
+                    oref.get().id(),
+                    wref.get_id(),
+                    target.get_id()
+                );
+                oref.get_mut().scheduled.remove(&wref);
+                wref.get_mut().scheduled_objects.remove(&oref);
+                oref.get_mut().scheduled.insert(target.clone());
+                target.get_mut().scheduled_objects.insert(oref.clone());
+                self.update_object_assignments(&oref, Some(&target));
+            }
+            if !drained {
+                continue;
+            }
+
+            let governor_id = wref.get_id();
+            self.remove_governor(
+                &wref,
+                events::GovernorRemovedReason::Drained,
+                "Drained".to_string(),
+            )
+            .unwrap();
+            if let Some(ref control) = wref.get().control {
+                self.handle.spawn(
+                    control
+                        .decommission_request()
+                        .send()
+                        .promise
+                        .map(|_| ())
+                        .map_err(move |e| {
+                            log::debug!("Decommission of governor {}: {:?}", governor_id, e)
+                        }),
+                );
+            }
+        }
+    }
+
+    /// Copy the finished objects that are still needed to more governors until
+    /// they are scheduled to as many non-draining governors as their spec asks for.
+    /// Holders that disappear are replaced here as well.
+    pub fn check_replicas(&mut self) {
+        for (oref, target) in self.place_replicas() {
+            self.update_object_assignments(&oref, Some(&target));
+        }
+    }
+
+    /// Schedule the missing copies of the underreplicated objects, return the scheduled
+    /// copies. Objects that still miss some copies (e.g. there are not enough governors)
+    /// are checked again in the next turn.
+    fn place_replicas(&mut self) -> Vec<(DataObjectRef, GovernorRef)> {
+        let mut placed = Vec::new();
+        if self.underreplicated_objects.is_empty() {
+            return placed;
+        }
+        let mut targets: Vec<GovernorRef> = self
+            .graph
+            .governors
+            .values()
+            .filter(|w| !w.get().draining && !w.get().disk_pressure)
+            .cloned()
+            .collect();
+        targets.sort_by_key(|w| w.get_id());
+
+        let objects: Vec<DataObjectRef> = self.underreplicated_objects.iter().cloned().collect();
+        for oref in objects {
+            let missing = {
+                let o = oref.get();
+                if o.state != DataObjectState::Finished || !o.is_needed() {
+                    0
+                } else {
+                    // Copies that are in progress are counted as well
+                    let copies = o.scheduled.iter().filter(|w| !w.get().draining).count();
+                    (o.spec.replicas.unwrap_or(1) as usize).saturating_sub(copies)
+                }
+            };
+            let mut added = 0;
+            while added < missing {
+                let target = match targets
+                    .iter()
+                    .filter(|w| !oref.get().scheduled.contains(*w))
+                    .min_by_key(|w| w.get().assigned_objects.len())
+                {
+                    Some(w) => w.clone(),
+                    None => break,
+                };
+                log::debug!(
+                    "Copying object {} to governor {} as a replica",
+                    oref.get().id(),
+                    target.get_id()
+                );
+                oref.get_mut().scheduled.insert(target.clone());
+                target.get_mut().scheduled_objects.insert(oref.clone());
+                placed.push((oref.clone(), target));
+                added += 1;
+            }
+            if added == missing {
+                self.underreplicated_objects.remove(&oref);
+            }
+        }
+        placed
+    }
+
+    /// Let `check_replicas` check the copies of the object if it is replicated.
+    fn replicas_changed(&mut self, oref: &DataObjectRef) {
+        if self.replicated_objects.contains(oref) {
+            self.underreplicated_objects.insert(oref.clone());
+        }
+    }
+
+    /// Release finished objects from a governor that runs short of disk space and
+    /// return the ids of the released objects.
+    ///
+    /// See `DataObject::can_evict_from` for the objects that are released. Only objects
+    /// that are not needed anymore may lose their last copy; they are removed.
+    pub fn evict_objects(
+        &mut self,
+        governor: &GovernorRef,
+        objects: Vec<DataObjectRef>,
+    ) -> Vec<DataObjectId> {
+        let mut evicted = Vec::new();
+        for oref in objects {
+            if !oref.get().can_evict_from(governor) {
+                continue;
+            }
+            log::debug!(
+                "Evicting object {} from governor {}",
+                oref.get().id(),
+                governor.get_id()
+            );
+            oref.get_mut().scheduled.remove(governor);
+            governor.get_mut().scheduled_objects.remove(&oref);
+            // Keep the remaining copy scheduled, so it is not removed as superfluous
+            let other = oref.get().located.iter().find(|w| *w != governor).cloned();
+            if let Some(wref) = other {
+                if oref.get().scheduled.is_empty() {
+                    oref.get_mut().scheduled.insert(wref.clone());
+                    wref.get_mut().scheduled_objects.insert(oref.clone());
+                }
+            }
+
+            self.send_unassign_object(&oref, governor);
+            {
+                let mut o = oref.get_mut();
+                o.assigned.remove(governor);
+                o.located.remove(governor);
+            }
+            {
+                let mut w = governor.get_mut();
+                w.assigned_objects.remove(&oref);
+                w.located_objects.remove(&oref);
+            }
+            evicted.push(oref.get().id());
+            self.replicas_changed(&oref);
+            if oref.get().located.is_empty() && oref.get().data.is_none() {
+                self.purge_object(&oref);
+                oref.get_mut().set_state(DataObjectState::Removed);
+            }
+            oref.check_consistency_opt().unwrap(); // non-recoverable
+        }
+        governor.check_consistency_opt().unwrap(); // non-recoverable
+        evicted
+    }
+
+    /// Handle a finished object whose last copy has disappeared.
+    /// Objects that are not needed anymore are simply removed, objects that are still needed
+    /// are recomputed. The session is failed only when the recomputation is not possible.
+    fn object_lost(&mut self, oref: &DataObjectRef) {
+        if oref.get().state != DataObjectState::Finished {
+            // Already handled, e.g. by a failed session or a restarted producer
+            return;
+        }
+        let session = oref.get().session.clone();
+        if oref.get().is_needed() {
+            if session.get().is_failed() {
+                return;
+            }
+            log::warn!("Object {} was lost, recomputing", oref.get().id());
+            if let Err(e) = self.recompute_object(oref) {
+                let producer_id = oref
+                    .get()
+                    .producer
+                    .as_ref()
+                    .map(|p| p.get().id())
+                    .unwrap_or_else(TaskId::invalid);
+                let cause = format!(
+                    "Object {} was lost together with its governor: {}",
+                    oref.get().id(),
+                    e
+                );
+                self.fail_session(&session, cause, String::new(), producer_id)
+                    .unwrap();
+            }
+        } else {
+            log::debug!("Object {} was lost, but it is not needed", oref.get().id());
+            self.purge_object(oref);
+            oref.get_mut().set_state(DataObjectState::Removed);
+        }
+    }
+
+    /// Make a lost or removed object available again, restarting its producer (and
+    /// recursively the producers of its missing inputs) when needed.
+    /// Objects uploaded by the client are served from the server again.
+    fn recompute_object(&mut self, oref: &DataObjectRef) -> Result<()> {
+        if oref.get().data.is_some() {
+            let mut o = oref.get_mut();
+            if o.state == DataObjectState::Removed {
+                o.set_state(DataObjectState::Finished);
+            }
+            return Ok(());
+        }
+        let producer = match oref.get().producer {
+            Some(ref p) => p.clone(),
+            None => bail!("Object {} has no producer", oref.get().id()),
+        };
+        if producer.get().state == TaskState::Finished {
+            self.restart_task(&producer)?;
+        }
+        Ok(())
+    }
+
+    /// Return a finished task to the scheduler to compute its outputs again.
+    ///
+    /// All outputs of the task become `Unfinished` (any remaining copies are unassigned) and
+    /// their unfinished consumers go back to waiting for them. Inputs that are no longer
+    /// available are recomputed recursively.
+    fn restart_task(&mut self, tref: &TaskRef) -> Result<()> {
+        assert_eq!(tref.get().state, TaskState::Finished);
+        log::info!(
+            "Restarting task {} to recompute its outputs",
+            tref.get().id()
+        );
+        let outputs = tref.get().outputs.clone();
+
+        // Stop the consumers while the outputs are still finished
+        let mut consumers = Vec::new();
+        for oref in &outputs {
+            for cref in oref.get().consumers.iter() {
+                if cref.get().state != TaskState::Finished {
+                    consumers.push(cref.clone());
+                }
+            }
+        }
+        for cref in &consumers {
+            cref.unschedule();
+            if cref.get().assigned.is_some() {
+                self.unassign_task(cref);
+            }
+        }
+
+        {
+            let mut t = tref.get_mut();
+            t.state = TaskState::NotAssigned;
+            t.info = Default::default();
+            t.session.get_mut().unfinished_tasks += 1;
+        }
+
+        // Invalidate the outputs, dropping any remaining copies
+        for oref in &outputs {
+            oref.unschedule();
+            let assigned = oref.get().assigned.clone();
+            for wref in assigned {
+                self.send_unassign_object(oref, &wref);
+                wref.get_mut().assigned_objects.remove(oref);
+                wref.get_mut().located_objects.remove(oref);
+            }
+            let mut o = oref.get_mut();
+            o.assigned.clear();
+            o.located.clear();
+            o.set_state(DataObjectState::Unfinished);
+            o.info = Default::default();
+        }
+        for cref in consumers {
+            {
+                let mut c = cref.get_mut();
+                for oref in &outputs {
+                    if c.inputs.contains(oref) {
+                        c.waiting_for.insert(oref.clone());
+                    }
+                }
+                c.state = TaskState::NotAssigned;
+            }
+            self.updates.tasks.insert(cref);
+        }
+
+        // Make the inputs available again
+        let inputs = tref.get().inputs.clone();
+        for iref in inputs {
+            iref.get_mut().need_by.insert(tref.clone());
+            let available = {
+                let i = iref.get();
+                match i.state {
+                    DataObjectState::Unfinished => true,
+                    DataObjectState::Finished => i.data.is_some() || !i.located.is_empty(),
+                    DataObjectState::Removed => false,
+                }
+            };
+            if !available {
+                self.recompute_object(&iref)?;
+            }
+            if iref.get().state == DataObjectState::Unfinished {
+                tref.get_mut().waiting_for.insert(iref.clone());
+            }
+        }
+
+        if tref.get().waiting_for.is_empty() {
+            tref.get_mut().state = TaskState::Ready;
+        }
+        self.updates.tasks.insert(tref.clone());
+        Ok(())
     }
 
-    /// Add new client, register it in the graph
-    pub fn add_client(&mut self, address: SocketAddr) -> Result<ClientRef> {
+    pub fn add_client(&mut self, address: SocketAddr, user: Option<String>) -> Result<ClientRef> {
         log::debug!("New client {}", address);
         if self.graph.clients.contains_key(&address) {
             bail!("State already contains client {}", address);
         }
         let c = ClientRef::new(address);
+        c.get_mut().user = user;
         self.graph.clients.insert(c.get().id, c.clone());
         self.logger.add_new_client_event(c.get().id);
         Ok(c)
@@ -122,7 +723,7 @@ impl State {
     /// Remove Client and its (owned) sessions. Called on client disconnect,
     /// so assume the client is inaccesible.
     pub fn remove_client(&mut self, client: &ClientRef) -> Result<()> {
-        // remove owned sessions
+        // remove owned sessions, keep those that survive their client
         let sessions = client
             .get()
             .sessions
@@ -130,7 +731,11 @@ impl State {
             .map(|x| x.clone())
             .collect::<Vec<_>>();
         for s in sessions {
-            self.remove_session(&s)?;
+            if s.get().spec.survives_client() {
+                self.detach_session(&s);
+            } else {
+                self.remove_session(&s)?;
+            }
         }
         // remove from graph
         self.graph.clients.remove(&client.get_id()).unwrap();
@@ -141,10 +746,31 @@ impl State {
 
     /// Create a new session fr a client, register it in the graph.
     pub fn add_session(&mut self, client: &ClientRef, spec: SessionSpec) -> Result<SessionRef> {
+        if !(spec.weight.is_finite() && spec.weight > 0f32) {
+            bail!(
+                "Invalid session weight {}, it has to be positive",
+                spec.weight
+            );
+        }
+        if let Some(ref limits) = spec.limits {
+            if limits.cpus == Some(0) || limits.tasks == Some(0) {
+                bail!(
+                    "Invalid session limits {:?}, limits have to be positive",
+                    limits
+                );
+            }
+        }
         let s = SessionRef::new(self.graph.new_session_id(), client, spec.clone());
+        if spec.survives_client() {
+            let token = new_session_token()?;
+            if let Some(ref store) = self.data_store {
+                store.save_token(s.get_id(), &token)?;
+            }
+            s.get_mut().token = Some(token);
+        }
         self.graph.sessions.insert(s.get_id(), s.clone());
         self.logger
-            .add_new_session_event(s.get_id(), client.get().id, spec);
+            .add_new_session_event(s.get_id(), client.get().id, spec, client.get().user.clone());
         Ok(s)
     }
 
@@ -175,12 +801,265 @@ impl State {
         }
         let objects = s.get_mut().objects.clone();
         for o in objects {
-            o.get_mut().client_keep = false;
+            o.get_mut().set_client_keep(false);
             o.unschedule();
             self.remove_object(&o)?;
         }
         // Remove all finish hooks
         s.get_mut().finish_hooks.clear();
+        if let Some(ref store) = self.data_store {
+            store.remove_session(session_id);
+        }
+        Ok(())
+    }
+
+    /// Move a session recovered from the event log to the client.
+    /// Returns the highest task or object id used in the session.
+    pub fn attach_session(&mut self, client: &ClientRef, session: &SessionRef) -> Result<Id> {
+        let owner = session.get().client.clone();
+        if Some(&owner) != self.placeholder_client.as_ref() {
+            bail!("Session {} is owned by another client", session.get_id());
+        }
+        if session.get().user != client.get().user {
+            bail!("Session {} belongs to another user", session.get_id());
+        }
+        if session.get().token.is_some() {
+            bail!(
+                "Session {} has to be reattached with its token",
+                session.get_id()
+            );
+        }
+        log::info!(
+            "Session {} attached by client {}",
+            session.get_id(),
+            client.get_id()
+        );
+        Ok(self.move_session(client, session))
+    }
+
+    /// Move a session that survives its client to another client, possibly taking
+    /// it over from a client whose connection is not known to be broken yet.
+    /// Returns the highest task or object id used in the session.
+    pub fn reattach_session(
+        &mut self,
+        client: &ClientRef,
+        session: &SessionRef,
+        token: &str,
+    ) -> Result<Id> {
+        if session.get().token.as_ref().map(|t| t.as_str()) != Some(token) {
+            bail!("Invalid token for session {}", session.get_id());
+        }
+        log::info!(
+            "Session {} reattached by client {}",
+            session.get_id(),
+            client.get_id()
+        );
+        Ok(self.move_session(client, session))
+    }
+
+    /// Keep a session of a disconnected client under the placeholder client.
+    fn detach_session(&mut self, session: &SessionRef) {
+        log::info!(
+            "Session {} detached from client {}",
+            session.get_id(),
+            session.get().client.get_id()
+        );
+        if self.placeholder_client.is_none() {
+            self.placeholder_client = Some(ClientRef::new(self.listen_address));
+        }
+        let owner = self.placeholder_client.clone().unwrap();
+        self.move_session(&owner, session);
+        session.get_mut().detached_since = Some(Instant::now());
+    }
+
+    /// Close the detached sessions whose grace period has expired. Detached sessions
+    /// without a grace period (recovered sessions that do not survive their client)
+    /// expire after `RECOVERED_SESSION_EXPIRY_SECONDS`.
+    pub fn close_expired_sessions(&mut self) {
+        let owner = match self.placeholder_client {
+            Some(ref owner) => owner.clone(),
+            None => return,
+        };
+        let now = Instant::now();
+        let expired: Vec<SessionRef> = owner
+            .get()
+            .sessions
+            .iter()
+            .filter(|s| {
+                let s = s.get();
+                let grace = s
+                    .spec
+                    .grace_period
+                    .unwrap_or(RECOVERED_SESSION_EXPIRY_SECONDS);
+                match s.detached_since {
+                    Some(since) if !s.spec.persistent => since + Duration::from_secs(grace) <= now,
+                    _ => false,
+                }
+            })
+            .cloned()
+            .collect();
+        for session in expired {
+            log::info!("Detached session {} expired, closing", session.get_id());
+            if let Err(e) = self.remove_session(&session) {
+                log::error!("Closing session {} failed: {}", session.get_id(), e);
+            }
+        }
+    }
+
+    /// Move the session to the client.
+    /// Returns the highest task or object id used in the session.
+    fn move_session(&mut self, client: &ClientRef, session: &SessionRef) -> Id {
+        let owner = session.get().client.clone();
+        owner.get_mut().sessions.remove(session);
+        client.get_mut().sessions.insert(session.clone());
+        {
+            let mut s = session.get_mut();
+            s.client = client.clone();
+            s.detached_since = None;
+        }
+        session.get().max_id()
+    }
+
+    /// Rebuild the sessions that were not closed by the previous server instance.
+    /// A session that cannot be recovered is closed with an error and removed.
+    fn recover_sessions(&mut self, recovered: Vec<RecoveredSession>) {
+        for session in recovered {
+            let id = session.id;
+            if let Err(e) = self.recover_session(session) {
+                log::error!("Session {} cannot be recovered: {}", id, e);
+                let session = self.session_by_id(id).unwrap();
+                let cause = format!("Recovery failed: {}", e);
+                self.fail_session(&session, cause, String::new(), TaskId::invalid())
+                    .unwrap();
+                // No client knows about the failed session
+                self.remove_session(&session).unwrap();
+            }
+        }
+    }
+
+    /// Rebuild a session from its logged events.
+    ///
+    /// The session is owned by a placeholder client until it is attached. The outputs of
+    /// finished tasks are considered removed; those that are still needed are recomputed,
+    /// everything else that did not finish is scheduled again.
+    fn recover_session(&mut self, recovered: RecoveredSession) -> Result<()> {
+        log::info!("Recovering session {}", recovered.id);
+        if self.placeholder_client.is_none() {
+            self.placeholder_client = Some(ClientRef::new(self.listen_address));
+        }
+        let owner = self.placeholder_client.clone().unwrap();
+        let session = SessionRef::new(recovered.id, &owner, recovered.spec);
+        session.get_mut().user = recovered.user;
+        self.graph.sessions.insert(recovered.id, session.clone());
+        if session.get().spec.survives_client() {
+            // Without its token, the session could be taken over by `attach_session`
+            let token = match self.data_store.as_ref().unwrap().load_token(recovered.id)? {
+                Some(token) => token,
+                None => {
+                    log::warn!(
+                        "Token of session {} is not stored, generating a new one",
+                        recovered.id
+                    );
+                    new_session_token()?
+                }
+            };
+            session.get_mut().token = Some(token);
+        }
+        // The grace period starts again with the restarted server
+        session.get_mut().detached_since = Some(Instant::now());
+
+        let mut finished = Vec::new();
+        let mut unkept = Vec::new();
+        for event in recovered.events {
+            match event {
+                events::Event::ClientSubmit(e) => {
+                    let produced: HashSet<DataObjectId> = e
+                        .tasks
+                        .iter()
+                        .flat_map(|t| t.outputs.iter().cloned())
+                        .collect();
+                    for spec in e.dataobjs {
+                        let data = if produced.contains(&spec.id) {
+                            None
+                        } else {
+                            Some(self.data_store.as_ref().unwrap().load(spec.id)?)
+                        };
+                        let keep = e.kept.contains(&spec.id);
+                        self.add_object(&session, spec, keep, data)?;
+                    }
+                    for spec in e.tasks {
+                        let inputs = spec
+                            .inputs
+                            .iter()
+                            .map(|i| self.object_by_id(i.id))
+                            .collect::<Result<Vec<_>>>()?;
+                        let outputs = spec
+                            .outputs
+                            .iter()
+                            .map(|id| self.object_by_id(*id))
+                            .collect::<Result<Vec<_>>>()?;
+                        self.add_task(&session, spec, inputs, outputs)?;
+                    }
+                }
+                events::Event::TaskFinished(e) => finished.push(e),
+                events::Event::ClientUnkeep(e) => unkept.extend(e.dataobjs),
+                _ => {}
+            }
+        }
+
+        for id in unkept {
+            self.object_by_id(id)?.get_mut().set_client_keep(false);
+        }
+
+        // Finished tasks keep their state, their outputs are gone with the old governors
+        for e in finished {
+            let tref = self.task_by_id(e.task)?;
+            if tref.get().state == TaskState::Finished {
+                continue;
+            }
+            {
+                let mut t = tref.get_mut();
+                t.state = TaskState::Finished;
+                t.info = e.info;
+                t.waiting_for.clear();
+                t.session.get_mut().task_finished();
+                for oref in &t.outputs {
+                    oref.get_mut().set_state(DataObjectState::Removed);
+                }
+            }
+            for iref in tref.get().inputs.iter() {
+                iref.get_mut().need_by.remove(&tref);
+            }
+        }
+
+        let objects: Vec<DataObjectRef> = session.get().objects.iter().cloned().collect();
+        for oref in objects {
+            if oref.get().state == DataObjectState::Removed && oref.get().is_needed() {
+                self.recompute_object(&oref)?;
+            }
+        }
+
+        let tasks: Vec<TaskRef> = session.get().tasks.iter().cloned().collect();
+        for tref in tasks {
+            let mut t = tref.get_mut();
+            if t.state == TaskState::Finished {
+                continue;
+            }
+            t.waiting_for = t
+                .inputs
+                .iter()
+                .filter(|i| i.get().state == DataObjectState::Unfinished)
+                .cloned()
+                .collect();
+            t.state = if t.waiting_for.is_empty() {
+                TaskState::Ready
+            } else {
+                TaskState::NotAssigned
+            };
+        }
+        // All recovered tasks are passed to the scheduler as new tasks
+        self.updates.tasks.clear();
+        session.check_consistency_opt().unwrap(); // non-recoverable
         Ok(())
     }
 
@@ -225,8 +1104,14 @@ impl State {
             session.get().client.get_id(),
             cause
         );
+        self.fail_session_with(session, SessionError::new(cause, debug, task_id))
+    }
+
+    /// Fail the session with the given error, see `fail_session`.
+    pub fn fail_session_with(&mut self, session: &SessionRef, error: SessionError) -> Result<()> {
+        let cause = error.message().to_string();
         assert!(session.get_mut().error.is_none());
-        session.get_mut().error = Some(SessionError::new(cause.clone(), debug, task_id));
+        session.get_mut().error = Some(error);
         // Remove all tasks + objects (with their finish hooks)
         self.clear_session(session)?;
         self.logger.add_closed_session_event(
@@ -236,6 +1121,29 @@ impl State {
         Ok(())
     }
 
+    /// Check the limit of kept bytes of the session of a newly finished object.
+    fn check_kept_bytes(&self, oref: &DataObjectRef) -> Option<SessionError> {
+        let o = oref.get();
+        if !o.client_keep {
+            return None;
+        }
+        let s = o.session.get();
+        let limit = s.spec.limits.as_ref().and_then(|l| l.kept_bytes)?;
+        let kept = s.kept_bytes();
+        if kept > limit {
+            Some(SessionError::limit_exceeded(format!(
+                "Session {} keeps {} bytes of objects after object {} finished, \
+                 the limit is {} bytes",
+                s.id,
+                kept,
+                o.id(),
+                limit
+            )))
+        } else {
+            None
+        }
+    }
+
     /// Add a new object, register it in the graph and the session.
     pub fn add_object(
         &mut self,
@@ -250,6 +1158,13 @@ impl State {
         let oref = DataObjectRef::new(session, spec, client_keep, data);
         // add to graph
         self.graph.objects.insert(oref.get().id(), oref.clone());
+        if oref.get().spec.replicas.map_or(false, |r| r > 1) {
+            self.replicated_objects.insert(oref.clone());
+            if oref.get().state == DataObjectState::Finished {
+                // Uploaded by the client
+                self.underreplicated_objects.insert(oref.clone());
+            }
+        }
         // add to updated objects
         self.updates.new_objects.insert(oref.clone());
         oref.check_consistency_opt().unwrap(); // non-recoverable
@@ -269,6 +1184,8 @@ impl State {
         oref.unlink();
         // remove from graph
         self.graph.objects.remove(&oref.get().id()).unwrap();
+        self.replicated_objects.remove(oref);
+        self.underreplicated_objects.remove(oref);
         Ok(())
     }
 
@@ -407,6 +1324,9 @@ impl State {
                     o.id()
                 );
             }
+            if o.spec.replicas == Some(0) {
+                bail!("Object {} submitted with zero replicas", o.id());
+            }
         }
         // Verify every submitted object
         for oref in objects.iter() {
@@ -416,6 +1336,68 @@ impl State {
         for tref in tasks.iter() {
             tref.check_consistency()?;
         }
+        // Verify the limits of the sessions
+        for tref in tasks.iter() {
+            let t = tref.get();
+            let s = t.session.get();
+            if let Some(limit) = s.spec.limits.as_ref().and_then(|l| l.cpus) {
+                if t.spec.resources.cpus() > limit {
+                    bail!(ErrorKind::SessionErr(SessionError::limit_exceeded(
+                        format!(
+                            "Task {} requires {} cpus but session {} is limited to {} cpus",
+                            t.id(),
+                            t.spec.resources.cpus(),
+                            s.id,
+                            limit
+                        )
+                    )));
+                }
+            }
+        }
+        // A task requiring more of a named resource than any governor provides would
+        // wait forever; governors that are not connected yet are given the benefit of the doubt
+        if !self.graph.governors.is_empty() {
+            for tref in tasks.iter() {
+                let t = tref.get();
+                for (name, &amount) in t.spec.resources.named.iter() {
+                    if amount > 0
+                        && !self
+                            .graph
+                            .governors
+                            .values()
+                            .any(|w| w.get().resources.named(name) >= amount)
+                    {
+                        bail!(ErrorKind::SessionErr(SessionError::unknown_resource(
+                            format!(
+                                "Task {} requires {} of resource {:?} but no governor provides it",
+                                t.id(),
+                                amount,
+                                name
+                            ),
+                            name.clone()
+                        )));
+                    }
+                }
+            }
+        }
+        let mut sessions = RcSet::new();
+        for oref in objects.iter() {
+            sessions.insert(oref.get().session.clone());
+        }
+        for session in sessions.iter() {
+            let s = session.get();
+            if let Some(limit) = s.spec.limits.as_ref().and_then(|l| l.kept_bytes) {
+                let kept = s.kept_bytes();
+                if kept > limit {
+                    bail!(ErrorKind::SessionErr(SessionError::limit_exceeded(
+                        format!(
+                            "Session {} keeps {} bytes of objects, the limit is {} bytes",
+                            s.id, kept, limit
+                        )
+                    )));
+                }
+            }
+        }
 
         self.check_consistency_opt().unwrap(); // non-recoverable
         Ok(())
@@ -450,11 +1432,12 @@ impl State {
             co.set_assigned(true);
         }
 
+        // Sending may fail when the governor is disconnecting, its removal cleans up the state
         self.handle.spawn(
             req.send()
                 .promise
                 .map(|_| ())
-                .map_err(|e| panic!("[assign_object] Send failed {:?}", e)),
+                .map_err(|e| log::error!("[assign_object] Send failed {:?}", e)),
         );
 
         object.get_mut().assigned.insert(wref.clone());
@@ -480,36 +1463,14 @@ impl State {
         object.check_consistency_opt().unwrap(); // non-recoverable
         wref.check_consistency_opt().unwrap(); // non-recoverable
 
-        // Create request
-        let mut req = wref.get()
-            .control
-            .as_ref()
-            .unwrap()
-            .unassign_objects_request();
-        {
-            let mut objects = req.get().init_objects(1);
-            let co = &mut objects.reborrow().get(0);
-            object.get().id().to_capnp(co);
-        }
-
-        {
-            let o2 = object.clone();
-            let w2 = wref.clone();
-            self.handle
-                .spawn(req.send().promise.map(|_| ()).map_err(move |e| {
-                    panic!(
-                        "Sending unassign_object {:?} to {:?} failed {:?}",
-                        o2, w2, e
-                    )
-                }));
-        }
+        self.send_unassign_object(object, wref);
 
         object.get_mut().assigned.remove(wref);
         wref.get_mut().assigned_objects.remove(object);
         object.get_mut().located.remove(wref); // may not be present
         wref.get_mut().located_objects.remove(object); // may not be present
         if object.get().assigned.is_empty() && object.get().state == DataObjectState::Finished {
-            object.get_mut().state = DataObjectState::Removed;
+            object.get_mut().set_state(DataObjectState::Removed);
             assert!(object.get().scheduled.is_empty());
             assert!(!object.get().client_keep);
         }
@@ -518,6 +1479,34 @@ impl State {
         wref.check_consistency_opt().unwrap(); // non-recoverable
     }
 
+    /// Send the unassign call for the object to the governor.
+    /// Does not change the graph.
+    fn send_unassign_object(&self, object: &DataObjectRef, wref: &GovernorRef) {
+        let mut req = wref
+            .get()
+            .control
+            .as_ref()
+            .unwrap()
+            .unassign_objects_request();
+        {
+            let mut objects = req.get().init_objects(1);
+            let co = &mut objects.reborrow().get(0);
+            object.get().id().to_capnp(co);
+        }
+
+        let o2 = object.clone();
+        let w2 = wref.clone();
+        self.handle
+            .spawn(req.send().promise.map(|_| ()).map_err(move |e| {
+                log::error!(
+                    "Sending unassign_object {:?} to {:?} failed {:?}",
+                    o2,
+                    w2,
+                    e
+                )
+            }));
+    }
+
     /// Assign and send the task to the governor it is scheduled for.
     /// Panics when the task is not scheduled or not ready.
     /// Assigns output objects to the governor, input objects are not assigned.
@@ -588,7 +1577,7 @@ impl State {
                 req.send()
                     .promise
                     .map(|_| ())
-                    .map_err(|e| panic!("[assign_task] Send failed {:?}", e)),
+                    .map_err(|e| log::error!("[assign_task] Send failed {:?}", e)),
             );
 
             {
@@ -630,7 +1619,7 @@ impl State {
             req.send()
                 .promise
                 .map(|_| ())
-                .map_err(|e| panic!("[unassign_task] Send failed {:?}", e)),
+                .map_err(|e| log::error!("[unassign_task] Send failed {:?}", e)),
         );
 
         task.get_mut().assigned = None;
@@ -654,7 +1643,7 @@ impl State {
     /// Removes a keep flag from an object.
     pub fn unkeep_object(&mut self, object: &DataObjectRef) {
         object.check_consistency_opt().unwrap(); // non-recoverable
-        object.get_mut().client_keep = false;
+        object.get_mut().set_client_keep(false);
         let needed = object.get().is_needed();
         if !needed {
             object.unschedule();
@@ -679,7 +1668,7 @@ impl State {
             self.updates.tasks.insert(tref.clone());
             if let Some(ref wref) = tref.get().scheduled {
                 let mut w = wref.get_mut();
-                w.active_resources += tref.get().spec.resources.cpus();
+                w.active_resources.add(&tref.get().spec.resources);
             }
         }
 
@@ -759,7 +1748,7 @@ impl State {
                         for wa in assigned {
                             self.unassign_object(oref, &wa);
                         }
-                        oref.get_mut().state = DataObjectState::Removed;
+                        oref.get_mut().set_state(DataObjectState::Removed);
                     }
                 } else if oref.get().located.len() > oref.get().scheduled.len() {
                     for wa in oref.get().located.clone() {
@@ -773,6 +1762,87 @@ impl State {
         oref.check_consistency_opt().unwrap(); // unrecoverable
     }
 
+    /// Check the retry policy of a task that has just failed.
+    fn should_retry_task(&self, tref: &TaskRef, info: &TaskInfo) -> bool {
+        let t = tref.get();
+        match t.spec.retry {
+            Some(ref policy) => {
+                !t.session.get().is_failed()
+                    && policy.should_retry(t.failed_attempts + 1, &info.error)
+            }
+            None => false,
+        }
+    }
+
+    /// Return a task that failed on the governor to the scheduler for another attempt.
+    /// The task becomes `Ready`, but it is passed to the scheduler only after the retry delay.
+    fn retry_task(&mut self, tref: &TaskRef, governor: &GovernorRef, info: TaskInfo) {
+        let (attempt, delay) = {
+            let mut t = tref.get_mut();
+            t.failed_attempts += 1;
+            let delay = t
+                .spec
+                .retry
+                .as_ref()
+                .unwrap()
+                .retry_delay(t.failed_attempts);
+            log::warn!(
+                "Task {} failed on {} (attempt {}), retrying in {:?}: {}",
+                t.id(),
+                governor.get_id(),
+                t.failed_attempts,
+                delay,
+                info.error
+            );
+            (t.failed_attempts, delay)
+        };
+        self.logger
+            .add_task_retry_event(tref.get().id(), attempt, info);
+
+        // The governor has already dropped the task, only the outputs have to be unassigned
+        tref.unschedule();
+        {
+            let mut t = tref.get_mut();
+            t.assigned = None;
+            t.state = TaskState::Ready;
+            t.info = Default::default();
+            governor.get_mut().assigned_tasks.remove(tref);
+        }
+        for oref in tref.get().outputs.clone() {
+            oref.unschedule();
+            if oref.get().assigned.contains(governor) {
+                self.unassign_object(&oref, governor);
+            }
+        }
+        self.underload_governors.insert(governor.clone());
+
+        if delay == Duration::from_secs(0) {
+            self.updates.tasks.insert(tref.clone());
+            return;
+        }
+        self.updates.tasks.remove(tref);
+        let state_ref = self.self_ref.clone().unwrap();
+        let tref = tref.clone();
+        self.handle.spawn(
+            ::tokio_timer::Delay::new(Instant::now() + delay)
+                .map(move |()| {
+                    let mut state = state_ref.get_mut();
+                    let ready = {
+                        let t = tref.get();
+                        // The session may have been closed or the task already passed
+                        // to the scheduler in the meantime
+                        t.state == TaskState::Ready
+                            && t.scheduled.is_none()
+                            && state.graph.tasks.contains_key(&t.id())
+                    };
+                    if ready && !state.scheduler.is_ready(&tref) {
+                        state.updates.tasks.insert(tref);
+                    }
+                })
+                .map_err(|e| log::error!("Retry delay failed: {:?}", e)),
+        );
+    }
+
     /// Process state updates from one Governor.
     pub fn updates_from_governor(
         &mut self,
@@ -794,6 +1864,16 @@ impl State {
             if ignore_check_again && self.is_task_ignored(&tref.get().id()) {
                 continue;
             }
+            if tref.get().assigned.as_ref() != Some(governor) {
+                // The task was unassigned (e.g. moved by rebalancing)
+                // before the governor processed the unassignment
+                log::debug!(
+                    "Ignoring update of task {} from {} where it is not assigned",
+                    tref.get().id(),
+                    governor.get_id()
+                );
+                continue;
+            }
             // inform the scheduler
             self.updates.tasks.insert(tref.clone());
             // set the state and possibly propagate
@@ -808,8 +1888,9 @@ impl State {
                         t.assigned = None;
                         let mut w = governor.get_mut();
                         w.scheduled_tasks.remove(&tref);
+                        w.avoiding_tasks.remove(&tref);
                         w.assigned_tasks.remove(&tref);
-                        w.active_resources -= t.spec.resources.cpus();
+                        w.active_resources.remove(&t.spec.resources);
                         self.logger.add_task_finished_event(t.id(), info);
                     }
                     tref.get_mut().trigger_finish_hooks();
@@ -844,6 +1925,10 @@ impl State {
                         governor,
                         info
                     );
+                    if self.should_retry_task(&tref, &info) {
+                        self.retry_task(&tref, governor, info);
+                        continue;
+                    }
                     let has_error = info.error.len() > 0;
                     let error_message = if has_error {
                         info.error.clone()
@@ -874,6 +1959,10 @@ impl State {
         }
 
         for (oref, state, info) in obj_updates {
+            if oref.get().session.get().is_failed() {
+                // The session failed during this update (e.g. exceeded its limits)
+                continue;
+            }
             // Inform the scheduler
             self.updates
                 .objects
@@ -897,13 +1986,29 @@ impl State {
                                 // capture `o`
                                 let mut o = oref.get_mut();
                                 // first completion
-                                o.state = state;
+                                if let Some(size) = info.size {
+                                    self.logger.add_dataobject_finished_event(
+                                        o.id(),
+                                        governor.get_id(),
+                                        size,
+                                    );
+                                }
                                 o.info = info;
+                                o.set_state(state);
                                 o.trigger_finish_hooks();
                             }
+                            if let Some(error) = self.check_kept_bytes(&oref) {
+                                let session = oref.get().session.clone();
+                                self.fail_session_with(&session, error).unwrap();
+                                continue;
+                            }
+                            self.replicas_changed(&oref);
                             for cref in oref.get().consumers.clone() {
+                                if !cref.get_mut().waiting_for.remove(&oref) {
+                                    // Finished consumer of a recomputed object
+                                    continue;
+                                }
                                 assert_eq!(cref.get().state, TaskState::NotAssigned);
-                                cref.get_mut().waiting_for.remove(&oref);
                                 self.update_task_assignment(&cref);
                             }
                             if oref.get().is_needed() {
@@ -949,16 +2054,11 @@ impl State {
         for wref in &::std::mem::replace(&mut self.underload_governors, Default::default()) {
             //let mut w = wref.get_mut();
             // TODO: Customize the overbook limit
-            while wref.get().assigned_tasks.len() < 128
-                && !wref.get().scheduled_ready_tasks.is_empty()
-            {
-                // TODO: Prioritize older members of w.scheduled_ready_tasks (order-preserving set)
-                let tref = wref.get()
-                    .scheduled_ready_tasks
-                    .iter()
-                    .next()
-                    .unwrap()
-                    .clone();
+            while wref.get().assigned_tasks.len() < 128 {
+                let tref = match next_ready_task(wref) {
+                    Some(tref) => tref,
+                    None => break,
+                };
                 assert!(tref.get().scheduled == Some(wref.clone()));
                 self.assign_task(&tref);
             }
@@ -977,7 +2077,12 @@ impl State {
         let changed = self.scheduler.schedule(&mut self.graph, &self.updates);
         self.updates.clear();
 
-        // Update assignments of (possibly) changed objects.
+        self.apply_schedule(&changed);
+        self.underload_governors = self.graph.governors.values().map(|w| w.clone()).collect();
+    }
+
+    /// Update the assignments of (possibly) changed tasks and objects.
+    fn apply_schedule(&mut self, changed: &UpdatedOut) {
         for (wref, os) in changed.objects.iter() {
             for oref in os.iter() {
                 self.update_object_assignments(oref, Some(wref));
@@ -987,7 +2092,92 @@ impl State {
         for tref in changed.tasks.iter() {
             self.update_task_assignment(tref);
         }
-        self.underload_governors = self.graph.governors.values().map(|w| w.clone()).collect();
+    }
+
+    /// Move tasks waiting on their governors (`Assigned` but not yet `Running`)
+    /// to idle governors, i.e. governors without any scheduled task.
+    ///
+    /// To avoid ping-pong, a task is moved only when it was already waiting at the previous
+    /// pass, it is not moved again within `REBALANCE_COOLDOWN_SECONDS`, and every idle
+    /// governor receives at most one task per pass.
+    pub fn rebalance(&mut self) {
+        let now = Instant::now();
+        if !self.rebalance
+            || now < self.last_rebalance + Duration::from_millis(REBALANCE_INTERVAL_MS)
+        {
+            return;
+        }
+        self.last_rebalance = now;
+
+        let mut waiting = RcSet::new();
+        for wref in self.graph.governors.values() {
+            for tref in &wref.get().assigned_tasks {
+                if tref.get().state == TaskState::Assigned {
+                    waiting.insert(tref.clone());
+                }
+            }
+        }
+        let previous = ::std::mem::replace(&mut self.rebalance_waiting, waiting);
+
+        let mut idle: Vec<GovernorRef> = self
+            .graph
+            .governors
+            .values()
+            .filter(|w| w.get().scheduled_tasks.is_empty())
+            .cloned()
+            .collect();
+        if idle.is_empty() {
+            return;
+        }
+        idle.sort_by_key(|w| w.get_id());
+
+        let cooldown = Duration::from_secs(REBALANCE_COOLDOWN_SECONDS);
+        let mut candidates: Vec<TaskRef> = self
+            .rebalance_waiting
+            .iter()
+            .filter(|t| {
+                previous.contains(t) && t.get().last_move.map_or(true, |m| m + cooldown <= now)
+            })
+            .cloned()
+            .collect();
+        candidates.sort_by_key(|t| {
+            let t = t.get();
+            (-(t.spec.priority as i64), t.id())
+        });
+
+        let mut changed = UpdatedOut::default();
+        for tref in candidates {
+            if idle.is_empty() {
+                break;
+            }
+            let target = idle.iter().position(|w| can_run_on(&tref.get(), &w.get()));
+            let wref = match target {
+                Some(i) => idle.remove(i),
+                None => continue,
+            };
+            let source = tref.get().assigned.clone().unwrap();
+            log::info!(
+                "Rebalancing: moving task {} from {} to {}",
+                tref.get().id(),
+                source.get_id(),
+                wref.get_id()
+            );
+            tref.unschedule();
+            for oref in tref.get().outputs.clone() {
+                oref.unschedule();
+            }
+            self.unassign_task(&tref);
+            tref.get_mut().last_move = Some(now);
+            self.rebalance_waiting.remove(&tref);
+            schedule_task(&tref, &wref, &mut changed);
+            self.underload_governors.insert(source);
+            self.underload_governors.insert(wref);
+        }
+        self.apply_schedule(&changed);
+    }
+
+    pub fn tls(&self) -> Option<&TlsConfig> {
+        self.tls.as_ref()
     }
 
     pub fn handle(&self) -> &Handle {
@@ -1028,8 +2218,19 @@ impl StateRef {
         http_listen_address: SocketAddr,
         log_dir: PathBuf,
         test_mode: bool,
+        recover: bool,
+        scheduler: Box<Scheduler>,
+        rebalance: bool,
+        heartbeat_timeout: Option<Duration>,
+        auth: Option<AuthTokens>,
+        tls: Option<TlsConfig>,
     ) -> Self {
-        let (logger, last_session) = SQLiteLogger::new(&log_dir).unwrap();
+        let (logger, last_session, recovered) = SQLiteLogger::new(&log_dir, recover).unwrap();
+        let data_store = if recover {
+            Some(DataStore::new(log_dir.join("data")).unwrap())
+        } else {
+            None
+        };
         log::debug!("Session counter set to {}", last_session);
         let graph = Graph::new(last_session);
 
@@ -1039,15 +2240,27 @@ impl StateRef {
             listen_address: listen_address,
             http_listen_address: http_listen_address,
             handle: handle,
-            scheduler: Default::default(),
+            scheduler,
+            rebalance,
+            rebalance_waiting: Default::default(),
+            last_rebalance: Instant::now(),
+            replicated_objects: Default::default(),
+            underreplicated_objects: Default::default(),
+            heartbeat_timeout,
+            last_heartbeat: Instant::now(),
+            auth,
+            tls,
             underload_governors: Default::default(),
             updates: Default::default(),
             stop_server: false,
             self_ref: None,
             logger: Box::new(logger),
             ignored_sessions: Default::default(),
+            data_store,
+            placeholder_client: None,
         });
         s.get_mut().self_ref = Some(s.clone());
+        s.get_mut().recover_sessions(recovered);
         s
     }
 
@@ -1123,6 +2336,12 @@ impl StateRef {
             self.get().check_consistency_opt().unwrap(); // unrecoverable
         }
 
+        self.get_mut().rebalance();
+        self.get_mut().check_drained_governors();
+        self.get_mut().check_replicas();
+        self.get_mut().check_heartbeats();
+        self.get_mut().close_expired_sessions();
+
         // Assign ready tasks to governors (up to overbook limit)
         self.get_mut().distribute_tasks();
         !self.get().stop_server
@@ -1133,13 +2352,546 @@ impl StateRef {
 
         log::info!("New connection from {}", address);
         stream.set_nodelay(true).unwrap();
-        let bootstrap = ::rain_core::server_capnp::server_bootstrap::ToClient::new(
-            ServerBootstrapImpl::new(self, address),
-        ).from_server::<::capnp_rpc::Server>();
-
-        let rpc_system = new_rpc_system(stream, Some(bootstrap.client));
-        self.get()
-            .handle
-            .spawn(rpc_system.map_err(|e| panic!("RPC error: {:?}", e)));
+        let state = self.clone();
+        let future = accept_stream(self.get().tls(), stream)
+            .map(move |stream| {
+                let bootstrap = ::rain_core::server_capnp::server_bootstrap::ToClient::new(
+                    ServerBootstrapImpl::new(&state, address),
+                )
+                .from_server::<::capnp_rpc::Server>();
+
+                let rpc_system = new_rpc_system(stream, Some(bootstrap.client));
+                state
+                    .get()
+                    .handle
+                    .spawn(rpc_system.map_err(|e| log::error!("RPC error: {:?}", e)));
+            })
+            .map_err(move |e| log::warn!("TLS handshake with {} failed: {}", address, e));
+        self.get().handle.spawn(future);
+    }
+}
+
+/// The scheduled ready task that is assigned to the governor first: the highest priority,
+/// then the lowest id (the same order in which the governor starts its ready tasks).
+fn next_ready_task(governor: &GovernorRef) -> Option<TaskRef> {
+    governor
+        .get()
+        .scheduled_ready_tasks
+        .iter()
+        .min_by_key(|t| {
+            let t = t.get();
+            (::std::cmp::Reverse(t.spec.priority), t.id())
+        })
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use server::scheduler::{FairShareMode, ReactiveScheduler};
+    use tempdir::TempDir;
+    use tokio_core::reactor::Core;
+
+    /// State with the recovery enabled, logging into `log_dir`.
+    /// The core has to outlive the state as the state spawns futures on it.
+    fn create_state(log_dir: &TempDir) -> (Core, StateRef) {
+        let core = Core::new().unwrap();
+        let state = StateRef::new(
+            core.handle(),
+            "127.0.0.1:7210".parse().unwrap(),
+            "127.0.0.1:8080".parse().unwrap(),
+            log_dir.path().to_path_buf(),
+            false,
+            true,
+            Box::new(ReactiveScheduler::new(FairShareMode::Off)),
+            false,
+            None,
+            None,
+            None,
+        );
+        (core, state)
+    }
+
+    fn session_spec(grace_period: Option<u64>) -> SessionSpec {
+        SessionSpec {
+            name: "test".to_string(),
+            grace_period,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn detach_and_reattach() {
+        let log_dir = TempDir::new("rain-test").unwrap();
+        let (_core, state) = create_state(&log_dir);
+        let mut s = state.get_mut();
+        let client = s
+            .add_client("127.0.0.1:1001".parse().unwrap(), None)
+            .unwrap();
+        let session = s.add_session(&client, session_spec(Some(3600))).unwrap();
+        let token = session.get().token.clone().unwrap();
+
+        s.remove_client(&client).unwrap();
+        assert!(session.get().detached_since.is_some());
+        assert!(s.graph.sessions.contains_key(&session.get_id()));
+
+        let other = s
+            .add_client("127.0.0.1:1002".parse().unwrap(), None)
+            .unwrap();
+        assert!(s.attach_session(&other, &session).is_err());
+        assert!(s.reattach_session(&other, &session, "wrong").is_err());
+        s.reattach_session(&other, &session, &token).unwrap();
+        assert!(session.get().client == other);
+        assert!(session.get().detached_since.is_none());
+
+        // Not expired while attached
+        s.close_expired_sessions();
+        assert!(s.graph.sessions.contains_key(&session.get_id()));
+    }
+
+    #[test]
+    fn detached_session_expires() {
+        let log_dir = TempDir::new("rain-test").unwrap();
+        let (_core, state) = create_state(&log_dir);
+        let mut s = state.get_mut();
+        let client = s
+            .add_client("127.0.0.1:1001".parse().unwrap(), None)
+            .unwrap();
+        let expiring = s.add_session(&client, session_spec(Some(0))).unwrap();
+        let waiting = s.add_session(&client, session_spec(Some(3600))).unwrap();
+
+        s.remove_client(&client).unwrap();
+        s.close_expired_sessions();
+        assert!(!s.graph.sessions.contains_key(&expiring.get_id()));
+        assert!(s.graph.sessions.contains_key(&waiting.get_id()));
+    }
+
+    #[test]
+    fn recovered_session_keeps_token() {
+        let log_dir = TempDir::new("rain-test").unwrap();
+        let (_core, state) = create_state(&log_dir);
+        let mut s = state.get_mut();
+        let client = s
+            .add_client("127.0.0.1:1001".parse().unwrap(), None)
+            .unwrap();
+        let session = s.add_session(&client, session_spec(Some(3600))).unwrap();
+        let id = session.get_id();
+        let token = session.get().token.clone().unwrap();
+        s.remove_client(&client).unwrap();
+        s.remove_session(&session).unwrap();
+        s.data_store
+            .as_ref()
+            .unwrap()
+            .save_token(id, &token)
+            .unwrap();
+
+        s.recover_session(RecoveredSession {
+            id,
+            spec: session_spec(Some(3600)),
+            user: None,
+            events: Vec::new(),
+        })
+        .unwrap();
+        let recovered = s.session_by_id(id).unwrap();
+        assert_eq!(recovered.get().token, Some(token.clone()));
+        assert!(recovered.get().detached_since.is_some());
+
+        let other = s
+            .add_client("127.0.0.1:1002".parse().unwrap(), None)
+            .unwrap();
+        assert!(s.attach_session(&other, &recovered).is_err());
+        s.reattach_session(&other, &recovered, &token).unwrap();
+    }
+
+    #[test]
+    fn attach_recovered_session() {
+        let log_dir = TempDir::new("rain-test").unwrap();
+        let (_core, state) = create_state(&log_dir);
+        let mut s = state.get_mut();
+        s.recover_session(RecoveredSession {
+            id: 10,
+            spec: session_spec(None),
+            user: Some("alice".to_string()),
+            events: Vec::new(),
+        })
+        .unwrap();
+        let session = s.session_by_id(10).unwrap();
+
+        let bob = s
+            .add_client("127.0.0.1:1001".parse().unwrap(), Some("bob".to_string()))
+            .unwrap();
+        assert!(s.attach_session(&bob, &session).is_err());
+        let alice = s
+            .add_client("127.0.0.1:1002".parse().unwrap(), Some("alice".to_string()))
+            .unwrap();
+        s.attach_session(&alice, &session).unwrap();
+        assert!(session.get().client == alice);
+    }
+
+    #[test]
+    fn unattached_recovered_session_expires() {
+        let log_dir = TempDir::new("rain-test").unwrap();
+        let (_core, state) = create_state(&log_dir);
+        let mut s = state.get_mut();
+        s.recover_session(RecoveredSession {
+            id: 10,
+            spec: session_spec(None),
+            user: None,
+            events: Vec::new(),
+        })
+        .unwrap();
+
+        s.close_expired_sessions();
+        assert!(s.session_by_id(10).is_ok());
+        s.session_by_id(10).unwrap().get_mut().detached_since =
+            Some(Instant::now() - Duration::from_secs(RECOVERED_SESSION_EXPIRY_SECONDS));
+        s.close_expired_sessions();
+        assert!(s.session_by_id(10).is_err());
+    }
+
+    #[test]
+    fn kept_bytes_limit() {
+        let log_dir = TempDir::new("rain-test").unwrap();
+        let (_core, state) = create_state(&log_dir);
+        let mut s = state.get_mut();
+        let client = s
+            .add_client("127.0.0.1:1001".parse().unwrap(), None)
+            .unwrap();
+        let mut spec = session_spec(None);
+        spec.limits = Some(SessionLimits {
+            kept_bytes: Some(10),
+            ..Default::default()
+        });
+        let session = s.add_session(&client, spec).unwrap();
+        let (first, second, unkept) = {
+            let mut add_object = |id: Id, keep: bool| {
+                let spec = ObjectSpec {
+                    id: DataObjectId::new(session.get_id(), id),
+                    ..Default::default()
+                };
+                s.add_object(&session, spec, keep, Some(vec![0; 6]))
+                    .unwrap()
+            };
+            (
+                add_object(1, true),
+                add_object(2, true),
+                add_object(3, false),
+            )
+        };
+        assert_eq!(session.get().kept_bytes(), 12);
+        assert!(s.check_kept_bytes(&second).is_some());
+        assert!(s.check_kept_bytes(&unkept).is_none());
+
+        s.unkeep_object(&first);
+        assert_eq!(session.get().kept_bytes(), 6);
+        assert!(s.check_kept_bytes(&second).is_none());
+
+        s.remove_object(&second).unwrap();
+        assert_eq!(session.get().kept_bytes(), 0);
+    }
+
+    #[test]
+    fn lost_replica_is_replaced() {
+        let log_dir = TempDir::new("rain-test").unwrap();
+        let (_core, state) = create_state(&log_dir);
+        let mut s = state.get_mut();
+        let resources = Resources {
+            cpus: 1,
+            ..Default::default()
+        };
+        let governors: Vec<GovernorRef> = (1..4)
+            .map(|i| {
+                let address = format!("10.0.0.{}:7211", i).parse().unwrap();
+                s.add_governor(address, None, resources.clone(), Default::default())
+                    .unwrap()
+            })
+            .collect();
+        let client = s
+            .add_client("127.0.0.1:1001".parse().unwrap(), None)
+            .unwrap();
+        let session = s.add_session(&client, session_spec(None)).unwrap();
+        let spec = ObjectSpec {
+            id: DataObjectId::new(session.get_id(), 1),
+            replicas: Some(2),
+            ..Default::default()
+        };
+        let oref = s
+            .add_object(&session, spec, true, Some(vec![1, 2, 3]))
+            .unwrap();
+
+        let placed = s.place_replicas();
+        assert_eq!(placed.len(), 2);
+        // The copies are done
+        for &(_, ref wref) in &placed {
+            let mut o = oref.get_mut();
+            o.assigned.insert(wref.clone());
+            o.located.insert(wref.clone());
+            let mut w = wref.get_mut();
+            w.assigned_objects.insert(oref.clone());
+            w.located_objects.insert(oref.clone());
+        }
+        assert!(s.place_replicas().is_empty());
+
+        let lost = placed[0].1.clone();
+        s.remove_governor(&lost, events::GovernorRemovedReason::Lost, String::new())
+            .unwrap();
+        let placed = s.place_replicas();
+        assert_eq!(placed.len(), 1);
+        let target = &placed[0].1;
+        assert!(target != &lost);
+        assert!(governors.contains(target));
+        assert_eq!(oref.get().scheduled.len(), 2);
+        assert!(s.place_replicas().is_empty());
+    }
+
+    #[test]
+    fn recover_sessions() {
+        let log_dir = TempDir::new("rain-test").unwrap();
+        let (_core, state) = create_state(&log_dir);
+        let mut s = state.get_mut();
+        let object_spec = |id: DataObjectId| ObjectSpec {
+            id,
+            ..Default::default()
+        };
+        let submit = |session_id: SessionId| {
+            let input = DataObjectId::new(session_id, 1);
+            let output = DataObjectId::new(session_id, 3);
+            events::Event::ClientSubmit(events::ClientSubmitEvent {
+                tasks: vec![TaskSpec {
+                    id: TaskId::new(session_id, 2),
+                    inputs: vec![TaskSpecInput {
+                        id: input,
+                        label: Default::default(),
+                    }],
+                    outputs: vec![output],
+                    ..Default::default()
+                }],
+                dataobjs: vec![object_spec(input), object_spec(output)],
+                kept: vec![output],
+            })
+        };
+        // The uploaded data of the second session are missing
+        s.data_store
+            .as_ref()
+            .unwrap()
+            .save(DataObjectId::new(10, 1), b"data")
+            .unwrap();
+        s.recover_sessions(vec![
+            RecoveredSession {
+                id: 10,
+                spec: session_spec(None),
+                user: None,
+                events: vec![submit(10)],
+            },
+            RecoveredSession {
+                id: 11,
+                spec: session_spec(None),
+                user: None,
+                events: vec![submit(11)],
+            },
+        ]);
+
+        let session = s.session_by_id(10).unwrap();
+        let input = s.object_by_id(DataObjectId::new(10, 1)).unwrap();
+        assert_eq!(input.get().state, DataObjectState::Finished);
+        assert_eq!(input.get().data, Some(b"data".to_vec()));
+        let task = s.task_by_id(TaskId::new(10, 2)).unwrap();
+        assert_eq!(task.get().state, TaskState::Ready);
+        let output = s.object_by_id(DataObjectId::new(10, 3)).unwrap();
+        assert_eq!(output.get().state, DataObjectState::Unfinished);
+        assert!(output.get().client_keep);
+        assert!(session.get().detached_since.is_some());
+
+        assert!(s.session_by_id(11).is_err());
+        assert!(s.object_by_id(DataObjectId::new(11, 1)).is_err());
+    }
+
+    #[test]
+    fn data_store() {
+        let dir = TempDir::new("rain-test").unwrap();
+        let store = DataStore::new(dir.path().join("data")).unwrap();
+        let id = DataObjectId::new(1, 2);
+        assert!(store.load(id).is_err());
+        store.save(id, b"data").unwrap();
+        assert_eq!(store.load(id).unwrap(), b"data".to_vec());
+        assert_eq!(store.load_token(1).unwrap(), None);
+        store.save_token(1, "secret").unwrap();
+        assert_eq!(store.load_token(1).unwrap(), Some("secret".to_string()));
+
+        store.remove_session(1);
+        assert!(store.load(id).is_err());
+        assert_eq!(store.load_token(1).unwrap(), None);
+        // Missing data are ignored
+        store.remove_session(2);
+    }
+
+    #[test]
+    fn submit_with_unknown_resource() {
+        let log_dir = TempDir::new("rain-test").unwrap();
+        let (_core, state) = create_state(&log_dir);
+        let mut s = state.get_mut();
+        let client = s
+            .add_client("127.0.0.1:1001".parse().unwrap(), None)
+            .unwrap();
+        let session = s.add_session(&client, session_spec(None)).unwrap();
+        let task_spec = |id: Id, license: u32| {
+            let mut resources = Resources::default();
+            resources.named.insert("license".to_string(), license);
+            TaskSpec {
+                id: TaskId::new(session.get_id(), id),
+                resources,
+                ..Default::default()
+            }
+        };
+
+        // No governor is connected yet
+        let task = s
+            .add_task(&session, task_spec(1, 2), Vec::new(), Vec::new())
+            .unwrap();
+        assert!(s.verify_submit(&[task], &[]).is_ok());
+
+        let mut resources = Resources::default();
+        resources.named.insert("license".to_string(), 1);
+        s.add_governor(
+            "10.0.0.1:7211".parse().unwrap(),
+            None,
+            resources,
+            Default::default(),
+        )
+        .unwrap();
+        let task = s
+            .add_task(&session, task_spec(2, 1), Vec::new(), Vec::new())
+            .unwrap();
+        assert!(s.verify_submit(&[task], &[]).is_ok());
+        let task = s
+            .add_task(&session, task_spec(3, 2), Vec::new(), Vec::new())
+            .unwrap();
+        match s.verify_submit(&[task], &[]) {
+            Err(Error(ErrorKind::SessionErr(ref e), _)) => {
+                assert_eq!(e.resource(), Some("license"))
+            }
+            _ => panic!("Submit with an unknown resource was not rejected"),
+        }
+    }
+
+    #[test]
+    fn ready_tasks_assigned_by_priority() {
+        let log_dir = TempDir::new("rain-test").unwrap();
+        let (_core, state) = create_state(&log_dir);
+        let mut s = state.get_mut();
+        let client = s
+            .add_client("127.0.0.1:1001".parse().unwrap(), None)
+            .unwrap();
+        let session = s.add_session(&client, session_spec(None)).unwrap();
+        let governor = s
+            .add_governor(
+                "10.0.0.1:7211".parse().unwrap(),
+                None,
+                Resources::default(),
+                Default::default(),
+            )
+            .unwrap();
+        // (id, priority)
+        for &(id, priority) in &[(10, 0), (9, 5), (8, 5), (7, -1)] {
+            let spec = TaskSpec {
+                id: TaskId::new(session.get_id(), id),
+                priority,
+                ..Default::default()
+            };
+            let tref = s.add_task(&session, spec, Vec::new(), Vec::new()).unwrap();
+            governor.get_mut().scheduled_ready_tasks.insert(tref);
+        }
+
+        let mut order = Vec::new();
+        while let Some(tref) = next_ready_task(&governor) {
+            governor.get_mut().scheduled_ready_tasks.remove(&tref);
+            order.push(tref.get().id().get_id());
+        }
+        assert_eq!(order, vec![8, 9, 10, 7]);
+    }
+
+    #[test]
+    fn retry_after_delay() {
+        let log_dir = TempDir::new("rain-test").unwrap();
+        let (mut core, state) = create_state(&log_dir);
+        let task = {
+            let mut s = state.get_mut();
+            let client = s
+                .add_client("127.0.0.1:1001".parse().unwrap(), None)
+                .unwrap();
+            let session = s.add_session(&client, session_spec(None)).unwrap();
+            s.add_governor(
+                "10.0.0.1:7211".parse().unwrap(),
+                None,
+                Resources {
+                    cpus: 1,
+                    ..Default::default()
+                },
+                Default::default(),
+            )
+            .unwrap();
+            let spec = TaskSpec {
+                id: TaskId::new(session.get_id(), 1),
+                retry: Some(RetryPolicy {
+                    max_attempts: 3,
+                    delay: 0.1,
+                    errors: vec!["Program exit with exit code 75\n".to_string()],
+                }),
+                ..Default::default()
+            };
+            let task = s.add_task(&session, spec, Vec::new(), Vec::new()).unwrap();
+            s.update_task_assignment(&task);
+            task
+        };
+        // Emulates `assign_task` without a governor connection
+        let run_on_governor = |tref: &TaskRef| {
+            let mut t = tref.get_mut();
+            let wref = t.scheduled.clone().unwrap();
+            let mut w = wref.get_mut();
+            w.scheduled_ready_tasks.remove(tref);
+            w.assigned_tasks.insert(tref.clone());
+            t.assigned = Some(wref.clone());
+            t.state = TaskState::Assigned;
+        };
+        let failure = |error: &str| TaskInfo {
+            error: error.to_string(),
+            ..Default::default()
+        };
+
+        {
+            let mut s = state.get_mut();
+            s.run_scheduler();
+            run_on_governor(&task);
+            let governor = task.get().assigned.clone().unwrap();
+            s.updates_from_governor(
+                &governor,
+                Vec::new(),
+                vec![(
+                    task.clone(),
+                    TaskState::Failed,
+                    failure("Program exit with exit code 75\nbusy"),
+                )],
+            );
+            assert_eq!(task.get().state, TaskState::Ready);
+            assert_eq!(task.get().failed_attempts, 1);
+            assert!(task.get().assigned.is_none() && task.get().scheduled.is_none());
+            assert!(!task.get().session.get().is_failed());
+            // Not passed to the scheduler before the delay elapses
+            assert!(!s.updates.tasks.contains(&task));
+        }
+
+        core.run(::tokio_timer::Delay::new(
+            Instant::now() + Duration::from_millis(300),
+        ))
+        .unwrap();
+
+        let mut s = state.get_mut();
+        assert!(s.updates.tasks.contains(&task));
+        s.run_scheduler();
+        assert!(task.get().scheduled.is_some());
+        // Only the errors starting with the given prefix are retried
+        assert!(!s.should_retry_task(&task, &failure("Program exit with exit code 7\n")));
+        assert!(!s.should_retry_task(&task, &failure("Error\nProgram exit with exit code 75\n")));
+        assert!(s.should_retry_task(&task, &failure("Program exit with exit code 75\n")));
     }
 }
@@ -100,6 +100,8 @@ fn data_spec(id: i32, label: &str, location: Option<DataLocation>) -> LocalObjec
             data_type: DataType::Blob,
             content_type: "".into(),
             user: HashMap::default(),
+            compression: None,
+            replicas: None,
         },
         info: if location.is_some() {
             Some(ObjectInfo::default())
@@ -64,6 +64,7 @@ class TestEnv(Env):
 
         self.server = None
         self.governors = []
+        self.stopped_governors = set()
         self.do_final_check = True
 
     @property
@@ -81,7 +82,8 @@ class TestEnv(Env):
               http_port=None,
               governor_defs=None,
               delete_list_timeout=None,
-              executor=None):
+              executor=None,
+              server_args=()):
         """
         Start infrastructure: server & n governors
         """
@@ -143,7 +145,7 @@ class TestEnv(Env):
                 "--ready-file", server_ready_file,
                 "--logdir", os.path.join(WORK_DIR, "server"),
                 "--listen", str(addr),
-                "--http-listen", str(http_port))
+                "--http-listen", str(http_port)) + tuple(server_args)
         self.server = self.start_process("server", args, env=env)
         assert self.server is not None
 
@@ -188,6 +190,8 @@ class TestEnv(Env):
     def check_running_processes(self):
         """Checks that everything is still running"""
         for i, governor in enumerate(self.governors):
+            if i in self.stopped_governors:
+                continue
             if governor.poll() is not None:
                 self.governors = []
                 raise Exception(
@@ -203,6 +207,32 @@ class TestEnv(Env):
                 "Note: If you are running more tests, "
                 "log may be overridden or deleted)".format(WORK_DIR))
 
+    def kill_governor(self, index):
+        """Kills governor `index` (as if its node crashed)"""
+        governor = self.governors[index]
+        os.killpg(os.getpgid(governor.pid), signal.SIGKILL)
+        governor.wait()
+        self.stopped_governors.add(index)
+
+    def wait_for_governor_exit(self, timeout=5):
+        """Waits until a governor terminates by itself, returns its index"""
+        end = time.time() + timeout
+        while time.time() < end:
+            for i, governor in enumerate(self.governors):
+                if i not in self.stopped_governors and \
+                        governor.poll() is not None:
+                    self.stopped_governors.add(i)
+                    return i
+            time.sleep(0.05)
+        raise Exception("No governor terminated after {} s".format(timeout))
+
+    def pause_governor(self, index):
+        """Stops governor `index` without closing its connections"""
+        os.killpg(os.getpgid(self.governors[index].pid), signal.SIGSTOP)
+
+    def resume_governor(self, index):
+        os.killpg(os.getpgid(self.governors[index].pid), signal.SIGCONT)
+
     @property
     def client(self):
         if self._client is not None:
@@ -221,7 +251,8 @@ class TestEnv(Env):
             time.sleep(0.1)
             info = self._client.get_server_info()
             governors = info["governors"]
-            assert len(governors) == len(self.governor_defs)
+            assert (len(governors) ==
+                    len(self.governor_defs) - len(self.stopped_governors))
             for w in governors:
                 assert not w["tasks"]
                 invalid = [o for o in w["objects"] if o not in w["objects_to_delete"]]
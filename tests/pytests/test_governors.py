@@ -0,0 +1,85 @@
+from rain.client import tasks, blob
+import time
+
+
+def test_governor_lost(test_env):
+    """Tasks of a killed governor are finished by the remaining governor"""
+    test_env.start(2)
+    with test_env.client.new_session() as s:
+        t1 = tasks.Sleep(blob("first"), 1)
+        t2 = tasks.Sleep(blob("second"), 1)
+        t1.output.keep()
+        t2.output.keep()
+        s.submit()
+        time.sleep(0.3)
+        test_env.kill_governor(0)
+        s.wait_all()
+        assert t1.output.fetch().get_bytes() == b"first"
+        assert t2.output.fetch().get_bytes() == b"second"
+    governors = test_env.client.get_server_info()["governors"]
+    assert len(governors) == 1
+
+
+def test_lost_object_recomputed(test_env):
+    """Kept objects of a killed governor are computed again"""
+    test_env.start(2)
+    with test_env.client.new_session() as s:
+        t1 = tasks.Sleep(blob("first"), 0.3)
+        t2 = tasks.Sleep(blob("second"), 0.3)
+        t1.output.keep()
+        t2.output.keep()
+        s.submit()
+        s.wait_all()
+        test_env.kill_governor(0)
+        time.sleep(0.3)
+        assert t1.output.fetch().get_bytes() == b"first"
+        assert t2.output.fetch().get_bytes() == b"second"
+        t3 = tasks.Concat((t1, t2))
+        t3.output.keep()
+        s.submit()
+        assert t3.output.fetch().get_bytes() == b"firstsecond"
+
+
+def test_rebalance_to_idle_governor(test_env):
+    """A task stuck on an unresponsive governor is moved to an idle one"""
+    test_env.start(2, server_args=("--rebalance",))
+    test_env.pause_governor(0)
+    try:
+        with test_env.client.new_session() as s:
+            t1 = tasks.Sleep(blob("first"), 0.1)
+            t2 = tasks.Sleep(blob("second"), 0.1)
+            t1.output.keep()
+            t2.output.keep()
+            s.submit()
+            test_env.assert_max_duration(5, lambda: s.wait_all())
+            assert t1.output.fetch().get_bytes() == b"first"
+            assert t2.output.fetch().get_bytes() == b"second"
+    finally:
+        test_env.resume_governor(0)
+
+
+def test_drain_governor(test_env):
+    """Needed objects are copied away before a drained governor leaves"""
+    test_env.start(2)
+    client = test_env.client
+    with client.new_session() as s:
+        t1 = tasks.Sleep(blob("first"), 0.3)
+        t2 = tasks.Sleep(blob("second"), 0.3)
+        t1.output.keep()
+        t2.output.keep()
+        s.submit()
+        s.wait_all()
+
+        governors = client.get_server_info()["governors"]
+        drained = [w for w in governors if w["objects"]][0]
+        client.drain_governor(drained["governor_id"])
+        test_env.wait_for_governor_exit()
+
+        governors = client.get_server_info()["governors"]
+        assert len(governors) == 1
+        assert governors[0]["governor_id"] != drained["governor_id"]
+        objects = governors[0]["objects"]
+        assert t1.output.id in objects
+        assert t2.output.id in objects
+        assert t1.output.fetch().get_bytes() == b"first"
+        assert t2.output.fetch().get_bytes() == b"second"
@@ -1,5 +1,6 @@
-from rain.client import tasks, blob
+from rain.client import tasks, blob, UnknownResourceException
 
+import pytest
 import time
 
 
@@ -43,6 +44,16 @@ def test_cpu_resources4(test_env):
         test_env.assert_duration(0.9, 1.1, lambda: s.wait_all())
 
 
+def test_unknown_resource(test_env):
+    """Submit of a task requesting a resource that no governor provides fails"""
+    test_env.start(1)
+    with test_env.client.new_session() as s:
+        tasks.Execute("true", resources={"license_matlab": 1})
+        with pytest.raises(UnknownResourceException) as e:
+            s.submit()
+        assert e.value.resource == "license_matlab"
+
+
 def test_number_of_tasks_and_objects(test_env):
     """Sleep followed by wait"""
     test_env.start(1, delete_list_timeout=0)